@@ -0,0 +1,35 @@
+//! Converts maps between the JSON and plain-text formats.
+//!
+//! Usage: `convert_map <from> <to>`. The format of each file is chosen by its
+//! extension: `.txt` for text maps, anything else for JSON.
+
+use std::path::Path;
+use std::process::ExitCode;
+
+use dungeon_crawler::maps::{self, text};
+
+fn main() -> ExitCode {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    let [from, to] = paths.as_slice() else {
+        eprintln!("usage: convert_map <from> <to>");
+        return ExitCode::FAILURE;
+    };
+
+    let map = match maps::from_file(from) {
+        Ok(map) => map,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let contents = if Path::new(to).extension().is_some_and(|e| e == "txt") {
+        text::to_text(&map)
+    } else {
+        serde_json::to_string_pretty(&map).expect("maps serialize")
+    };
+    if let Err(error) = std::fs::write(to, contents) {
+        eprintln!("{}: could not write map: {}", to, error);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,41 @@
+//! Checks map files for problems before anyone plays them.
+//!
+//! Usage: `validate_map <map.json>...`. Prints one line per problem and exits
+//! with a non-zero status if any file fails to load or has problems.
+
+use std::process::ExitCode;
+
+use dungeon_crawler::maps;
+
+fn main() -> ExitCode {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: validate_map <map.json>...");
+        return ExitCode::FAILURE;
+    }
+
+    let mut ok = true;
+    for path in paths {
+        match maps::from_file(&path) {
+            Ok(map) => {
+                let problems = map.validate();
+                if !problems.is_empty() {
+                    ok = false;
+                }
+                for problem in problems {
+                    println!("{}: {}", path, problem);
+                }
+            }
+            Err(error) => {
+                ok = false;
+                println!("{}", error);
+            }
+        }
+    }
+
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
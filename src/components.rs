@@ -1,9 +1,13 @@
 use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 pub use positioning::Position;
 
+use crate::map::{Equipment, Item, ItemId};
+
 use std::{
-    collections::{BTreeSet, VecDeque},
+    collections::{BTreeMap, BTreeSet, VecDeque},
 };
 
 #[derive(Component, Debug)]
@@ -24,7 +28,7 @@ pub struct WakeZone(pub BTreeSet<Position>);
 #[derive(Component, Debug)]
 pub struct Awake(pub bool);
 
-#[derive(Component, Debug, Clone, Copy)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum AIBehavior {
     Aggressive,   // Always chase player
     Defensive,    // Retreat when health < 30%
@@ -50,9 +54,16 @@ pub struct SpriteIndex(pub usize);
 #[derive(Component, Debug)]
 pub struct Tile;
 
-#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+/// Marks entities that belong to the room currently on screen.
+#[derive(Component, Debug)]
+pub struct RoomMember;
+
+#[derive(
+    Component, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
 pub enum EnemyType {
     Skeleton,  // Fast, weak (sprite: 2700)
+    #[default]
     Orc,       // Balanced (sprite: 2701)
     Ghost,     // Slow, strong (sprite: 2702)
 }
@@ -72,6 +83,15 @@ impl EnemyType {
         )
     }
 
+    /// The energy the type gains each tick, against the player's 100.
+    pub fn speed(&self) -> u64 {
+        match self {
+            EnemyType::Skeleton => 150,
+            EnemyType::Orc => 100,
+            EnemyType::Ghost => 75,
+        }
+    }
+
     pub fn sprite_index(&self) -> usize {
         match self {
             EnemyType::Skeleton => 2700,
@@ -80,8 +100,8 @@ impl EnemyType {
         }
     }
 
-    pub fn random() -> Self {
-        let r: f32 = rand::random();
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let r: f32 = rng.gen();
         if r < 0.4 {
             EnemyType::Skeleton
         } else if r < 0.7 {
@@ -95,6 +115,14 @@ impl EnemyType {
 #[derive(Component, Debug)]
 pub struct Enemy;
 
+/// The group an enemy belongs to, for triggers to refer to.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct Group(pub String);
+
+/// Someone the player escorts, following them around the room.
+#[derive(Component, Debug)]
+pub struct Ally;
+
 #[derive(Component, Debug)]
 pub struct Player;
 
@@ -104,6 +132,31 @@ pub struct CameraMarker;
 #[derive(Component, Debug)]
 pub struct Passable(pub bool);
 
+/// Ticks left before the player or an enemy can move again, after wading
+/// into slow terrain.
+#[derive(Component, Debug, Default)]
+pub struct Slowed(pub u64);
+
+/// The energy the player or an enemy gains each tick, or each turn of
+/// turn-based play, before equipment and speed effects.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Speed(pub u64);
+
+/// Energy saved up towards the next action. Moving, attacking and waiting
+/// each spend some, so faster actors act more often.
+#[derive(Component, Debug, Default)]
+pub struct Energy(pub u64);
+
+/// Changes to speed that wear off, such as a ghost's chill.
+#[derive(Component, Debug, Default)]
+pub struct SpeedEffects(pub Vec<SpeedEffect>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeedEffect {
+    pub change: i64,
+    pub ticks_left: u64,
+}
+
 #[test]
 fn test_adjacency() {
     let position = Position { x: 5, y: 5, z: 0 };
@@ -120,9 +173,116 @@ pub struct MovementPath {
 #[derive(Component)]
 pub struct Menu;
 
+/// Sprites drawn by the map editor for the room and floor being edited.
+#[derive(Component)]
+pub struct EditorView;
+
+/// The map editor's status text and brush preview.
+#[derive(Component)]
+pub struct EditorUi;
+
+#[derive(Component)]
+pub struct EditorCursor;
+
+#[derive(Component)]
+pub struct EditorPreview;
+
 #[derive(Component)]
 pub struct HealthGain;
 
+/// An item lying in the room, waiting to be picked up.
+#[derive(Component, Debug)]
+pub struct ItemPickup(pub ItemId);
+
+/// The items the player carries, in the order they were picked up.
+#[derive(Component, Debug)]
+pub struct Inventory {
+    pub capacity: usize,
+    pub items: Vec<ItemId>,
+}
+
+impl Inventory {
+    pub fn new(capacity: usize) -> Self {
+        Inventory {
+            capacity,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= self.capacity
+    }
+
+    pub fn contains(&self, item: ItemId) -> bool {
+        self.items.contains(&item)
+    }
+}
+
+/// The items the player has equipped, out of those in their inventory.
+#[derive(Component, Debug, Default)]
+pub struct Equipped {
+    pub weapon: Option<ItemId>,
+    pub armor: Option<ItemId>,
+}
+
+impl Equipped {
+    /// Puts `item` in the slot its equipment goes in, or takes it off if it's
+    /// already there.
+    pub fn toggle(&mut self, item: ItemId, equipment: Equipment) {
+        let slot = match equipment {
+            Equipment::Weapon { .. } => &mut self.weapon,
+            Equipment::Armor { .. } => &mut self.armor,
+        };
+        *slot = if *slot == Some(item) { None } else { Some(item) };
+    }
+
+    pub fn contains(&self, item: ItemId) -> bool {
+        self.weapon == Some(item) || self.armor == Some(item)
+    }
+
+    /// The damage the equipped weapon adds and how many steps it reaches.
+    pub fn weapon(&self, items: &BTreeMap<ItemId, Item>) -> (i64, i64) {
+        match self.weapon.and_then(|item| items.get(&item)?.equipment) {
+            Some(Equipment::Weapon { damage, reach, .. }) => (damage as i64, reach.max(1) as i64),
+            _ => (0, 1),
+        }
+    }
+
+    /// The damage the equipped armor takes off each enemy's attack.
+    pub fn defense(&self, items: &BTreeMap<ItemId, Item>) -> i64 {
+        match self.armor.and_then(|item| items.get(&item)?.equipment) {
+            Some(Equipment::Armor { defense, .. }) => defense as i64,
+            _ => 0,
+        }
+    }
+
+    /// How much the equipped items change the player's speed.
+    pub fn speed(&self, items: &BTreeMap<ItemId, Item>) -> i64 {
+        [self.weapon, self.armor]
+            .into_iter()
+            .flatten()
+            .filter_map(|item| items.get(&item)?.equipment)
+            .map(|equipment| equipment.speed())
+            .sum()
+    }
+}
+
+/// An item an enemy leaves behind when it dies.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Drops(pub ItemId);
+
+/// The panel listing what the player carries.
+#[derive(Component)]
+pub struct InventoryUi;
+
+/// The text where trigger messages are shown.
+#[derive(Component)]
+pub struct MessageUi;
+
+/// The menu line naming the pace the next game is played at.
+#[derive(Component)]
+pub struct PaceUi;
+
 #[derive(Component)]
 pub struct TargetIndicator;
 
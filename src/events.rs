@@ -1 +1,36 @@
+use bevy::prelude::*;
 
+use crate::components::Position;
+use crate::map::{Connection, Enemy, ItemId, Tile};
+
+/// Sent when the player steps onto a connection to another room.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct EnterConnection(pub Connection);
+
+/// Sent when the player walks into an enemy, to attack it.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct Attack(pub Entity);
+
+/// Sent when the player walks into a locked door they hold the key for.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct OpenDoor(pub Position);
+
+/// Sent by a trigger to spawn an enemy in the current room.
+#[derive(Message, Clone)]
+pub struct SpawnEnemy(pub Position, pub Enemy);
+
+/// Sent by a trigger to replace a tile of the current room.
+#[derive(Message, Clone)]
+pub struct ChangeTile(pub Position, pub Tile);
+
+/// Sent by a trigger to wake every enemy of a group.
+#[derive(Message, Debug, Clone)]
+pub struct WakeGroup(pub String);
+
+/// Sent by a trigger to show the player some text.
+#[derive(Message, Debug, Clone)]
+pub struct ShowMessage(pub String);
+
+/// Sent by a trigger to hand the player an item.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct GiveItem(pub ItemId);
@@ -0,0 +1,9 @@
+pub mod components;
+pub mod events;
+pub mod map;
+pub mod maps;
+pub mod resources;
+pub mod state;
+pub mod systems;
+pub mod utils;
+pub mod validation;
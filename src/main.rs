@@ -1,36 +1,89 @@
-mod components;
-mod events;
-mod map;
-mod maps;
-mod resources;
-mod state;
-mod systems;
-mod utils;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
 use bevy::prelude::*;
-use state::GameState;
-use systems::*;
+use dungeon_crawler::{
+    events, maps,
+    resources::{GameRng, MapFiles, Pace, PendingTurns, PlayerAttack},
+    state::GameState,
+    systems::*,
+};
+
+/// Usage: `dungeon-crawler [--seed <number>] [--turn-based] [<map.json>...]`.
+/// Playing with the seed shown on the end screen replays the same random
+/// choices, and `--turn-based` starts with turn-based play picked in the menu.
+fn main() -> ExitCode {
+    let mut seed = None;
+    let mut pace = Pace::RealTime;
+    let mut map_files: Vec<PathBuf> = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            let Some(number) = args.next().and_then(|number| number.parse().ok()) else {
+                eprintln!(
+                    "usage: dungeon-crawler [--seed <number>] [--turn-based] [<map.json>...]"
+                );
+                return ExitCode::FAILURE;
+            };
+            seed = Some(number);
+        } else if arg == "--turn-based" {
+            pace = Pace::TurnBased;
+        } else {
+            map_files.push(PathBuf::from(arg));
+        }
+    }
+    map_files.extend(maps::map_files(maps::MAPS_DIRECTORY));
 
-fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
         .init_state::<GameState>()
+        .insert_resource(MapFiles(map_files))
+        .insert_resource(GameRng::new(seed.unwrap_or_else(rand::random)))
+        .insert_resource(pace)
+        .init_resource::<PendingTurns>()
+        .init_resource::<PlayerAttack>()
+        .add_message::<events::EnterConnection>()
+        .add_message::<events::OpenDoor>()
+        .add_message::<events::Attack>()
+        .add_message::<events::SpawnEnemy>()
+        .add_message::<events::ChangeTile>()
+        .add_message::<events::WakeGroup>()
+        .add_message::<events::ShowMessage>()
+        .add_message::<events::GiveItem>()
         .insert_resource(Time::<Fixed>::from_hz(30.0))
         .add_systems(Startup, setup)
         .add_systems(Update, menu.run_if(in_state(GameState::Menu)))
         .add_systems(OnEnter(GameState::Playing), setup_play)
+        .add_systems(OnExit(GameState::Playing), roll_seed)
         .add_systems(
             FixedUpdate,
             (
                 follow,
                 display_health,
                 animate_sprites,
-                walk_enemies,
-                combat,
+                (
+                    gain_energy,
+                    walk_enemies.after(gain_energy),
+                    combat.after(walk_enemies),
+                    escort.after(combat),
+                    terrain,
+                    count_ticks,
+                )
+                    .run_if(world_advances),
+                end_turn
+                    .after(gain_energy)
+                    .after(walk_enemies)
+                    .after(combat)
+                    .after(escort)
+                    .after(terrain)
+                    .after(count_ticks),
                 cleanup_dead_enemies,
                 cleanup_collected_health,
-                victory,
+                victory.after(count_ticks),
                 defeat,
+                fire_triggers,
+                (spawn_enemies, change_tiles, wake_groups, show_messages, give_items)
+                    .after(fire_triggers),
             ).run_if(in_state(GameState::Playing)),
         )
         .add_systems(
@@ -38,15 +91,35 @@ fn main() {
             (
                 move_camera,
                 move_player,
+                change_room.after(move_player),
+                open_doors.after(move_player),
+                pick_up_items,
+                equip_items,
+                display_inventory,
                 set_follow,
                 health,
                 set_visibility,
-                track_mouse_movement,
-                update_target_indicator,
+                pick_hovered_tile.after(move_camera),
+                update_target_indicator.after(pick_hovered_tile),
+                player_attacks
+                    .after(move_player)
+                    .after(update_target_indicator),
                 update_particles,
             ).run_if(in_state(GameState::Playing)),
         )
+        .add_systems(OnEnter(GameState::Editor), enter_editor)
+        .add_systems(
+            Update,
+            (
+                move_camera,
+                editor,
+                draw_editor.after(editor),
+                update_editor_ui.after(editor),
+            ).run_if(in_state(GameState::Editor)),
+        )
+        .add_systems(OnExit(GameState::Editor), exit_editor)
         .add_systems(OnEnter(GameState::Victory), on_victory)
         .add_systems(OnEnter(GameState::Defeat), on_defeat)
         .run();
+    ExitCode::SUCCESS
 }
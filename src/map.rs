@@ -1,17 +1,43 @@
 use bevy::prelude::Resource;
 use itertools::Itertools;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 use std::collections::{BTreeMap, BTreeSet};
 
-use crate::components::Position;
+use crate::components::{AIBehavior, EnemyType, Position};
+
+mod grid;
+
+/// Version of the map schema written by this build. Older documents are
+/// upgraded by `maps::migrate` when they are loaded.
+pub const MAP_VERSION: u64 = 6;
+
+/// Dimensions, in sprites, of the `tiles.png` atlas that sprite indices refer to.
+pub const ATLAS_COLUMNS: u32 = 64;
+pub const ATLAS_ROWS: u32 = 48;
+
+/// Staircase sprites in `tiles.png`.
+pub const UP_STAIRS_SPRITE: u64 = 64 * 15 + 42;
+pub const DOWN_STAIRS_SPRITE: u64 = 64 * 15 + 41;
 
 #[derive(Deserialize, Serialize, PartialEq, Eq, Clone)]
 pub struct Room {
     pub initial_position: Position,
+    #[serde(with = "grid")]
     pub tiles: PositionMap<Tile>,
     pub enemies: PositionMap<Enemy>,
     pub healths: PositionMap<Health>,
+    #[serde(default)]
+    pub connections: PositionMap<Connection>,
+    /// Items lying in the room, described in `Map::items`
+    #[serde(default)]
+    pub items: PositionMap<ItemId>,
+    #[serde(default)]
+    pub allies: PositionMap<Ally>,
+    /// Scripted events, each run once while the player is in the room
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<Trigger>,
 }
 
 impl Room {
@@ -21,6 +47,10 @@ impl Room {
             tiles: PositionMap(BTreeMap::new()),
             enemies: PositionMap(BTreeMap::new()),
             healths: PositionMap(BTreeMap::new()),
+            connections: PositionMap(BTreeMap::new()),
+            items: PositionMap(BTreeMap::new()),
+            allies: PositionMap(BTreeMap::new()),
+            triggers: Vec::new(),
         }
     }
 
@@ -38,29 +68,228 @@ impl Room {
         self.healths.0.insert(position, health);
         self
     }
+
+    pub fn add_item(&mut self, position: Position, item: ItemId) -> &mut Self {
+        self.items.0.insert(position, item);
+        self
+    }
+
+    pub fn add_ally(&mut self, position: Position, ally: Ally) -> &mut Self {
+        self.allies.0.insert(position, ally);
+        self
+    }
+
+    pub fn add_connection(&mut self, position: Position, connection: Connection) -> &mut Self {
+        self.connections.0.insert(position, connection);
+        self
+    }
+
+    /// Whether stairs lead from `position` to the same spot on the floor `dz`
+    /// (1 or -1) away.
+    pub fn has_stairs(&self, position: &Position, dz: i64) -> bool {
+        let other = Position::new(position.x, position.y, position.z + dz);
+        match (self.tiles.get(position), self.tiles.get(&other)) {
+            (Some(from), Some(to)) if dz > 0 => TileKind::connects(from.kind, to.kind),
+            (Some(from), Some(to)) => TileKind::connects(to.kind, from.kind),
+            _ => false,
+        }
+    }
+}
+
+/// A door or staircase: stepping onto its position moves the player to
+/// `position` in the room with index `room`.
+#[derive(Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Clone, Copy, Debug)]
+pub struct Connection {
+    pub room: usize,
+    pub position: Position,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Clone)]
 pub struct Tile {
     pub sprite_index: u64,
-    pub passable: bool,
+    pub kind: TileKind,
+    /// Makes the tile a locked door, impassable until the player carries the
+    /// item with this id. It then opens into a tile of its `kind`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock: Option<ItemId>,
 }
 
 impl Tile {
+    /// A plain floor or wall tile.
     pub fn new(sprite_index: u64, passable: bool) -> Self {
         Tile {
             sprite_index,
-            passable,
+            kind: if passable {
+                TileKind::Floor
+            } else {
+                TileKind::Wall
+            },
+            lock: None,
+        }
+    }
+
+    pub fn passable(&self) -> bool {
+        self.kind.passable() && self.lock.is_none()
+    }
+}
+
+/// What a tile does. Only stairs let the player change floors, onto the
+/// stairs directly above or below. The terrain kinds hurt, slow down or slide
+/// the player and enemies alike.
+#[derive(Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Clone, Copy, Debug)]
+pub enum TileKind {
+    Floor,
+    Wall,
+    UpStairs,
+    DownStairs,
+    /// Stairs leading both up and down
+    Stairs,
+    Lava,
+    Spikes,
+    Water,
+    Ice,
+}
+
+impl TileKind {
+    pub const ALL: [TileKind; 9] = [
+        TileKind::Floor,
+        TileKind::Wall,
+        TileKind::UpStairs,
+        TileKind::DownStairs,
+        TileKind::Stairs,
+        TileKind::Lava,
+        TileKind::Spikes,
+        TileKind::Water,
+        TileKind::Ice,
+    ];
+
+    pub fn passable(self) -> bool {
+        self != TileKind::Wall
+    }
+
+    /// Health lost each second spent standing on the tile, or each turn of
+    /// turn-based play.
+    pub fn damage(self) -> i64 {
+        match self {
+            TileKind::Lava => 20,
+            TileKind::Spikes => 5,
+            _ => 0,
         }
     }
+
+    /// How many moves' worth of time it takes to cross the tile.
+    pub fn movement_cost(self) -> u64 {
+        match self {
+            TileKind::Water => 3,
+            _ => 1,
+        }
+    }
+
+    /// Whether whatever steps onto the tile keeps sliding in the same
+    /// direction until something stops it.
+    pub fn slippery(self) -> bool {
+        self == TileKind::Ice
+    }
+
+    pub fn goes_up(self) -> bool {
+        matches!(self, TileKind::UpStairs | TileKind::Stairs)
+    }
+
+    pub fn goes_down(self) -> bool {
+        matches!(self, TileKind::DownStairs | TileKind::Stairs)
+    }
+
+    pub fn is_stairs(self) -> bool {
+        self.goes_up() || self.goes_down()
+    }
+
+    /// Whether stairs join a `lower` tile to the `upper` tile directly above
+    /// it. Either end leading towards the other is enough, and the stairs can
+    /// then be taken both ways.
+    pub fn connects(lower: TileKind, upper: TileKind) -> bool {
+        lower.is_stairs() && upper.is_stairs() && (lower.goes_up() || upper.goes_down())
+    }
 }
 
+/// An enemy as authored in a map. Whatever is left unset falls back to the
+/// enemy's type: its sprite, its AI behavior, and its stats scaled by floor.
 #[derive(Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Clone)]
 pub struct Enemy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enemy_type: Option<EnemyType>,
+    /// Rolls a random type each time the enemy spawns, in place of `enemy_type`.
+    #[serde(default)]
+    pub random_type: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ai_behavior: Option<AIBehavior>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sprite_index: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strength: Option<u64>,
+    pub wake_zone: BTreeSet<Position>,
+    /// An item, described in `Map::items`, left behind when the enemy dies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drops: Option<ItemId>,
+    /// A name shared with other enemies of the room, for triggers to refer to
+    /// them together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+/// Actions a room runs once, the first time `condition` holds while the
+/// player is in it.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct Trigger {
+    pub condition: TriggerCondition,
+    pub actions: Vec<TriggerAction>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug)]
+pub enum TriggerCondition {
+    /// The player stepping anywhere into the box between two corners,
+    /// inclusive.
+    Enter(Position, Position),
+    /// Every enemy of the group being dead, including those a trigger of the
+    /// room has yet to spawn.
+    Defeated(String),
+    /// The player spending this many ticks in the room, 30 to the second,
+    /// counted from when they last came in.
+    Delay(u64),
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub enum TriggerAction {
+    SpawnEnemy(Position, Enemy),
+    /// Replaces the tile at the position, such as a wall with a floor to open
+    /// a passage.
+    SetTile(Position, Tile),
+    /// Wakes every enemy of the group.
+    Wake(String),
+    /// Shows the text to the player for a few seconds.
+    Message(String),
+    /// Puts the item in the player's inventory, or at their feet if it is
+    /// full.
+    Give(ItemId),
+}
+
+impl TriggerAction {
+    /// The group of the enemy the action spawns, if any.
+    pub fn spawned_group(&self) -> Option<&str> {
+        match self {
+            TriggerAction::SpawnEnemy(_, enemy) => enemy.group.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Someone the player escorts. Allies follow the player around their room,
+/// and every enemy next to one hurts it.
+#[derive(Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Clone, Debug)]
+pub struct Ally {
     pub sprite_index: u64,
     pub health: u64,
-    pub strength: u64,
-    pub wake_zone: BTreeSet<Position>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Clone)]
@@ -69,6 +298,55 @@ pub struct Health {
     pub health: u64,
 }
 
+/// Something the player can pick up and carry. Any item opens the doors
+/// locked with its id.
+#[derive(Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Clone, Debug)]
+pub struct Item {
+    pub name: String,
+    pub sprite_index: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equipment: Option<Equipment>,
+}
+
+/// What an item does while the player has it equipped. The player has one
+/// slot for a weapon and one for armor. Either may also change the player's
+/// speed by `speed`, such as heavy armor slowing them down.
+#[derive(Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Clone, Copy, Debug)]
+pub enum Equipment {
+    /// Adds `damage` to the player's strength and lets them hit enemies up to
+    /// `reach` steps away.
+    Weapon {
+        damage: u64,
+        reach: u64,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        speed: i64,
+    },
+    /// Takes `defense` off the damage each adjacent enemy deals.
+    Armor {
+        defense: u64,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        speed: i64,
+    },
+}
+
+impl Equipment {
+    pub fn speed(&self) -> i64 {
+        match self {
+            Equipment::Weapon { speed, .. } | Equipment::Armor { speed, .. } => *speed,
+        }
+    }
+
+    pub fn speed_mut(&mut self) -> &mut i64 {
+        match self {
+            Equipment::Weapon { speed, .. } | Equipment::Armor { speed, .. } => speed,
+        }
+    }
+}
+
+fn is_zero(value: &i64) -> bool {
+    *value == 0
+}
+
 impl Enemy {
     pub fn new(
         sprite_index: u64,
@@ -77,10 +355,24 @@ impl Enemy {
         wake_zone: BTreeSet<Position>,
     ) -> Self {
         Enemy {
-            sprite_index,
-            health,
-            strength,
+            enemy_type: None,
+            random_type: false,
+            ai_behavior: None,
+            sprite_index: Some(sprite_index),
+            health: Some(health),
+            strength: Some(strength),
             wake_zone,
+            drops: None,
+            group: None,
+        }
+    }
+
+    /// The type to spawn this enemy as.
+    pub fn spawn_type(&self, rng: &mut impl Rng) -> EnemyType {
+        if self.random_type {
+            EnemyType::random(rng)
+        } else {
+            self.enemy_type.unwrap_or_default()
         }
     }
 
@@ -102,30 +394,202 @@ impl Enemy {
     }
 }
 
-#[derive(Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Clone)]
-pub struct ItemId(u64);
+#[derive(Deserialize, Serialize, PartialEq, PartialOrd, Eq, Ord, Clone, Copy, Debug)]
+pub struct ItemId(pub u64);
 
 #[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Resource)]
 pub struct Map {
-    pub room: Room,
+    pub version: u64,
+    pub rooms: Vec<Room>,
+    pub initial_room: usize,
     pub player_health: u64,
     pub player_strength: u64,
     pub player_sprite: u64,
     pub victory_condition: VictoryCondition,
+    /// Every item that appears in the map's rooms
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub items: BTreeMap<ItemId, Item>,
+    /// How many items the player can carry
+    #[serde(default = "default_inventory_capacity")]
+    pub inventory_capacity: u64,
+    /// What loses the map, besides the player dying
+    #[serde(default)]
+    pub defeat_condition: DefeatCondition,
+}
+
+pub const DEFAULT_INVENTORY_CAPACITY: u64 = 10;
+
+fn default_inventory_capacity() -> u64 {
+    DEFAULT_INVENTORY_CAPACITY
+}
+
+impl Map {
+    pub fn starting_room(&self) -> &Room {
+        &self.rooms[self.initial_room]
+    }
+
+    /// A map of just `room` with a weak player, won by exterminating the
+    /// enemies, for tests to adjust.
+    #[cfg(test)]
+    pub fn single_room(room: Room) -> Self {
+        Map {
+            version: MAP_VERSION,
+            rooms: vec![room],
+            initial_room: 0,
+            player_health: 10,
+            player_strength: 1,
+            player_sprite: 71,
+            victory_condition: VictoryCondition::Extermination,
+            items: BTreeMap::new(),
+            inventory_capacity: DEFAULT_INVENTORY_CAPACITY,
+            defeat_condition: Default::default(),
+        }
+    }
+
+    /// Joins two rooms with a connection in each direction.
+    pub fn connect(&mut self, from: (usize, Position), to: (usize, Position)) -> &mut Self {
+        self.rooms[from.0].add_connection(
+            from.1,
+            Connection {
+                room: to.0,
+                position: to.1,
+            },
+        );
+        self.rooms[to.0].add_connection(
+            to.1,
+            Connection {
+                room: from.0,
+                position: from.1,
+            },
+        );
+        self
+    }
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Eq, Clone)]
 pub enum VictoryCondition {
-    Arrival(Position),
+    /// Standing on the position in the room with this index.
+    Arrival(usize, Position),
+    /// Standing anywhere in the box between two corners, inclusive, in the
+    /// room with this index.
+    Region(usize, Position, Position),
     Extermination,
+    /// Killing every enemy on one floor, in every room.
+    FloorExtermination(i64),
+    /// Carrying the item.
+    Collect(ItemId),
+    /// Killing `count` enemies of one type.
+    Kill { enemy_type: EnemyType, count: u64 },
+    /// Staying alive for this many ticks, 30 to the second.
+    Survive(u64),
     Or(Vec<VictoryCondition>),
     And(Vec<VictoryCondition>),
+    Not(Box<VictoryCondition>),
     Unwinnable,
 }
 
+impl VictoryCondition {
+    /// Reaching any of `arrivals`, each a room index and a position in that
+    /// room, or `Unwinnable` if there are none.
+    pub fn arrival_at_any(arrivals: &[(usize, Position)]) -> Self {
+        match arrivals {
+            [] => VictoryCondition::Unwinnable,
+            [(room, position)] => VictoryCondition::Arrival(*room, *position),
+            _ => VictoryCondition::Or(
+                arrivals
+                    .iter()
+                    .map(|(room, position)| VictoryCondition::Arrival(*room, *position))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Every `Arrival` target in the condition, in order, leaving out those
+    /// the player must stay away from.
+    pub fn arrivals(&self) -> Vec<(usize, Position)> {
+        match self {
+            VictoryCondition::Arrival(room, position) => vec![(*room, *position)],
+            VictoryCondition::Or(cs) | VictoryCondition::And(cs) => {
+                cs.iter().flat_map(VictoryCondition::arrivals).collect()
+            }
+            VictoryCondition::Region(..)
+            | VictoryCondition::Extermination
+            | VictoryCondition::FloorExtermination(_)
+            | VictoryCondition::Collect(_)
+            | VictoryCondition::Kill { .. }
+            | VictoryCondition::Survive(_)
+            | VictoryCondition::Not(_)
+            | VictoryCondition::Unwinnable => Vec::new(),
+        }
+    }
+}
+
+/// What loses a map. The player dying always does.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug, Default)]
+pub enum DefeatCondition {
+    /// Running out of time after this many ticks, 30 to the second.
+    TimeLimit(u64),
+    /// Any ally dying.
+    AllyDeath,
+    /// Any enemy stepping onto the position in the room with this index.
+    EnemyArrival(usize, Position),
+    Or(Vec<DefeatCondition>),
+    And(Vec<DefeatCondition>),
+    /// Only the player dying.
+    #[default]
+    Never,
+}
+
+impl DefeatCondition {
+    /// Every `EnemyArrival` target in the condition, in order.
+    pub fn enemy_arrivals(&self) -> Vec<(usize, Position)> {
+        match self {
+            DefeatCondition::EnemyArrival(room, position) => vec![(*room, *position)],
+            DefeatCondition::Or(cs) | DefeatCondition::And(cs) => cs
+                .iter()
+                .flat_map(DefeatCondition::enemy_arrivals)
+                .collect(),
+            DefeatCondition::TimeLimit(_) | DefeatCondition::AllyDeath | DefeatCondition::Never => {
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Whether `position` lies in the box with corners `from` and `to`, inclusive.
+pub fn in_region(position: &Position, from: &Position, to: &Position) -> bool {
+    (from.x.min(to.x)..=from.x.max(to.x)).contains(&position.x)
+        && (from.y.min(to.y)..=from.y.max(to.y)).contains(&position.y)
+        && (from.z.min(to.z)..=from.z.max(to.z)).contains(&position.z)
+}
+
 #[derive(PartialEq, Eq, Clone)]
 pub struct PositionMap<A>(BTreeMap<Position, A>);
 
+impl<A> PositionMap<A> {
+    pub fn get(&self, position: &Position) -> Option<&A> {
+        self.0.get(position)
+    }
+
+    pub fn get_mut(&mut self, position: &Position) -> Option<&mut A> {
+        self.0.get_mut(position)
+    }
+
+    pub fn remove(&mut self, position: &Position) -> Option<A> {
+        self.0.remove(position)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<A> Default for PositionMap<A> {
+    fn default() -> Self {
+        PositionMap(BTreeMap::new())
+    }
+}
+
 impl<'a, A> IntoIterator for &'a PositionMap<A> {
     type Item = (&'a Position, &'a A);
 
@@ -0,0 +1,280 @@
+//! Compact encoding for a layer of tiles. Each floor is a rectangular grid
+//! whose cells index into a palette shared by all floors, run-length encoded
+//! row by row from the bottom-left corner. Loading also accepts the plain
+//! `[[position, tile], ...]` list that `PositionMap` serializes to.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{Error, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::PositionMap;
+use crate::components::Position;
+
+/// Cell value for positions with no tile.
+const EMPTY: i64 = -1;
+
+#[derive(Serialize, Deserialize)]
+struct Grid<A> {
+    palette: Vec<A>,
+    floors: Vec<Floor>,
+}
+
+/// `runs` alternates a run length with the palette index repeated for it.
+#[derive(Serialize, Deserialize)]
+struct Floor {
+    z: i64,
+    x: i64,
+    y: i64,
+    width: u64,
+    height: u64,
+    runs: Vec<i64>,
+}
+
+enum Encoding<A> {
+    Grid(Grid<A>),
+    List(PositionMap<A>),
+}
+
+/// Tells the encodings apart by shape, an object or a list, so that errors
+/// inside either keep their path.
+struct EncodingVisitor<A>(PhantomData<A>);
+
+impl<'de, A: Deserialize<'de>> Visitor<'de> for EncodingVisitor<A> {
+    type Value = Encoding<A>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tile grid or a list of positioned tiles")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, seq: S) -> Result<Self::Value, S::Error> {
+        PositionMap::deserialize(SeqAccessDeserializer::new(seq)).map(Encoding::List)
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, map: M) -> Result<Self::Value, M::Error> {
+        Grid::deserialize(MapAccessDeserializer::new(map)).map(Encoding::Grid)
+    }
+}
+
+pub fn serialize<A, S>(tiles: &PositionMap<A>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    A: Serialize + Ord + Clone,
+    S: Serializer,
+{
+    let mut palette: BTreeMap<&A, i64> = BTreeMap::new();
+    let mut floors: BTreeMap<i64, Vec<(&Position, i64)>> = BTreeMap::new();
+    for (position, tile) in tiles {
+        let next = palette.len() as i64;
+        let index = *palette.entry(tile).or_insert(next);
+        floors
+            .entry(position.z)
+            .or_default()
+            .push((position, index));
+    }
+
+    let floors = floors
+        .into_iter()
+        .map(|(z, cells)| {
+            let x = cells
+                .iter()
+                .map(|(position, _)| position.x)
+                .min()
+                .unwrap_or(0);
+            let y = cells
+                .iter()
+                .map(|(position, _)| position.y)
+                .min()
+                .unwrap_or(0);
+            let width = cells
+                .iter()
+                .map(|(position, _)| position.x - x + 1)
+                .max()
+                .unwrap_or(0);
+            let height = cells
+                .iter()
+                .map(|(position, _)| position.y - y + 1)
+                .max()
+                .unwrap_or(0);
+
+            let mut grid = vec![EMPTY; (width * height) as usize];
+            for (position, index) in cells {
+                grid[((position.y - y) * width + position.x - x) as usize] = index;
+            }
+
+            let mut runs: Vec<i64> = Vec::new();
+            for cell in grid {
+                match runs.as_mut_slice() {
+                    [.., count, last] if *last == cell => *count += 1,
+                    _ => runs.extend([1, cell]),
+                }
+            }
+
+            Floor {
+                z,
+                x,
+                y,
+                width: width as u64,
+                height: height as u64,
+                runs,
+            }
+        })
+        .collect();
+
+    let mut palette: Vec<(&A, i64)> = palette.into_iter().collect();
+    palette.sort_by_key(|(_, index)| *index);
+    Grid {
+        palette: palette.into_iter().map(|(tile, _)| tile.clone()).collect(),
+        floors,
+    }
+    .serialize(serializer)
+}
+
+pub fn deserialize<'de, A, D>(deserializer: D) -> Result<PositionMap<A>, D::Error>
+where
+    A: Deserialize<'de> + Clone,
+    D: Deserializer<'de>,
+{
+    let grid = match deserializer.deserialize_any(EncodingVisitor(PhantomData))? {
+        Encoding::List(tiles) => return Ok(tiles),
+        Encoding::Grid(grid) => grid,
+    };
+
+    let mut tiles = Vec::new();
+    for floor in grid.floors {
+        if floor.runs.len() % 2 != 0 {
+            return Err(D::Error::custom(format!(
+                "floor {} has an unpaired run length",
+                floor.z
+            )));
+        }
+        if floor.width == 0 {
+            return Err(D::Error::custom(format!("floor {} has no width", floor.z)));
+        }
+        let (width, cells) = i64::try_from(floor.width)
+            .ok()
+            .zip(i64::try_from(floor.height).ok())
+            .and_then(|(width, height)| Some((width, width.checked_mul(height)?)))
+            .ok_or_else(|| {
+                D::Error::custom(format!(
+                    "floor {} is too large at {}x{}",
+                    floor.z, floor.width, floor.height
+                ))
+            })?;
+        let mut cell = 0i64;
+        for run in floor.runs.chunks(2) {
+            let (count, index) = (run[0], run[1]);
+            if count <= 0 {
+                return Err(D::Error::custom(format!(
+                    "floor {} has a run of length {}",
+                    floor.z, count
+                )));
+            }
+            // Checked before expanding the run, which may be huge
+            if count > cells - cell {
+                return Err(D::Error::custom(format!(
+                    "floor {} has more than {} cells but is {}x{}",
+                    floor.z, cells, floor.width, floor.height
+                )));
+            }
+            if index != EMPTY {
+                let tile = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| grid.palette.get(index))
+                    .ok_or_else(|| {
+                        D::Error::custom(format!(
+                            "floor {} uses missing palette entry {}",
+                            floor.z, index
+                        ))
+                    })?;
+                for i in cell..cell + count {
+                    let position = Position::new(floor.x + i % width, floor.y + i / width, floor.z);
+                    tiles.push((position, tile.clone()));
+                }
+            }
+            cell += count;
+        }
+        if cell != cells {
+            return Err(D::Error::custom(format!(
+                "floor {} has {} cells but is {}x{}",
+                floor.z, cell, floor.width, floor.height
+            )));
+        }
+    }
+    Ok(tiles.into_iter().collect())
+}
+
+#[test]
+fn test_grid_round_trip() {
+    use crate::map::Tile;
+
+    let wall = Tile::new(947, false);
+    let floor = Tile::new(960, true);
+    let tiles: PositionMap<Tile> = (-2..=2)
+        .flat_map(|x: i64| (0..3).map(move |y| (x, y)))
+        .flat_map(|(x, y)| [(x, y, 0), (x * 2, y, 3)])
+        .filter(|(x, y, _)| !(*x == 1 && *y == 1))
+        .map(|(x, y, z)| {
+            let tile = if x.abs() == 2 {
+                wall.clone()
+            } else {
+                floor.clone()
+            };
+            (Position::new(x, y, z), tile)
+        })
+        .collect();
+
+    let mut json = Vec::new();
+    serialize(&tiles, &mut serde_json::Serializer::new(&mut json)).unwrap();
+    let decoded: PositionMap<Tile> =
+        deserialize(&mut serde_json::Deserializer::from_slice(&json)).unwrap();
+    assert!(decoded == tiles);
+
+    let list = serde_json::to_string(&tiles).unwrap();
+    let decoded: PositionMap<Tile> =
+        deserialize(&mut serde_json::Deserializer::from_str(&list)).unwrap();
+    assert!(decoded == tiles);
+    assert!(json.len() < list.len());
+}
+
+#[test]
+fn test_grid_rejects_bad_floors() {
+    use crate::map::Tile;
+
+    let decode = |json: &str| {
+        deserialize::<Tile, _>(&mut serde_json::Deserializer::from_str(json))
+            .err()
+            .map(|error| error.to_string())
+    };
+    let grid = |width: u64, height: u64, runs: &str| {
+        format!(
+            r#"{{"palette":[{{"sprite_index":960,"kind":"Floor"}}],"floors":[{{"z":0,"x":0,"y":0,"width":{},"height":{},"runs":{}}}]}}"#,
+            width, height, runs
+        )
+    };
+
+    assert!(decode(&grid(2, 1, "[2,0]")).is_none());
+    assert!(decode(&grid(0, 1, "[1,0]")).is_some_and(|error| error.contains("no width")));
+    assert!(decode(&grid(2, 1, "[0,0,2,0]")).is_some_and(|error| error.contains("length 0")));
+    assert!(decode(&grid(2, 1, "[-5,0,7,0]")).is_some_and(|error| error.contains("length -5")));
+    assert!(decode(&grid(2, 1, "[9223372036854775807,0]"))
+        .is_some_and(|error| error.contains("more than 2 cells")));
+    assert!(decode(&grid(u64::MAX, 2, "[1,0]")).is_some_and(|error| error.contains("too large")));
+
+    // A mistake inside the grid is reported at its own path
+    #[derive(Deserialize)]
+    struct Room {
+        #[serde(deserialize_with = "deserialize")]
+        #[allow(dead_code)]
+        tiles: PositionMap<Tile>,
+    }
+    let error =
+        serde_path_to_error::deserialize::<_, Room>(&mut serde_json::Deserializer::from_str(
+            &format!(r#"{{"tiles":{}}}"#, grid(1, 1, r#"[1,"zero"]"#)),
+        ))
+        .err()
+        .expect("runs should not parse");
+    assert_eq!(error.path().to_string(), "tiles.floors[0].runs[1]");
+}
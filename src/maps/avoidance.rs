@@ -5,6 +5,16 @@ pub fn avoidance() -> Map {
     const N_FLOORS: i64 = 10;
     let mut room = Room::new(Position::new(5, 5, 0));
     let victory_position = Position::new(0, 5, N_FLOORS * 2 - 1);
+    let up_stairs = Tile {
+        sprite_index: UP_STAIRS_SPRITE,
+        kind: TileKind::UpStairs,
+        lock: None,
+    };
+    let down_stairs = Tile {
+        sprite_index: DOWN_STAIRS_SPRITE,
+        kind: TileKind::DownStairs,
+        lock: None,
+    };
 
     for z in 0..(N_FLOORS * 2) {
         if z % 2 == 0 {
@@ -13,13 +23,13 @@ pub fn avoidance() -> Map {
                     if Position::new(x, y, z) == victory_position {
                         room.add_tile(Position::new(x, y, z), Tile::new(960 + 64 + 30, true));
                     } else if z % 4 == 0 && x == 20 && y == 5 {
-                        room.add_tile(Position::new(x, y, z), Tile::new(64 * 15 + 42, true));
+                        room.add_tile(Position::new(x, y, z), up_stairs.clone());
                     } else if z % 4 == 2 && x == 0 && y == 5 {
-                        room.add_tile(Position::new(x, y, z), Tile::new(64 * 15 + 42, true));
+                        room.add_tile(Position::new(x, y, z), up_stairs.clone());
                     } else if z % 4 == 0 && x == 0 && y == 5 && z > 0 {
-                        room.add_tile(Position::new(x, y, z), Tile::new(64 * 15 + 41, true));
+                        room.add_tile(Position::new(x, y, z), down_stairs.clone());
                     } else if z % 4 == 2 && x == 20 && y == 5 {
-                        room.add_tile(Position::new(x, y, z), Tile::new(64 * 15 + 41, true));
+                        room.add_tile(Position::new(x, y, z), down_stairs.clone());
                     } else {
                         room.add_tile(Position::new(x, y, z), Tile::new(960, true));
                     }
@@ -63,10 +73,13 @@ pub fn avoidance() -> Map {
         } else {
             for x in 0..=20 {
                 for y in 0..=10 {
-                    if z % 4 == 1 && x == 20 && y == 5 {
-                        room.add_tile(Position::new(x, y, z), Tile::new(64 * 15 + 42, true));
+                    // The top of the last staircase only leads back down
+                    if Position::new(x, y, z) == victory_position {
+                        room.add_tile(Position::new(x, y, z), down_stairs.clone());
+                    } else if z % 4 == 1 && x == 20 && y == 5 {
+                        room.add_tile(Position::new(x, y, z), up_stairs.clone());
                     } else if z % 4 == 3 && x == 0 && y == 5 {
-                        room.add_tile(Position::new(x, y, z), Tile::new(64 * 15 + 42, true));
+                        room.add_tile(Position::new(x, y, z), up_stairs.clone());
                     } else {
                         room.add_tile(Position::new(x, y, z), Tile::new(15 * 64 - 13, false));
                     }
@@ -76,10 +89,15 @@ pub fn avoidance() -> Map {
     }
 
     Map {
-        room,
+        version: MAP_VERSION,
+        rooms: vec![room],
+        initial_room: 0,
         player_health: 1000,
         player_strength: 20,
         player_sprite: 31 * 64 + 20,
-        victory_condition: VictoryCondition::Arrival(victory_position),
+        items: Default::default(),
+        inventory_capacity: DEFAULT_INVENTORY_CAPACITY,
+        defeat_condition: Default::default(),
+        victory_condition: VictoryCondition::Arrival(0, victory_position),
     }
 }
@@ -0,0 +1,227 @@
+//! Seeded cave generation by cellular automaton. The cave starts out as
+//! random rock and is smoothed until the rock clumps into walls, then every
+//! pocket cut off from the largest open area is filled in, so the start and
+//! the victory tile, at opposite ends of the cave, are always connected.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::{
+    components::{EnemyType, Position},
+    map::{
+        Enemy, Health, Map, Room, Tile, VictoryCondition, DEFAULT_INVENTORY_CAPACITY, MAP_VERSION,
+    },
+    maps::procedural::{compute_reasonable_player_health, compute_reasonable_player_strength},
+};
+
+const WIDTH: i64 = 60;
+const HEIGHT: i64 = 40;
+/// Chance of each cell starting out as rock.
+const FILL: f64 = 0.45;
+const SMOOTHING_PASSES: usize = 5;
+/// Caves whose open area is smaller than this fraction are generated again.
+const MIN_OPEN_FRACTION: f64 = 0.3;
+/// Enemies keep this many steps away from the start.
+const SAFE_DISTANCE: u64 = 10;
+
+/// Rough grey rock, one picked at random for each wall tile.
+const WALL_SPRITES: [u64; 4] = [12 * 64 + 41, 12 * 64 + 42, 12 * 64 + 43, 12 * 64 + 44];
+const FLOOR_SPRITE: u64 = 13 * 64 + 8;
+const ARRIVAL_SPRITE: u64 = 960 + 64 + 30;
+const HEALTH_SPRITE: u64 = 64 * 23 + 45;
+
+/// Whether each cell of the cave is rock, row by row.
+type Grid = Vec<Vec<bool>>;
+
+fn is_rock(grid: &Grid, x: i64, y: i64) -> bool {
+    // Beyond the edges is solid rock
+    !(0..WIDTH).contains(&x) || !(0..HEIGHT).contains(&y) || grid[y as usize][x as usize]
+}
+
+/// One pass of the automaton: a cell becomes rock when most of the 3x3
+/// block around it is.
+fn smooth(grid: &Grid) -> Grid {
+    (0..HEIGHT)
+        .map(|y| {
+            (0..WIDTH)
+                .map(|x| {
+                    let rock = (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                        .filter(|(dx, dy)| is_rock(grid, x + dx, y + dy))
+                        .count();
+                    rock >= 5
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Steps from `from` to every open cell reachable from it, moving the way
+/// the player does.
+fn distances(grid: &Grid, from: (i64, i64)) -> BTreeMap<(i64, i64), u64> {
+    let mut distances = BTreeMap::from([(from, 0)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some((x, y)) = queue.pop_front() {
+        let distance = distances[&(x, y)];
+        for next in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+            if !is_rock(grid, next.0, next.1) && !distances.contains_key(&next) {
+                distances.insert(next, distance + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+    distances
+}
+
+/// Generates caves until one is open enough, and returns it with only its
+/// largest open area left open.
+fn carve(rng: &mut StdRng) -> Grid {
+    loop {
+        let mut grid: Grid = (0..HEIGHT)
+            .map(|_| (0..WIDTH).map(|_| rng.gen_bool(FILL)).collect())
+            .collect();
+        for _ in 0..SMOOTHING_PASSES {
+            grid = smooth(&grid);
+        }
+
+        let mut seen = BTreeSet::new();
+        let mut largest = BTreeSet::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if is_rock(&grid, x, y) || seen.contains(&(x, y)) {
+                    continue;
+                }
+                let pocket: BTreeSet<(i64, i64)> = distances(&grid, (x, y)).into_keys().collect();
+                seen.extend(pocket.iter().copied());
+                if pocket.len() > largest.len() {
+                    largest = pocket;
+                }
+            }
+        }
+        if (largest.len() as f64) < MIN_OPEN_FRACTION * (WIDTH * HEIGHT) as f64 {
+            continue;
+        }
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                grid[y as usize][x as usize] = !largest.contains(&(x, y));
+            }
+        }
+        return grid;
+    }
+}
+
+/// The open cell furthest from `from`, and how far every cell is from it.
+fn furthest(grid: &Grid, from: (i64, i64)) -> ((i64, i64), BTreeMap<(i64, i64), u64>) {
+    let distances = distances(grid, from);
+    let cell = distances
+        .iter()
+        .max_by_key(|(_, distance)| **distance)
+        .map(|(cell, _)| *cell)
+        .unwrap_or(from);
+    (cell, distances)
+}
+
+pub fn caves(seed: u64) -> Map {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let grid = carve(&mut rng);
+    let open: Vec<(i64, i64)> = (0..HEIGHT)
+        .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+        .filter(|(x, y)| !is_rock(&grid, *x, *y))
+        .collect();
+
+    // The two ends of the cave: the cell furthest from a random one, and the
+    // one furthest from that
+    let (start, _) = furthest(&grid, open[rng.gen_range(0..open.len())]);
+    let (arrival, from_start) = furthest(&grid, start);
+
+    let mut room = Room::new(Position::new(start.0, start.1, 0));
+    for (x, y) in &open {
+        let sprite_index = if (*x, *y) == arrival {
+            ARRIVAL_SPRITE
+        } else {
+            FLOOR_SPRITE
+        };
+        room.add_tile(Position::new(*x, *y, 0), Tile::new(sprite_index, true));
+    }
+    for y in -1..=HEIGHT {
+        for x in -1..=WIDTH {
+            let next_to_open = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                .any(|(dx, dy)| !is_rock(&grid, x + dx, y + dy));
+            if is_rock(&grid, x, y) && next_to_open {
+                let sprite_index = WALL_SPRITES[rng.gen_range(0..WALL_SPRITES.len())];
+                room.add_tile(Position::new(x, y, 0), Tile::new(sprite_index, false));
+            }
+        }
+    }
+
+    let mut taken = BTreeSet::from([start, arrival]);
+    let far: Vec<(i64, i64)> = open
+        .iter()
+        .copied()
+        .filter(|cell| from_start[cell] >= SAFE_DISTANCE)
+        .collect();
+    for _ in 0..open.len() / 60 {
+        if far.is_empty() {
+            break;
+        }
+        let cell = far[rng.gen_range(0..far.len())];
+        if !taken.insert(cell) {
+            continue;
+        }
+        let enemy_type =
+            [EnemyType::Skeleton, EnemyType::Orc, EnemyType::Ghost][rng.gen_range(0..3)];
+        let (health, strength) = enemy_type.get_stats(0);
+        let position = Position::new(cell.0, cell.1, 0);
+        room.add_enemy(
+            position,
+            Enemy {
+                enemy_type: Some(enemy_type),
+                ..Enemy::new(
+                    enemy_type.sprite_index() as u64,
+                    health as u64,
+                    strength as u64,
+                    Enemy::circular_wake_zone(position, 4),
+                )
+            },
+        );
+    }
+    for _ in 0..open.len() / 150 {
+        let cell = open[rng.gen_range(0..open.len())];
+        if taken.insert(cell) {
+            room.add_health(
+                Position::new(cell.0, cell.1, 0),
+                Health {
+                    sprite_index: HEALTH_SPRITE,
+                    health: 5,
+                },
+            );
+        }
+    }
+
+    let player_strength = compute_reasonable_player_strength(&room);
+    Map {
+        version: MAP_VERSION,
+        player_health: compute_reasonable_player_health(&room, player_strength),
+        player_strength,
+        rooms: vec![room],
+        initial_room: 0,
+        player_sprite: 32 * 64 + 45,
+        items: Default::default(),
+        inventory_capacity: DEFAULT_INVENTORY_CAPACITY,
+        defeat_condition: Default::default(),
+        victory_condition: VictoryCondition::Arrival(0, Position::new(arrival.0, arrival.1, 0)),
+    }
+}
+
+#[test]
+fn test_caves_are_seeded_and_connected() {
+    for seed in 0..10 {
+        let map = caves(seed);
+        assert!(map == caves(seed), "seed {} is not reproducible", seed);
+        assert_eq!(map.validate(), vec![], "seed {}", seed);
+    }
+    assert!(caves(1) != caves(2));
+}
@@ -0,0 +1,424 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use serde_path_to_error::Segment;
+
+use super::migrate::{self, MigrationError};
+use super::text::{self, TextMapError};
+use super::tiled::{self, TiledError};
+use crate::map::{Map, MAP_VERSION};
+use crate::validation::MapProblem;
+
+/// Directory scanned for map files when the game starts.
+pub const MAPS_DIRECTORY: &str = "assets/maps";
+
+#[derive(Debug)]
+pub enum MapLoadError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// `line` and `column` point into the file as written, even when the
+    /// error was found after upgrading it, and are 0 if unknown.
+    Parse {
+        path: PathBuf,
+        field: String,
+        line: usize,
+        column: usize,
+        error: serde_json::Error,
+    },
+    Migration {
+        path: PathBuf,
+        error: MigrationError,
+    },
+    Text {
+        path: PathBuf,
+        error: TextMapError,
+    },
+    Tiled {
+        path: PathBuf,
+        error: TiledError,
+    },
+    /// The map loaded, but has problems that would stop it from being played
+    Invalid {
+        path: PathBuf,
+        problems: Vec<MapProblem>,
+    },
+}
+
+impl fmt::Display for MapLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapLoadError::Io { path, error } => {
+                write!(f, "{}: could not read map: {}", path.display(), error)
+            }
+            MapLoadError::Parse {
+                path,
+                field,
+                line: 0,
+                error,
+                ..
+            } => write!(
+                f,
+                "{}: invalid map at `{}`: {}",
+                path.display(),
+                field,
+                error
+            ),
+            MapLoadError::Parse {
+                path,
+                field,
+                line,
+                column,
+                error,
+            } => write!(
+                f,
+                "{}:{}:{}: invalid map at `{}`: {}",
+                path.display(),
+                line,
+                column,
+                field,
+                error
+            ),
+            MapLoadError::Migration { path, error } => {
+                write!(f, "{}: could not upgrade map: {}", path.display(), error)
+            }
+            MapLoadError::Text { path, error } => write!(
+                f,
+                "{}:{}: invalid text map: {}",
+                path.display(),
+                error.line,
+                error.message
+            ),
+            MapLoadError::Tiled { path, error } => {
+                write!(f, "{}: invalid Tiled map: {}", path.display(), error)
+            }
+            MapLoadError::Invalid { path, problems } => {
+                write!(f, "{}: unplayable map: ", path.display())?;
+                for (i, problem) in problems.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapLoadError {}
+
+fn is_text_map(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "txt")
+}
+
+/// Loads a JSON map, or a text map (see `maps::text`) if the file ends in
+/// `.txt`, and rejects it if it has problems the game can't play around,
+/// such as a missing initial room.
+pub fn from_file(path: impl AsRef<Path>) -> Result<Map, MapLoadError> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).map_err(|error| MapLoadError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let map = if is_text_map(path) {
+        text::from_text(&contents).map_err(|error| MapLoadError::Text {
+            path: path.to_path_buf(),
+            error,
+        })?
+    } else {
+        from_str(path, &contents)?
+    };
+    playable(path, map)
+}
+
+fn playable(path: &Path, map: Map) -> Result<Map, MapLoadError> {
+    let problems: Vec<MapProblem> = map
+        .validate()
+        .into_iter()
+        .filter(MapProblem::is_fatal)
+        .collect();
+    if problems.is_empty() {
+        Ok(map)
+    } else {
+        Err(MapLoadError::Invalid {
+            path: path.to_path_buf(),
+            problems,
+        })
+    }
+}
+
+/// Parses a map document, upgrading it first if it was written by an older
+/// version, and reports the path of the offending field on failure. Maps
+/// exported from Tiled are imported instead. `path` is only used to label
+/// errors.
+pub fn from_str(path: impl AsRef<Path>, contents: &str) -> Result<Map, MapLoadError> {
+    let path = path.as_ref();
+    let parse_error = |error: serde_path_to_error::Error<serde_json::Error>| {
+        // Errors in migrated documents carry no position, so look the field
+        // up in the text as written
+        let (line, column) = match error.inner().line() {
+            0 => line_and_column(contents, locate(contents, error.path())),
+            line => (line, error.inner().column()),
+        };
+        MapLoadError::Parse {
+            path: path.to_path_buf(),
+            field: error.path().to_string(),
+            line,
+            column,
+            error: error.into_inner(),
+        }
+    };
+    let document: Value = serde_json::from_str(contents).map_err(|error| MapLoadError::Parse {
+        path: path.to_path_buf(),
+        field: ".".into(),
+        line: error.line(),
+        column: error.column(),
+        error,
+    })?;
+    if tiled::is_tiled(&document) {
+        return tiled::from_tiled(document).map_err(|error| MapLoadError::Tiled {
+            path: path.to_path_buf(),
+            error,
+        });
+    }
+    if migrate::version(&document) == MAP_VERSION {
+        // Deserialize from the text rather than the value to keep line numbers
+        let deserializer = &mut serde_json::Deserializer::from_str(contents);
+        serde_path_to_error::deserialize(deserializer).map_err(parse_error)
+    } else {
+        let document = migrate::migrate(document).map_err(|error| MapLoadError::Migration {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        serde_path_to_error::deserialize(document).map_err(parse_error)
+    }
+}
+
+/// Finds the byte offset of the value at `path` in the JSON text `contents`.
+/// Migrations may move or rename fields, so if part of the path doesn't exist
+/// as written, the offset of the deepest part that does is returned.
+fn locate(contents: &str, path: &serde_path_to_error::Path) -> usize {
+    let bytes = contents.as_bytes();
+    let mut offset = skip_whitespace(bytes, 0);
+    for segment in path.iter() {
+        let found = match segment {
+            Segment::Map { key } | Segment::Enum { variant: key } => member(bytes, offset, key),
+            Segment::Seq { index } => element(bytes, offset, *index),
+            Segment::Unknown => None,
+        };
+        match found {
+            Some(found) => offset = found,
+            None => break,
+        }
+    }
+    offset
+}
+
+/// The offset of the value of `key` in the object starting at `offset`.
+fn member(bytes: &[u8], offset: usize, key: &str) -> Option<usize> {
+    if bytes.get(offset) != Some(&b'{') {
+        return None;
+    }
+    let mut offset = skip_whitespace(bytes, offset + 1);
+    while bytes.get(offset) == Some(&b'"') {
+        let key_end = skip_value(bytes, offset);
+        let name: String = serde_json::from_slice(&bytes[offset..key_end]).ok()?;
+        offset = skip_whitespace(bytes, key_end);
+        if bytes.get(offset) != Some(&b':') {
+            return None;
+        }
+        offset = skip_whitespace(bytes, offset + 1);
+        if name == key {
+            return Some(offset);
+        }
+        offset = skip_whitespace(bytes, skip_value(bytes, offset));
+        if bytes.get(offset) == Some(&b',') {
+            offset = skip_whitespace(bytes, offset + 1);
+        }
+    }
+    None
+}
+
+/// The offset of element `index` of the array starting at `offset`.
+fn element(bytes: &[u8], offset: usize, index: usize) -> Option<usize> {
+    if bytes.get(offset) != Some(&b'[') {
+        return None;
+    }
+    let mut offset = skip_whitespace(bytes, offset + 1);
+    for _ in 0..index {
+        offset = skip_whitespace(bytes, skip_value(bytes, offset));
+        if bytes.get(offset) != Some(&b',') {
+            return None;
+        }
+        offset = skip_whitespace(bytes, offset + 1);
+    }
+    match bytes.get(offset) {
+        Some(b']') | None => None,
+        Some(_) => Some(offset),
+    }
+}
+
+fn skip_whitespace(bytes: &[u8], mut offset: usize) -> usize {
+    while bytes.get(offset).is_some_and(u8::is_ascii_whitespace) {
+        offset += 1;
+    }
+    offset
+}
+
+/// The offset just past the value starting at `offset`. The document has
+/// already been parsed, so it is known to be valid JSON.
+fn skip_value(bytes: &[u8], mut offset: usize) -> usize {
+    let mut depth = 0;
+    let mut in_string = false;
+    while let Some(&byte) = bytes.get(offset) {
+        offset += 1;
+        match byte {
+            b'\\' if in_string => offset += 1,
+            b'"' => {
+                in_string = !in_string;
+                if !in_string && depth == 0 {
+                    return offset;
+                }
+            }
+            _ if in_string => {}
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return offset;
+                }
+                if depth < 0 {
+                    return offset - 1;
+                }
+            }
+            b',' | b':' if depth == 0 => return offset - 1,
+            _ if depth == 0 && byte.is_ascii_whitespace() => return offset - 1,
+            _ => {}
+        }
+    }
+    offset
+}
+
+/// The 1-based line and column of a byte offset.
+fn line_and_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Lists the `.json`, `.tmj` and `.txt` map files in `directory`, sorted by
+/// name. A missing directory simply yields no maps.
+pub fn map_files(directory: impl AsRef<Path>) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    is_text_map(path)
+                        || path
+                            .extension()
+                            .is_some_and(|extension| extension == "json" || extension == "tmj")
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_parse_error_names_field() {
+    let error = from_str(
+        "broken.json",
+        r#"{
+  "version": 6,
+  "rooms": [{
+    "initial_position": [0, 0, 0],
+    "tiles": [],
+    "enemies": [],
+    "healths": []
+  }],
+  "initial_room": 0,
+  "player_health": "lots",
+  "player_strength": 1,
+  "player_sprite": 1,
+  "victory_condition": "Extermination"
+}"#,
+    )
+    .err()
+    .expect("map should not parse");
+    match error {
+        MapLoadError::Parse { field, line, .. } => {
+            assert_eq!(field, "player_health");
+            assert_eq!(line, 10);
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn test_missing_initial_room_is_rejected() {
+    let map = from_str(
+        "lost.json",
+        r#"{
+  "version": 6,
+  "rooms": [],
+  "initial_room": 2,
+  "player_health": 10,
+  "player_strength": 1,
+  "player_sprite": 1,
+  "victory_condition": "Extermination"
+}"#,
+    )
+    .unwrap_or_else(|error| panic!("{}", error));
+    assert!(matches!(
+        playable(Path::new("lost.json"), map),
+        Err(MapLoadError::Invalid { problems, .. })
+            if problems == vec![MapProblem::MissingInitialRoom { room: 2 }]
+    ));
+}
+
+#[test]
+fn test_migrated_parse_error_keeps_line() {
+    let error = from_str(
+        "old.json",
+        r#"{
+  "version": 3,
+  "rooms": [{
+    "tiles": [
+      [[0, 0, 0], {"sprite_index": 1, "kind": "Floor"}],
+      [[1, 0, 0], {"sprite_index": 1, "kind": "Floor"}]
+    ],
+    "initial_position": [0, 0, "top"],
+    "enemies": [],
+    "healths": []
+  }],
+  "initial_room": 0,
+  "player_health": 10,
+  "player_strength": 1,
+  "player_sprite": 1,
+  "victory_condition": {"Arrival": [1, 0, 0]}
+}"#,
+    )
+    .err()
+    .expect("map should not parse");
+    match error {
+        MapLoadError::Parse {
+            field,
+            line,
+            column,
+            ..
+        } => {
+            assert_eq!(field, "rooms[0].initial_position[2]");
+            assert_eq!((line, column), (8, 32));
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+}
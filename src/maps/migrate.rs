@@ -0,0 +1,444 @@
+//! Upgrades map documents written against older versions of `map::Map`.
+//!
+//! Every change that stops old documents from deserializing bumps
+//! `map::MAP_VERSION` and appends a step to `MIGRATIONS` which rewrites a
+//! document of the previous version, as raw JSON, into the new shape. Each
+//! historical shape has a fixture under `src/maps/fixtures`.
+
+use std::fmt;
+
+use serde_json::{json, Map as Object, Value};
+
+use crate::map::{DOWN_STAIRS_SPRITE, MAP_VERSION, UP_STAIRS_SPRITE};
+
+/// `MIGRATIONS[n]` upgrades a version `n` document to version `n + 1`.
+const MIGRATIONS: [fn(&mut Object<String, Value>); MAP_VERSION as usize] = [
+    unversioned_to_v1,
+    v1_to_v2,
+    v2_to_v3,
+    v3_to_v4,
+    v4_to_v5,
+    v5_to_v6,
+];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    NotAnObject,
+    TooNew(u64),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::NotAnObject => write!(f, "a map must be a JSON object"),
+            MigrationError::TooNew(version) => write!(
+                f,
+                "map version {} is newer than the supported version {}",
+                version, MAP_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+/// The schema version of a document. Documents from before versioning have none.
+pub fn version(document: &Value) -> u64 {
+    document.get("version").and_then(Value::as_u64).unwrap_or(0)
+}
+
+pub fn migrate(mut document: Value) -> Result<Value, MigrationError> {
+    let from = version(&document);
+    if from > MAP_VERSION {
+        return Err(MigrationError::TooNew(from));
+    }
+    let map = document
+        .as_object_mut()
+        .ok_or(MigrationError::NotAnObject)?;
+    for (step, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        migration(map);
+        map.insert("version".into(), json!(step + 1));
+    }
+    Ok(document)
+}
+
+/// Unversioned documents either hold a single `room`, or a `rooms` list whose
+/// rooms may be missing `healths`, in which case they also lack a
+/// `victory_condition`.
+fn unversioned_to_v1(map: &mut Object<String, Value>) {
+    if let Some(room) = map.remove("room") {
+        map.insert("rooms".into(), json!([room]));
+    }
+    if let Some(Value::Array(rooms)) = map.get_mut("rooms") {
+        for room in rooms.iter_mut().filter_map(Value::as_object_mut) {
+            room.entry("healths").or_insert(json!([]));
+        }
+    }
+    map.entry("initial_room").or_insert(json!(0));
+    map.entry("victory_condition")
+        .or_insert(json!("Extermination"));
+}
+
+/// Tiles trade `passable` for a `kind`. Passable tiles drawn with a staircase
+/// sprite become stairs in that direction, since floors used to be changed
+/// from any tile.
+fn v1_to_v2(map: &mut Object<String, Value>) {
+    fn tile_kind(tile: &mut Value) {
+        let Some(tile) = tile.as_object_mut() else {
+            return;
+        };
+        let passable = tile
+            .remove("passable")
+            .and_then(|passable| passable.as_bool())
+            .unwrap_or(false);
+        let sprite_index = tile.get("sprite_index").and_then(Value::as_u64);
+        let kind = match sprite_index {
+            _ if !passable => "Wall",
+            Some(UP_STAIRS_SPRITE) => "UpStairs",
+            Some(DOWN_STAIRS_SPRITE) => "DownStairs",
+            _ => "Floor",
+        };
+        tile.insert("kind".into(), json!(kind));
+    }
+
+    let Some(Value::Array(rooms)) = map.get_mut("rooms") else {
+        return;
+    };
+    for room in rooms.iter_mut() {
+        match room.get_mut("tiles") {
+            // Either a list of `[position, tile]` pairs or a grid with a palette
+            Some(Value::Array(tiles)) => {
+                for entry in tiles.iter_mut() {
+                    if let Some(tile) = entry.get_mut(1) {
+                        tile_kind(tile);
+                    }
+                }
+            }
+            Some(Value::Object(grid)) => {
+                if let Some(Value::Array(palette)) = grid.get_mut("palette") {
+                    palette.iter_mut().for_each(tile_kind);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+/// Rooms' `keys` become `items`, each key's item described once in the map's
+/// `items`.
+fn v2_to_v3(map: &mut Object<String, Value>) {
+    let mut items = Object::new();
+    if let Some(Value::Array(rooms)) = map.get_mut("rooms") {
+        for room in rooms.iter_mut().filter_map(Value::as_object_mut) {
+            let Some(Value::Array(keys)) = room.remove("keys") else {
+                continue;
+            };
+            let mut placed = Vec::new();
+            for entry in keys {
+                let (Some(position), Some(key)) = (entry.get(0), entry.get(1)) else {
+                    continue;
+                };
+                let Some(item) = key.get("item").and_then(Value::as_u64) else {
+                    continue;
+                };
+                items.entry(item.to_string()).or_insert_with(|| {
+                    json!({
+                        "name": format!("Key {}", item),
+                        "sprite_index": key.get("sprite_index").cloned().unwrap_or(json!(0)),
+                    })
+                });
+                placed.push(json!([position, item]));
+            }
+            room.insert("items".into(), Value::Array(placed));
+        }
+    }
+    if !items.is_empty() {
+        map.insert("items".into(), Value::Object(items));
+    }
+}
+
+/// `Arrival` targets name their room, which is taken to be the initial room,
+/// since reaching the position in any room used to win.
+fn v3_to_v4(map: &mut Object<String, Value>) {
+    fn add_room(condition: &mut Value, room: &Value) {
+        let Some(condition) = condition.as_object_mut() else {
+            return;
+        };
+        for (name, value) in condition.iter_mut() {
+            match (name.as_str(), value) {
+                ("Arrival", position) => *position = json!([room, position.take()]),
+                ("Or" | "And", Value::Array(conditions)) => {
+                    for condition in conditions {
+                        add_room(condition, room);
+                    }
+                }
+                ("Not", condition) => add_room(condition, room),
+                _ => {}
+            }
+        }
+    }
+
+    let room = map.get("initial_room").cloned().unwrap_or(json!(0));
+    if let Some(condition) = map.get_mut("victory_condition") {
+        add_room(condition, &room);
+    }
+}
+
+/// `Region` targets name their room too, again taken to be the initial room.
+fn v4_to_v5(map: &mut Object<String, Value>) {
+    fn add_room(condition: &mut Value, room: &Value) {
+        let Some(condition) = condition.as_object_mut() else {
+            return;
+        };
+        for (name, value) in condition.iter_mut() {
+            match (name.as_str(), value) {
+                ("Region", Value::Array(corners)) => corners.insert(0, room.clone()),
+                ("Or" | "And", Value::Array(conditions)) => {
+                    for condition in conditions {
+                        add_room(condition, room);
+                    }
+                }
+                ("Not", condition) => add_room(condition, room),
+                _ => {}
+            }
+        }
+    }
+
+    let room = map.get("initial_room").cloned().unwrap_or(json!(0));
+    if let Some(condition) = map.get_mut("victory_condition") {
+        add_room(condition, &room);
+    }
+}
+
+/// `EnemyArrival` targets in the defeat condition name their room, again
+/// taken to be the initial room.
+fn v5_to_v6(map: &mut Object<String, Value>) {
+    fn add_room(condition: &mut Value, room: &Value) {
+        let Some(condition) = condition.as_object_mut() else {
+            return;
+        };
+        for (name, value) in condition.iter_mut() {
+            match (name.as_str(), value) {
+                ("EnemyArrival", position) => *position = json!([room, position.take()]),
+                ("Or" | "And", Value::Array(conditions)) => {
+                    for condition in conditions {
+                        add_room(condition, room);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let room = map.get("initial_room").cloned().unwrap_or(json!(0));
+    if let Some(condition) = map.get_mut("defeat_condition") {
+        add_room(condition, &room);
+    }
+}
+
+#[cfg(test)]
+fn load_fixture(contents: &str) -> crate::map::Map {
+    crate::maps::file::from_str("fixture.json", contents)
+        .unwrap_or_else(|error| panic!("{}", error))
+}
+
+#[test]
+fn test_migrate_unversioned_single_room() {
+    use crate::components::Position;
+    use crate::map::*;
+
+    let map = load_fixture(include_str!("fixtures/v0_single_room.json"));
+
+    let mut room = Room::new(Position::new(1, 1, 0));
+    room.add_tile(Position::new(1, 1, 0), Tile::new(960, true))
+        .add_tile(Position::new(2, 1, 0), Tile::new(960, true))
+        .add_tile(Position::new(3, 1, 0), Tile::new(947, false))
+        .add_enemy(
+            Position::new(2, 1, 0),
+            Enemy::new(74, 5, 2, [Position::new(1, 1, 0)].into_iter().collect()),
+        )
+        .add_health(
+            Position::new(1, 1, 0),
+            Health {
+                sprite_index: 64 * 23 + 45,
+                health: 10,
+            },
+        );
+    assert!(
+        map == Map {
+            player_health: 20,
+            player_strength: 3,
+            victory_condition: VictoryCondition::Or(vec![
+                VictoryCondition::Extermination,
+                VictoryCondition::Arrival(0, Position::new(2, 1, 0)),
+            ]),
+            ..Map::single_room(room)
+        }
+    );
+}
+
+#[test]
+fn test_migrate_unversioned_rooms_without_healths() {
+    use crate::components::Position;
+    use crate::map::*;
+
+    let map = load_fixture(include_str!("fixtures/v0_rooms_without_healths.json"));
+
+    let mut room = Room::new(Position::new(0, 0, 0));
+    room.add_tile(Position::new(0, 0, 0), Tile::new(960, true))
+        .add_tile(Position::new(1, 0, 0), Tile::new(960, true))
+        .add_enemy(
+            Position::new(1, 0, 0),
+            Enemy::new(74, 100, 5, [Position::new(0, 0, 0)].into_iter().collect()),
+        );
+    assert!(
+        map == Map {
+            player_health: 1000,
+            player_strength: 2,
+            ..Map::single_room(room)
+        }
+    );
+}
+
+#[test]
+fn test_migrate_v1_stairs() {
+    use crate::components::Position;
+    use crate::map::*;
+
+    let map = load_fixture(include_str!("fixtures/v1_stairs.json"));
+
+    let mut room = Room::new(Position::new(0, 0, 0));
+    room.add_tile(
+        Position::new(0, 0, 0),
+        Tile {
+            sprite_index: UP_STAIRS_SPRITE,
+            kind: TileKind::UpStairs,
+            lock: None,
+        },
+    )
+    .add_tile(Position::new(1, 0, 0), Tile::new(947, false))
+    .add_tile(
+        Position::new(0, 0, 1),
+        Tile {
+            sprite_index: DOWN_STAIRS_SPRITE,
+            kind: TileKind::DownStairs,
+            lock: None,
+        },
+    )
+    .add_tile(Position::new(1, 0, 1), Tile::new(960, true));
+    assert!(map.rooms[0] == room);
+    assert!(map.rooms[0].has_stairs(&Position::new(0, 0, 0), 1));
+    assert!(map.rooms[0].has_stairs(&Position::new(0, 0, 1), -1));
+    assert!(!map.rooms[0].has_stairs(&Position::new(1, 0, 0), 1));
+}
+
+#[test]
+fn test_migrate_v2_keys() {
+    use crate::components::Position;
+    use crate::map::*;
+
+    let map = load_fixture(include_str!("fixtures/v2_keys.json"));
+
+    let mut room = Room::new(Position::new(0, 0, 0));
+    room.add_tile(Position::new(0, 0, 0), Tile::new(960, true))
+        .add_tile(
+            Position::new(1, 0, 0),
+            Tile {
+                lock: Some(ItemId(4)),
+                ..Tile::new(960, true)
+            },
+        )
+        .add_item(Position::new(0, 0, 0), ItemId(4));
+    assert!(map.rooms[0] == room);
+    assert!(map.rooms[1].items.get(&Position::new(0, 0, 0)) == Some(&ItemId(4)));
+    assert_eq!(
+        map.items.into_iter().collect::<Vec<_>>(),
+        vec![(
+            ItemId(4),
+            Item {
+                name: "Key 4".into(),
+                sprite_index: 2936,
+                equipment: None,
+            }
+        )]
+    );
+    assert_eq!(map.inventory_capacity, DEFAULT_INVENTORY_CAPACITY);
+}
+
+#[test]
+fn test_migrate_v3_arrivals() {
+    use crate::components::Position;
+    use crate::map::*;
+
+    let map = load_fixture(include_str!("fixtures/v3_arrivals.json"));
+
+    assert!(
+        map.victory_condition
+            == VictoryCondition::And(vec![
+                VictoryCondition::Or(vec![
+                    VictoryCondition::Arrival(1, Position::new(1, 0, 0)),
+                    VictoryCondition::Extermination,
+                ]),
+                VictoryCondition::Not(Box::new(VictoryCondition::Arrival(
+                    1,
+                    Position::new(0, 0, 0)
+                ))),
+            ])
+    );
+}
+
+#[test]
+fn test_migrate_v4_regions() {
+    use crate::components::Position;
+    use crate::map::*;
+
+    let map = load_fixture(include_str!("fixtures/v4_regions.json"));
+
+    assert!(
+        map.victory_condition
+            == VictoryCondition::Or(vec![
+                VictoryCondition::Region(1, Position::new(0, 0, 0), Position::new(1, 1, 0)),
+                VictoryCondition::Not(Box::new(VictoryCondition::Region(
+                    1,
+                    Position::new(1, 0, 0),
+                    Position::new(1, 0, 0)
+                ))),
+                VictoryCondition::Arrival(0, Position::new(1, 0, 0)),
+            ])
+    );
+}
+
+#[test]
+fn test_migrate_v5_enemy_arrivals() {
+    use crate::components::Position;
+    use crate::map::*;
+
+    let map = load_fixture(include_str!("fixtures/v5_enemy_arrivals.json"));
+
+    assert!(
+        map.defeat_condition
+            == DefeatCondition::Or(vec![
+                DefeatCondition::EnemyArrival(1, Position::new(1, 0, 0)),
+                DefeatCondition::And(vec![
+                    DefeatCondition::EnemyArrival(1, Position::new(0, 0, 0)),
+                    DefeatCondition::AllyDeath,
+                ]),
+            ])
+    );
+}
+
+#[test]
+fn test_repository_map_loads() {
+    let map = load_fixture(include_str!("../../map.json"));
+    assert_eq!(map.version, MAP_VERSION);
+    assert_eq!(map.rooms.len(), 1);
+    assert!(!map.rooms[0].enemies.is_empty());
+}
+
+#[test]
+fn test_newer_version_is_rejected() {
+    let document = json!({ "version": MAP_VERSION + 1 });
+    assert!(matches!(
+        migrate(document),
+        Err(MigrationError::TooNew(version)) if version == MAP_VERSION + 1
+    ));
+}
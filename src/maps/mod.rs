@@ -1,7 +1,15 @@
 mod avoidance;
+mod caves;
+mod file;
+mod migrate;
+mod prefab;
 mod procedural;
+pub mod text;
+mod tiled;
 mod unbeatable;
 
 pub use avoidance::avoidance;
+pub use caves::caves;
+pub use file::{from_file, map_files, MAPS_DIRECTORY};
 pub use procedural::procedural;
 pub use unbeatable::unbeatable;
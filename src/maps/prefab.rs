@@ -0,0 +1,238 @@
+//! Hand-made room fragments, such as treasure vaults, ambush rooms and boss
+//! arenas, for generators to stamp into their levels. A prefab is a JSON
+//! file under `src/maps/prefabs` holding a `Room` laid out on floor 0 from
+//! the origin, together with the items it uses. The room's
+//! `initial_position` is its doorway: a passable tile on the edge of the
+//! fragment, which the generator joins to the rest of the level.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::components::Position;
+use crate::map::{Item, ItemId, PositionMap, Room, Trigger, TriggerAction, TriggerCondition};
+
+/// The built-in prefabs. Their item ids must not clash, since a level may
+/// hold several of them.
+const LIBRARY: [&str; 3] = [
+    include_str!("prefabs/vault.json"),
+    include_str!("prefabs/ambush.json"),
+    include_str!("prefabs/arena.json"),
+];
+
+#[derive(Deserialize, Clone)]
+pub struct Prefab {
+    pub name: String,
+    /// The items the fragment holds, gives or has enemies drop
+    #[serde(default)]
+    pub items: BTreeMap<ItemId, Item>,
+    pub room: Room,
+}
+
+/// Panics if a built-in prefab is broken, so that it is caught before a
+/// generator stamps it.
+pub fn library() -> Vec<Prefab> {
+    LIBRARY
+        .iter()
+        .map(|json| {
+            let prefab: Prefab = serde_json::from_str(json).expect("built-in prefabs parse");
+            if let Err(problem) = prefab.check() {
+                panic!("built-in prefab {}: {}", prefab.name, problem);
+            }
+            prefab
+        })
+        .collect()
+}
+
+impl Prefab {
+    /// The width and height of the box the fragment's tiles fill.
+    pub fn size(&self) -> (i64, i64) {
+        (&self.room.tiles)
+            .into_iter()
+            .fold((0, 0), |(width, height), (position, _)| {
+                (width.max(position.x + 1), height.max(position.y + 1))
+            })
+    }
+
+    pub fn doorway(&self) -> Position {
+        self.room.initial_position
+    }
+
+    /// Checks that the doorway is a passable tile on the fragment's edge.
+    pub fn check(&self) -> Result<(), String> {
+        let (width, height) = self.size();
+        let doorway = self.doorway();
+        if !self
+            .room
+            .tiles
+            .get(&doorway)
+            .is_some_and(|tile| tile.passable())
+        {
+            return Err(format!(
+                "doorway ({}, {}) is not a passable tile",
+                doorway.x, doorway.y
+            ));
+        }
+        if doorway.x != 0 && doorway.x != width - 1 && doorway.y != 0 && doorway.y != height - 1 {
+            return Err(format!(
+                "doorway ({}, {}) is not on the edge",
+                doorway.x, doorway.y
+            ));
+        }
+        Ok(())
+    }
+
+    /// The step from the doorway out of the fragment, across the edge it
+    /// lies on. Only meaningful for prefabs that pass `check`.
+    pub fn outwards(&self) -> (i64, i64) {
+        let (width, _) = self.size();
+        let doorway = self.doorway();
+        if doorway.x == 0 {
+            (-1, 0)
+        } else if doorway.x == width - 1 {
+            (1, 0)
+        } else if doorway.y == 0 {
+            (0, -1)
+        } else {
+            (0, 1)
+        }
+    }
+
+    /// The fragment turned a quarter anticlockwise `quarter_turns` times,
+    /// then mirrored left to right if `mirrored`, still starting at the
+    /// origin.
+    pub fn oriented(&self, quarter_turns: u8, mirrored: bool) -> Prefab {
+        let (mut width, mut height) = self.size();
+        let mut prefab = self.clone();
+        for _ in 0..quarter_turns % 4 {
+            prefab.room = moved(&prefab.room, |p| Position::new(height - 1 - p.y, p.x, p.z));
+            (width, height) = (height, width);
+        }
+        if mirrored {
+            prefab.room = moved(&prefab.room, |p| Position::new(width - 1 - p.x, p.y, p.z));
+        }
+        prefab
+    }
+
+    /// The fragment's room with its origin moved to `origin`.
+    pub fn placed(&self, origin: Position) -> Room {
+        moved(&self.room, |p| {
+            Position::new(p.x + origin.x, p.y + origin.y, p.z + origin.z)
+        })
+    }
+}
+
+/// A copy of `room` with every position in it, including those in wake
+/// zones and triggers, passed through `f`. Connections are left as they are,
+/// since they lead to other rooms.
+fn moved(room: &Room, f: impl Fn(Position) -> Position) -> Room {
+    let mut moved = Room::new(f(room.initial_position));
+    moved.tiles = moved_map(&room.tiles, &f);
+    moved.enemies = (&room.enemies)
+        .into_iter()
+        .map(|(position, enemy)| {
+            let mut enemy = enemy.clone();
+            enemy.wake_zone = enemy.wake_zone.into_iter().map(&f).collect();
+            (f(*position), enemy)
+        })
+        .collect();
+    moved.healths = moved_map(&room.healths, &f);
+    moved.connections = room.connections.clone();
+    moved.items = moved_map(&room.items, &f);
+    moved.allies = moved_map(&room.allies, &f);
+    moved.triggers = room
+        .triggers
+        .iter()
+        .map(|trigger| Trigger {
+            condition: match &trigger.condition {
+                TriggerCondition::Enter(from, to) => TriggerCondition::Enter(f(*from), f(*to)),
+                condition => condition.clone(),
+            },
+            actions: trigger
+                .actions
+                .iter()
+                .map(|action| match action {
+                    TriggerAction::SpawnEnemy(position, enemy) => {
+                        let mut enemy = enemy.clone();
+                        enemy.wake_zone = enemy.wake_zone.into_iter().map(&f).collect();
+                        TriggerAction::SpawnEnemy(f(*position), enemy)
+                    }
+                    TriggerAction::SetTile(position, tile) => {
+                        TriggerAction::SetTile(f(*position), tile.clone())
+                    }
+                    action => action.clone(),
+                })
+                .collect(),
+        })
+        .collect();
+    moved
+}
+
+fn moved_map<A: Clone>(map: &PositionMap<A>, f: impl Fn(Position) -> Position) -> PositionMap<A> {
+    map.into_iter()
+        .map(|(position, a)| (f(*position), a.clone()))
+        .collect()
+}
+
+#[test]
+fn test_prefabs_keep_their_shape_when_oriented() {
+    use std::collections::BTreeSet;
+
+    let prefabs = library();
+    let ids: BTreeSet<ItemId> = prefabs
+        .iter()
+        .flat_map(|p| p.items.keys().copied())
+        .collect();
+    assert_eq!(
+        ids.len(),
+        prefabs.iter().map(|p| p.items.len()).sum::<usize>()
+    );
+
+    for prefab in &prefabs {
+        assert!(prefab.oriented(1, true).oriented(1, true).room == prefab.room);
+        let (width, height) = prefab.size();
+        for quarter_turns in 0..4 {
+            for mirrored in [false, true] {
+                let oriented = prefab.oriented(quarter_turns, mirrored);
+                let size = if quarter_turns % 2 == 0 {
+                    (width, height)
+                } else {
+                    (height, width)
+                };
+                assert_eq!(oriented.size(), size, "{}", prefab.name);
+                assert!(oriented
+                    .room
+                    .tiles
+                    .get(&oriented.doorway())
+                    .is_some_and(|tile| tile.passable()));
+                let (dx, dy) = oriented.outwards();
+                let outside = oriented.doorway();
+                let outside = Position::new(outside.x + dx, outside.y + dy, 0);
+                assert!(
+                    oriented.room.tiles.get(&outside).is_none(),
+                    "{}",
+                    prefab.name
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_prefab_doorways_are_checked() {
+    use crate::map::Tile;
+
+    let mut prefab = library().remove(0);
+    assert_eq!(prefab.check(), Ok(()));
+    let (width, height) = prefab.size();
+    let middle = Position::new(width / 2, height / 2, 0);
+    prefab.room.add_tile(middle, Tile::new(960, true));
+    prefab.room.initial_position = middle;
+    assert!(prefab
+        .check()
+        .is_err_and(|problem| problem.contains("not on the edge")));
+    prefab.room.initial_position = Position::new(width, 0, 0);
+    assert!(prefab
+        .check()
+        .is_err_and(|problem| problem.contains("not a passable tile")));
+}
@@ -1,28 +1,436 @@
+//! Seeded dungeon generation. Each floor is laid out by binary space
+//! partitioning: the floor is split in two again and again, a room is carved
+//! in every leaf and sibling rooms are joined by corridors. Stairs lead from
+//! each floor to the next, and the victory tile lies on the top floor, far
+//! from the stairs that reach it. Each floor also holds one of the
+//! hand-made prefabs, turned and mirrored at random and joined to the rest by
+//! a corridor. The same seed always makes the same map.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
 use crate::{
-    components::Position,
-    map::{Map, Room, VictoryCondition},
+    components::{EnemyType, Position},
+    map::{
+        Enemy, Health, Map, Room, Tile, TileKind, TriggerAction, VictoryCondition,
+        DEFAULT_INVENTORY_CAPACITY, DOWN_STAIRS_SPRITE, MAP_VERSION, UP_STAIRS_SPRITE,
+    },
+    maps::prefab::{self, Prefab},
 };
 
-// TODO Procedurally generate a map
-pub fn procedural() -> Map {
-    let mut room = Room::new(Position { x: 0, y: 0, z: 0 });
-    let mut victory_condition = VictoryCondition::Unwinnable;
+const WIDTH: i64 = 48;
+const HEIGHT: i64 = 32;
+const FLOORS: i64 = 3;
+/// Leaves are only split while both halves can be at least this big.
+const MIN_LEAF: i64 = 8;
+const MIN_ROOM: i64 = 4;
+/// Prefabs may lie this far outside the partitioned area, so that there is
+/// always somewhere to put them.
+const PREFAB_BORDER: i64 = 14;
+const PREFAB_TRIES: usize = 200;
+
+const FLOOR_SPRITE: u64 = 960;
+const WALL_SPRITE: u64 = 15 * 64 - 13;
+const ARRIVAL_SPRITE: u64 = 960 + 64 + 30;
+const HEALTH_SPRITE: u64 = 64 * 23 + 45;
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+}
+
+impl Rect {
+    fn center(&self) -> (i64, i64) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    fn random_cell(&self, rng: &mut StdRng) -> (i64, i64) {
+        (
+            rng.gen_range(self.x..self.x + self.width),
+            rng.gen_range(self.y..self.y + self.height),
+        )
+    }
+}
+
+/// The rooms of one floor and every cell carved out of it.
+struct Level {
+    rooms: Vec<Rect>,
+    carved: BTreeSet<(i64, i64)>,
+}
+
+impl Level {
+    fn generate(rng: &mut StdRng) -> Self {
+        let mut carved = BTreeSet::new();
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: WIDTH,
+            height: HEIGHT,
+        };
+        let rooms = partition(area, rng, &mut carved);
+        Level { rooms, carved }
+    }
+
+    /// Finds a spot for the prefab clear of everything carved and joins its
+    /// doorway to the level by a corridor. Returns the prefab placed on floor
+    /// `z`, or `None` if there was no spot.
+    fn stamp(&mut self, prefab: &Prefab, z: i64, rng: &mut StdRng) -> Option<Room> {
+        let (width, height) = prefab.size();
+        for _ in 0..PREFAB_TRIES {
+            let x0 = rng.gen_range(-PREFAB_BORDER..=WIDTH + PREFAB_BORDER - width);
+            let y0 = rng.gen_range(-PREFAB_BORDER..=HEIGHT + PREFAB_BORDER - height);
+            // Keep a cell clear all around, for the walls of what is carved
+            let clear = (x0 - 1..=x0 + width)
+                .all(|x| (y0 - 1..=y0 + height).all(|y| !self.carved.contains(&(x, y))));
+            if !clear {
+                continue;
+            }
+            let footprint: BTreeSet<(i64, i64)> = (x0..x0 + width)
+                .flat_map(|x| (y0..y0 + height).map(move |y| (x, y)))
+                .collect();
+            let doorway = prefab.doorway();
+            let (dx, dy) = prefab.outwards();
+            let outside = (x0 + doorway.x + dx, y0 + doorway.y + dy);
+            if let Some(corridor) = self.corridor_to_carved(outside, &footprint) {
+                self.carved.extend(corridor);
+                return Some(prefab.placed(Position::new(x0, y0, z)));
+            }
+        }
+        None
+    }
+
+    /// The cells of the shortest corridor from `from` to the nearest carved
+    /// cell that keeps out of `avoid`.
+    fn corridor_to_carved(
+        &self,
+        from: (i64, i64),
+        avoid: &BTreeSet<(i64, i64)>,
+    ) -> Option<Vec<(i64, i64)>> {
+        let mut came_from = BTreeMap::from([(from, from)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(cell) = queue.pop_front() {
+            if self.carved.contains(&cell) {
+                let mut corridor = Vec::new();
+                let mut at = cell;
+                while at != from {
+                    at = came_from[&at];
+                    corridor.push(at);
+                }
+                return Some(corridor);
+            }
+            let (x, y) = cell;
+            for next in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                let inside = (-PREFAB_BORDER..WIDTH + PREFAB_BORDER).contains(&next.0)
+                    && (-PREFAB_BORDER..HEIGHT + PREFAB_BORDER).contains(&next.1);
+                if inside && !avoid.contains(&next) && !came_from.contains_key(&next) {
+                    came_from.insert(next, cell);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// A random cell inside one of the rooms, other than those `taken`.
+    fn free_cell(&self, rng: &mut StdRng, taken: &BTreeSet<(i64, i64)>) -> (i64, i64) {
+        loop {
+            let cell = self.rooms[rng.gen_range(0..self.rooms.len())].random_cell(rng);
+            if !taken.contains(&cell) {
+                return cell;
+            }
+        }
+    }
+}
+
+/// Splits `area` in two until it is too small, carving a room in each leaf
+/// and a corridor between a room of each half. Returns the rooms.
+fn partition(area: Rect, rng: &mut StdRng, carved: &mut BTreeSet<(i64, i64)>) -> Vec<Rect> {
+    let across = area.width >= 2 * MIN_LEAF;
+    let along = area.height >= 2 * MIN_LEAF;
+    if !across && !along {
+        let width = rng.gen_range(MIN_ROOM..=area.width - 2);
+        let height = rng.gen_range(MIN_ROOM..=area.height - 2);
+        let room = Rect {
+            x: area.x + rng.gen_range(1..=area.width - width - 1),
+            y: area.y + rng.gen_range(1..=area.height - height - 1),
+            width,
+            height,
+        };
+        for x in room.x..room.x + room.width {
+            for y in room.y..room.y + room.height {
+                carved.insert((x, y));
+            }
+        }
+        return vec![room];
+    }
+
+    let split_x = if across && along {
+        area.width >= area.height
+    } else {
+        across
+    };
+    let (first, second) = if split_x {
+        let at = rng.gen_range(MIN_LEAF..=area.width - MIN_LEAF);
+        (
+            Rect { width: at, ..area },
+            Rect {
+                x: area.x + at,
+                width: area.width - at,
+                ..area
+            },
+        )
+    } else {
+        let at = rng.gen_range(MIN_LEAF..=area.height - MIN_LEAF);
+        (
+            Rect { height: at, ..area },
+            Rect {
+                y: area.y + at,
+                height: area.height - at,
+                ..area
+            },
+        )
+    };
+    let mut rooms = partition(first, rng, carved);
+    let others = partition(second, rng, carved);
+    let from = rooms[rng.gen_range(0..rooms.len())].center();
+    let to = others[rng.gen_range(0..others.len())].center();
+    carve_corridor(from, to, rng, carved);
+    rooms.extend(others);
+    rooms
+}
+
+/// Carves an L-shaped corridor, turning at one of its two possible corners.
+fn carve_corridor(
+    (x0, y0): (i64, i64),
+    (x1, y1): (i64, i64),
+    rng: &mut StdRng,
+    carved: &mut BTreeSet<(i64, i64)>,
+) {
+    let corner = if rng.gen() { (x1, y0) } else { (x0, y1) };
+    for (from, to) in [((x0, y0), corner), (corner, (x1, y1))] {
+        for x in from.0.min(to.0)..=from.0.max(to.0) {
+            for y in from.1.min(to.1)..=from.1.max(to.1) {
+                carved.insert((x, y));
+            }
+        }
+    }
+}
 
+pub fn procedural(seed: u64) -> Map {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut levels: Vec<Level> = (0..FLOORS).map(|_| Level::generate(&mut rng)).collect();
+
+    let start = levels[0].rooms[0].center();
+    // Cells of each floor that already hold the start, stairs or the arrival
+    let mut taken: Vec<BTreeSet<(i64, i64)>> = vec![BTreeSet::new(); FLOORS as usize];
+    taken[0].insert(start);
+
+    // `stairs[z]` leads up from floor z to floor z + 1, where a corridor
+    // joins it to the nearest room
+    let mut stairs = Vec::new();
+    for z in 0..(FLOORS - 1) as usize {
+        let cell = levels[z].free_cell(&mut rng, &taken[z]);
+        let above = &mut levels[z + 1];
+        let nearest = above
+            .rooms
+            .iter()
+            .map(Rect::center)
+            .min_by_key(|(x, y)| (x - cell.0).abs() + (y - cell.1).abs())
+            .expect("every floor has a room");
+        carve_corridor(cell, nearest, &mut rng, &mut above.carved);
+        taken[z].insert(cell);
+        taken[z + 1].insert(cell);
+        stairs.push(cell);
+    }
+
+    let top = (FLOORS - 1) as usize;
+    let arrived_at = stairs.last().copied().unwrap_or(start);
+    let arrival = levels[top]
+        .rooms
+        .iter()
+        .map(Rect::center)
+        .filter(|cell| !taken[top].contains(cell))
+        .max_by_key(|(x, y)| (x - arrived_at.0).abs() + (y - arrived_at.1).abs())
+        .unwrap_or_else(|| levels[top].free_cell(&mut rng, &taken[top]));
+    taken[top].insert(arrival);
+
+    let mut prefabs = prefab::library();
+    prefabs.shuffle(&mut rng);
+    let mut items = BTreeMap::new();
+    let mut stamped = Vec::new();
+    for (z, (level, prefab)) in levels.iter_mut().zip(&prefabs).enumerate() {
+        let prefab = prefab.oriented(rng.gen_range(0..4), rng.gen());
+        if let Some(placed) = level.stamp(&prefab, z as i64, &mut rng) {
+            items.extend(prefab.items);
+            stamped.push(placed);
+        }
+    }
+
+    let mut room = Room::new(Position::new(start.0, start.1, 0));
+    for (z, level) in levels.iter().enumerate() {
+        let at = |(x, y): (i64, i64)| Position::new(x, y, z as i64);
+        for cell in &level.carved {
+            let tile = if stairs.get(z) == Some(cell) {
+                Tile {
+                    sprite_index: UP_STAIRS_SPRITE,
+                    kind: TileKind::UpStairs,
+                    lock: None,
+                }
+            } else if z > 0 && stairs[z - 1] == *cell {
+                Tile {
+                    sprite_index: DOWN_STAIRS_SPRITE,
+                    kind: TileKind::DownStairs,
+                    lock: None,
+                }
+            } else if z == top && *cell == arrival {
+                Tile::new(ARRIVAL_SPRITE, true)
+            } else {
+                Tile::new(FLOOR_SPRITE, true)
+            };
+            room.add_tile(at(*cell), tile);
+        }
+        for (x, y) in &level.carved {
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let wall = (x + dx, y + dy);
+                    if !level.carved.contains(&wall) {
+                        room.add_tile(at(wall), Tile::new(WALL_SPRITE, false));
+                    }
+                }
+            }
+        }
+
+        let taken = &mut taken[z];
+        for (i, rect) in level.rooms.iter().enumerate() {
+            if z == 0 && i == 0 {
+                continue;
+            }
+            // Deeper floors hold more, stronger enemies that wake from further away
+            for _ in 0..rng.gen_range(0..=1 + z) {
+                let cell = rect.random_cell(&mut rng);
+                if !taken.insert(cell) {
+                    continue;
+                }
+                let enemy_type =
+                    [EnemyType::Skeleton, EnemyType::Orc, EnemyType::Ghost][rng.gen_range(0..3)];
+                let (health, strength) = enemy_type.get_stats(z as i64);
+                let position = at(cell);
+                room.add_enemy(
+                    position,
+                    Enemy {
+                        enemy_type: Some(enemy_type),
+                        ..Enemy::new(
+                            enemy_type.sprite_index() as u64,
+                            health as u64,
+                            strength as u64,
+                            Enemy::circular_wake_zone(position, 3 + z as i64),
+                        )
+                    },
+                );
+            }
+            if rng.gen_bool(0.3) {
+                let cell = rect.random_cell(&mut rng);
+                if taken.insert(cell) {
+                    room.add_health(
+                        at(cell),
+                        Health {
+                            sprite_index: HEALTH_SPRITE,
+                            health: 5 + 5 * z as u64,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    // Prefabs go on top, replacing the walls around their doorways
+    for placed in stamped {
+        for (position, tile) in placed.tiles {
+            room.add_tile(position, tile);
+        }
+        for (position, enemy) in placed.enemies {
+            room.add_enemy(position, enemy);
+        }
+        for (position, health) in placed.healths {
+            room.add_health(position, health);
+        }
+        for (position, item) in placed.items {
+            room.add_item(position, item);
+        }
+        for (position, ally) in placed.allies {
+            room.add_ally(position, ally);
+        }
+        room.triggers.extend(placed.triggers);
+    }
+
+    let player_strength = compute_reasonable_player_strength(&room);
     Map {
-        player_health: compute_reasonable_player_health(&room),
-        player_strength: compute_reasonable_player_strength(&room),
-        room,
+        version: MAP_VERSION,
+        player_health: compute_reasonable_player_health(&room, player_strength),
+        player_strength,
+        rooms: vec![room],
+        initial_room: 0,
         player_sprite: 32 * 64 + 45,
-        victory_condition,
+        items,
+        inventory_capacity: DEFAULT_INVENTORY_CAPACITY,
+        defeat_condition: Default::default(),
+        victory_condition: VictoryCondition::Arrival(
+            0,
+            Position::new(arrival.0, arrival.1, top as i64),
+        ),
     }
 }
 
-// TODO
-fn compute_reasonable_player_strength(room: &Room) -> u64 {
-    10
+/// The enemies in the room, and those its triggers spawn.
+fn all_enemies(room: &Room) -> impl Iterator<Item = &Enemy> {
+    let spawned = room
+        .triggers
+        .iter()
+        .flat_map(|trigger| &trigger.actions)
+        .filter_map(|action| match action {
+            TriggerAction::SpawnEnemy(_, enemy) => Some(enemy),
+            _ => None,
+        });
+    (&room.enemies)
+        .into_iter()
+        .map(|(_, enemy)| enemy)
+        .chain(spawned)
 }
 
-// TODO
-fn compute_reasonable_player_health(room: &Room) -> u64 {
-    10
+/// Enough to kill the toughest enemy in three blows.
+pub(super) fn compute_reasonable_player_strength(room: &Room) -> u64 {
+    all_enemies(room)
+        .filter_map(|enemy| enemy.health)
+        .max()
+        .map_or(1, |health| health.div_ceil(3).max(1))
+}
+
+/// Enough to fight every enemy in turn, each hitting back until it dies.
+pub(super) fn compute_reasonable_player_health(room: &Room, player_strength: u64) -> u64 {
+    let damage: u64 = all_enemies(room)
+        .map(|enemy| {
+            let blows = enemy.health.unwrap_or(0).div_ceil(player_strength);
+            blows * enemy.strength.unwrap_or(0)
+        })
+        .sum();
+    10 + damage
+}
+
+#[test]
+fn test_procedural_is_seeded_and_valid() {
+    for seed in 0..20 {
+        let map = procedural(seed);
+        assert!(map == procedural(seed), "seed {} is not reproducible", seed);
+        assert_eq!(map.validate(), vec![], "seed {}", seed);
+        assert!(!map.starting_room().enemies.is_empty(), "seed {}", seed);
+        // Every prefab found a spot and brought its items along
+        assert_eq!(map.items.len(), 3, "seed {}", seed);
+    }
+    assert!(procedural(1) != procedural(2));
 }
@@ -0,0 +1,1039 @@
+//! Plain-text map format: one character grid per floor of each room, for
+//! authoring maps by hand and reviewing them in diffs.
+//!
+//! ```text
+//! version 4
+//! player_health 20
+//! player_strength 3
+//! player_sprite 71
+//! initial_room 0
+//! inventory_capacity 10
+//! victory {"Or":["Extermination",{"Arrival":[0,[3,1,0]]}]}
+//! defeat {"TimeLimit":1800}
+//! item 1 2936 Brass key
+//! item 2 2900 Short sword
+//! weapon 2 3 1
+//!
+//! legend
+//! # tile 947 wall
+//! . tile 960 floor
+//! @ tile 960 floor; start
+//! E tile 960 floor; enemy {"enemy_type":"Orc"}
+//! h tile 960 floor; health 1517 10
+//! A tile 960 floor; ally 26 20
+//! X tile 960 floor; arrival
+//! end
+//!
+//! room 0
+//! wake 2 1 0 circle 3
+//! connection 3 1 0 -> 1 0 0 0
+//! trigger {"condition":{"Delay":300},"actions":[{"Message":"Hurry!"}]}
+//! floor 0 0 0
+//! ######
+//! #@EhX#
+//! ######
+//! end
+//! ```
+//!
+//! Grid rows run from the top (highest `y`) down, `floor z x y` names the
+//! floor and the coordinates of its bottom-left cell, and a space is an empty
+//! cell. A legend entry combines a tile, whose kind is `floor`, `wall`, `up`,
+//! `down`, `stairs`, `lava`, `spikes`, `water` or `ice`, with whatever stands
+//! on it. `item <id> <sprite> <name>` lines describe the map's items, which
+//! legend entries place with `item <id>`, and a tile ending in `locked <id>`
+//! is a door opened by carrying that item. `weapon <id> <damage> <reach>` and
+//! `armor <id> <defense>` lines make an item equipment, and a later
+//! `speed <id> <change>` line makes it change its wearer's speed by `change`,
+//! which may be negative. Cells marked `arrival` only build the victory
+//! condition when there is no `victory` line; the exporter marks every
+//! `Arrival` target for readability. Without a `defeat` line, only the
+//! player dying loses.
+//! A wake zone is `circle r` around the enemy, `rect x0 y0 x1 y1` on the
+//! enemy's floor, or a JSON list of positions. Each `trigger` line holds one
+//! of the room's triggers as JSON.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write;
+
+use serde_json::Value;
+
+use crate::components::Position;
+use crate::map::{
+    Ally, Connection, DefeatCondition, Enemy, Equipment, Health, Item, ItemId, Map, Room, Tile,
+    TileKind, VictoryCondition, DEFAULT_INVENTORY_CAPACITY, MAP_VERSION,
+};
+
+#[derive(Debug)]
+pub struct TextMapError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TextMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for TextMapError {}
+
+/// Everything at one position of a room. Enemies are kept without their wake
+/// zones, which are listed separately.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Cell {
+    tile: Option<Tile>,
+    enemy: Option<Enemy>,
+    health: Option<Health>,
+    item: Option<ItemId>,
+    ally: Option<Ally>,
+    start: bool,
+    arrival: bool,
+}
+
+fn room_cells(room: &Room, arrivals: &BTreeSet<Position>) -> BTreeMap<Position, Cell> {
+    let mut cells: BTreeMap<Position, Cell> = BTreeMap::new();
+    for (position, tile) in &room.tiles {
+        cells.entry(*position).or_default().tile = Some(tile.clone());
+    }
+    for (position, enemy) in &room.enemies {
+        cells.entry(*position).or_default().enemy = Some(Enemy {
+            wake_zone: BTreeSet::new(),
+            ..enemy.clone()
+        });
+    }
+    for (position, health) in &room.healths {
+        cells.entry(*position).or_default().health = Some(health.clone());
+    }
+    for (position, item) in &room.items {
+        cells.entry(*position).or_default().item = Some(*item);
+    }
+    for (position, ally) in &room.allies {
+        cells.entry(*position).or_default().ally = Some(ally.clone());
+    }
+    cells.entry(room.initial_position).or_default().start = true;
+    for position in arrivals {
+        cells.entry(*position).or_default().arrival = true;
+    }
+    cells
+}
+
+fn preferred_characters(cell: &Cell) -> &'static [char] {
+    if cell.start {
+        &['@']
+    } else if cell.arrival {
+        &['X']
+    } else if cell.enemy.is_some() {
+        &['E', 'e']
+    } else if cell.ally.is_some() {
+        &['A', 'a']
+    } else if cell.health.is_some() {
+        &['h', '+']
+    } else if cell.item.is_some() {
+        &['i', 'k']
+    } else if cell.tile.as_ref().is_some_and(|tile| tile.lock.is_some()) {
+        &['D', '|']
+    } else {
+        match cell.tile.as_ref().map(|tile| tile.kind) {
+            Some(TileKind::Floor) => &['.', ','],
+            Some(TileKind::Wall) => &['#', '%'],
+            Some(TileKind::UpStairs) => &['<'],
+            Some(TileKind::DownStairs) => &['>'],
+            Some(TileKind::Stairs) => &['='],
+            Some(TileKind::Lava) => &['^'],
+            Some(TileKind::Spikes) => &['*'],
+            Some(TileKind::Water) => &['~'],
+            Some(TileKind::Ice) => &['_'],
+            None => &[],
+        }
+    }
+}
+
+const KIND_NAMES: [(TileKind, &str); 9] = [
+    (TileKind::Floor, "floor"),
+    (TileKind::Wall, "wall"),
+    (TileKind::UpStairs, "up"),
+    (TileKind::DownStairs, "down"),
+    (TileKind::Stairs, "stairs"),
+    (TileKind::Lava, "lava"),
+    (TileKind::Spikes, "spikes"),
+    (TileKind::Water, "water"),
+    (TileKind::Ice, "ice"),
+];
+
+fn kind_name(kind: TileKind) -> &'static str {
+    KIND_NAMES
+        .iter()
+        .find(|(k, _)| *k == kind)
+        .map(|(_, name)| *name)
+        .expect("every kind is named")
+}
+
+/// Letters are left out so that no row of a floor can read `end`.
+fn fallback_characters() -> impl Iterator<Item = char> {
+    (33u32..127)
+        .chain(0xA1..0x3000)
+        .filter_map(char::from_u32)
+        .filter(|c| {
+            !c.is_whitespace() && !c.is_control() && !c.is_ascii_alphabetic() && *c != '\u{AD}'
+        })
+}
+
+fn enemy_json(enemy: &Enemy) -> String {
+    let mut value = serde_json::to_value(enemy).expect("enemies serialize");
+    if let Value::Object(fields) = &mut value {
+        fields.remove("wake_zone");
+    }
+    value.to_string()
+}
+
+fn describe(cell: &Cell) -> String {
+    let mut entries = Vec::new();
+    if let Some(tile) = &cell.tile {
+        let mut entry = format!("tile {} {}", tile.sprite_index, kind_name(tile.kind));
+        if let Some(ItemId(item)) = tile.lock {
+            write!(entry, " locked {}", item).unwrap();
+        }
+        entries.push(entry);
+    }
+    if let Some(enemy) = &cell.enemy {
+        entries.push(format!("enemy {}", enemy_json(enemy)));
+    }
+    if let Some(health) = &cell.health {
+        entries.push(format!("health {} {}", health.sprite_index, health.health));
+    }
+    if let Some(ItemId(item)) = cell.item {
+        entries.push(format!("item {}", item));
+    }
+    if let Some(ally) = &cell.ally {
+        entries.push(format!("ally {} {}", ally.sprite_index, ally.health));
+    }
+    if cell.start {
+        entries.push("start".into());
+    }
+    if cell.arrival {
+        entries.push("arrival".into());
+    }
+    entries.join("; ")
+}
+
+fn rectangle(z: i64, x0: i64, y0: i64, x1: i64, y1: i64) -> BTreeSet<Position> {
+    (x0..=x1)
+        .flat_map(|x| (y0..=y1).map(move |y| Position::new(x, y, z)))
+        .collect()
+}
+
+fn describe_wake_zone(center: Position, wake_zone: &BTreeSet<Position>) -> String {
+    let radius = wake_zone
+        .iter()
+        .map(|position| {
+            (((position.x - center.x).pow(2) + (position.y - center.y).pow(2)) as f64).sqrt()
+        })
+        .fold(0., f64::max)
+        .ceil() as i64;
+    if *wake_zone == Enemy::circular_wake_zone(center, radius) {
+        return format!("circle {}", radius);
+    }
+    let x0 = wake_zone.iter().map(|p| p.x).min().unwrap_or(0);
+    let x1 = wake_zone.iter().map(|p| p.x).max().unwrap_or(0);
+    let y0 = wake_zone.iter().map(|p| p.y).min().unwrap_or(0);
+    let y1 = wake_zone.iter().map(|p| p.y).max().unwrap_or(0);
+    if *wake_zone == rectangle(center.z, x0, y0, x1, y1) {
+        format!("rect {} {} {} {}", x0, y0, x1, y1)
+    } else {
+        serde_json::to_string(wake_zone).expect("positions serialize")
+    }
+}
+
+pub fn to_text(map: &Map) -> String {
+    let arrivals = map.victory_condition.arrivals();
+    let rooms: Vec<BTreeMap<Position, Cell>> = map
+        .rooms
+        .iter()
+        .enumerate()
+        .map(|(i, room)| {
+            let arrivals: BTreeSet<Position> = arrivals
+                .iter()
+                .filter(|(room, _)| *room == i)
+                .map(|(_, position)| *position)
+                .collect();
+            room_cells(room, &arrivals)
+        })
+        .collect();
+
+    let mut legend: BTreeMap<&Cell, char> = BTreeMap::new();
+    let mut used: BTreeSet<char> = BTreeSet::new();
+    for cell in rooms.iter().flat_map(|cells| cells.values()) {
+        if legend.contains_key(cell) {
+            continue;
+        }
+        let character = preferred_characters(cell)
+            .iter()
+            .copied()
+            .chain(fallback_characters())
+            .find(|c| !used.contains(c))
+            .expect("ran out of legend characters");
+        used.insert(character);
+        legend.insert(cell, character);
+    }
+
+    let mut text = String::new();
+    writeln!(text, "version {}", map.version).unwrap();
+    writeln!(text, "player_health {}", map.player_health).unwrap();
+    writeln!(text, "player_strength {}", map.player_strength).unwrap();
+    writeln!(text, "player_sprite {}", map.player_sprite).unwrap();
+    writeln!(text, "initial_room {}", map.initial_room).unwrap();
+    writeln!(text, "inventory_capacity {}", map.inventory_capacity).unwrap();
+    writeln!(
+        text,
+        "victory {}",
+        serde_json::to_string(&map.victory_condition).expect("victory conditions serialize")
+    )
+    .unwrap();
+    if map.defeat_condition != DefeatCondition::Never {
+        writeln!(
+            text,
+            "defeat {}",
+            serde_json::to_string(&map.defeat_condition).expect("defeat conditions serialize")
+        )
+        .unwrap();
+    }
+    for (ItemId(id), item) in &map.items {
+        writeln!(text, "item {} {} {}", id, item.sprite_index, item.name).unwrap();
+        match item.equipment {
+            Some(Equipment::Weapon { damage, reach, .. }) => {
+                writeln!(text, "weapon {} {} {}", id, damage, reach).unwrap()
+            }
+            Some(Equipment::Armor { defense, .. }) => {
+                writeln!(text, "armor {} {}", id, defense).unwrap()
+            }
+            None => {}
+        }
+        match item.equipment.map(|equipment| equipment.speed()) {
+            Some(speed) if speed != 0 => writeln!(text, "speed {} {}", id, speed).unwrap(),
+            _ => {}
+        }
+    }
+
+    let mut entries: Vec<(char, &Cell)> = legend.iter().map(|(cell, c)| (*c, *cell)).collect();
+    entries.sort_by_key(|(c, _)| *c);
+    writeln!(text, "\nlegend").unwrap();
+    for (character, cell) in entries {
+        writeln!(text, "{} {}", character, describe(cell)).unwrap();
+    }
+    writeln!(text, "end").unwrap();
+
+    for (i, (room, cells)) in map.rooms.iter().zip(rooms.iter()).enumerate() {
+        writeln!(text, "\nroom {}", i).unwrap();
+        for (position, enemy) in &room.enemies {
+            if !enemy.wake_zone.is_empty() {
+                writeln!(
+                    text,
+                    "wake {} {} {} {}",
+                    position.x,
+                    position.y,
+                    position.z,
+                    describe_wake_zone(*position, &enemy.wake_zone)
+                )
+                .unwrap();
+            }
+        }
+        for (position, connection) in &room.connections {
+            writeln!(
+                text,
+                "connection {} {} {} -> {} {} {} {}",
+                position.x,
+                position.y,
+                position.z,
+                connection.room,
+                connection.position.x,
+                connection.position.y,
+                connection.position.z
+            )
+            .unwrap();
+        }
+        for trigger in &room.triggers {
+            let json = serde_json::to_string(trigger).expect("triggers serialize");
+            writeln!(text, "trigger {}", json).unwrap();
+        }
+
+        let mut floors: BTreeMap<i64, Vec<(&Position, &Cell)>> = BTreeMap::new();
+        for (position, cell) in cells {
+            floors.entry(position.z).or_default().push((position, cell));
+        }
+        for (z, floor) in floors {
+            let x0 = floor
+                .iter()
+                .map(|(position, _)| position.x)
+                .min()
+                .unwrap_or(0);
+            let x1 = floor
+                .iter()
+                .map(|(position, _)| position.x)
+                .max()
+                .unwrap_or(0);
+            let y0 = floor
+                .iter()
+                .map(|(position, _)| position.y)
+                .min()
+                .unwrap_or(0);
+            let y1 = floor
+                .iter()
+                .map(|(position, _)| position.y)
+                .max()
+                .unwrap_or(0);
+            let width = (x1 - x0 + 1) as usize;
+            let mut rows = vec![vec![' '; width]; (y1 - y0 + 1) as usize];
+            for (position, cell) in floor {
+                rows[(y1 - position.y) as usize][(position.x - x0) as usize] = legend[cell];
+            }
+            writeln!(text, "floor {} {} {}", z, x0, y0).unwrap();
+            for row in rows {
+                writeln!(text, "{}", row.iter().collect::<String>().trim_end()).unwrap();
+            }
+            writeln!(text, "end").unwrap();
+        }
+    }
+    text
+}
+
+struct Parser<'a> {
+    lines: std::iter::Peekable<std::iter::Enumerate<std::str::Lines<'a>>>,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, TextMapError> {
+        Err(TextMapError {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    /// The next line that is not blank, split into its keyword and the rest.
+    fn next_directive(&mut self) -> Option<(&'a str, &'a str)> {
+        for (i, line) in self.lines.by_ref() {
+            self.line = i + 1;
+            let line = line.trim();
+            if !line.is_empty() {
+                let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+                return Some((keyword, rest.trim()));
+            }
+        }
+        None
+    }
+
+    fn next_raw_line(&mut self) -> Option<&'a str> {
+        self.lines.next().map(|(i, line)| {
+            self.line = i + 1;
+            line
+        })
+    }
+
+    fn number<T: std::str::FromStr>(&self, word: &str) -> Result<T, TextMapError> {
+        word.parse()
+            .or_else(|_| self.error(format!("expected a number, found `{}`", word)))
+    }
+
+    fn numbers<T: std::str::FromStr>(
+        &self,
+        rest: &str,
+        count: usize,
+    ) -> Result<Vec<T>, TextMapError> {
+        let numbers = rest
+            .split_whitespace()
+            .map(|word| self.number(word))
+            .collect::<Result<Vec<T>, _>>()?;
+        if numbers.len() != count {
+            return self.error(format!("expected {} numbers, found `{}`", count, rest));
+        }
+        Ok(numbers)
+    }
+
+    fn position(&self, rest: &str) -> Result<Position, TextMapError> {
+        let xyz: Vec<i64> = self.numbers(rest, 3)?;
+        Ok(Position::new(xyz[0], xyz[1], xyz[2]))
+    }
+
+    fn json<T: serde::de::DeserializeOwned>(&self, rest: &str) -> Result<T, TextMapError> {
+        serde_json::from_str(rest).or_else(|error| self.error(error.to_string()))
+    }
+
+    fn legend_entry(&self, rest: &str) -> Result<Cell, TextMapError> {
+        let mut cell = Cell::default();
+        for entry in split_entries(rest)
+            .into_iter()
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (keyword, arguments) = entry.split_once(char::is_whitespace).unwrap_or((entry, ""));
+            let arguments = arguments.trim();
+            match keyword {
+                "tile" => {
+                    let words: Vec<&str> = arguments.split_whitespace().collect();
+                    let Some(kind) = words.get(1).and_then(|word| {
+                        KIND_NAMES
+                            .iter()
+                            .find(|(_, name)| name == word)
+                            .map(|(kind, _)| *kind)
+                    }) else {
+                        return self.error("expected `tile <sprite> <kind> [locked <item>]`");
+                    };
+                    let lock = match words[2..] {
+                        [] => None,
+                        ["locked", item] => Some(ItemId(self.number(item)?)),
+                        _ => return self.error("expected `tile <sprite> <kind> [locked <item>]`"),
+                    };
+                    cell.tile = Some(Tile {
+                        sprite_index: self.number(words[0])?,
+                        kind,
+                        lock,
+                    });
+                }
+                "enemy" => {
+                    let mut value: Value = self.json(arguments)?;
+                    if let Value::Object(fields) = &mut value {
+                        fields.insert("wake_zone".into(), Value::Array(Vec::new()));
+                    }
+                    cell.enemy = Some(
+                        serde_json::from_value(value)
+                            .or_else(|error| self.error(error.to_string()))?,
+                    );
+                }
+                "health" => {
+                    let numbers: Vec<u64> = self.numbers(arguments, 2)?;
+                    cell.health = Some(Health {
+                        sprite_index: numbers[0],
+                        health: numbers[1],
+                    });
+                }
+                "item" => cell.item = Some(ItemId(self.number(arguments)?)),
+                "ally" => {
+                    let numbers: Vec<u64> = self.numbers(arguments, 2)?;
+                    cell.ally = Some(Ally {
+                        sprite_index: numbers[0],
+                        health: numbers[1],
+                    });
+                }
+                "start" => cell.start = true,
+                "arrival" => cell.arrival = true,
+                _ => return self.error(format!("unknown legend entry `{}`", keyword)),
+            }
+        }
+        Ok(cell)
+    }
+}
+
+/// Splits a legend line at the `;` between entries, but not at those inside
+/// the JSON strings of an enemy, such as its group name.
+fn split_entries(line: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, character) in line.char_indices() {
+        match character {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => {
+                entries.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(&line[start..]);
+    entries
+}
+
+pub fn from_text(text: &str) -> Result<Map, TextMapError> {
+    let mut parser = Parser {
+        lines: text.lines().enumerate().peekable(),
+        line: 0,
+    };
+    let mut header: BTreeMap<&str, (usize, &str)> = BTreeMap::new();
+    let mut legend: BTreeMap<char, Cell> = BTreeMap::new();
+    let mut rooms: Vec<Room> = Vec::new();
+    let mut arrivals: Vec<(usize, Position)> = Vec::new();
+    let mut items: BTreeMap<ItemId, Item> = BTreeMap::new();
+
+    // State of the room being read
+    let mut start: Option<Position> = None;
+    let mut wake_zones: BTreeMap<Position, (usize, BTreeSet<Position>)> = BTreeMap::new();
+
+    fn finish_room(
+        rooms: &mut [Room],
+        start: &mut Option<Position>,
+        wake_zones: &mut BTreeMap<Position, (usize, BTreeSet<Position>)>,
+        line: usize,
+    ) -> Result<(), TextMapError> {
+        let index = rooms.len().wrapping_sub(1);
+        let Some(room) = rooms.last_mut() else {
+            return Ok(());
+        };
+        room.initial_position = start.take().ok_or_else(|| TextMapError {
+            line,
+            message: format!("room {} has no start", index),
+        })?;
+        for (position, (line, wake_zone)) in std::mem::take(wake_zones) {
+            match room.enemies.get_mut(&position) {
+                Some(enemy) => enemy.wake_zone = wake_zone,
+                None => {
+                    return Err(TextMapError {
+                        line,
+                        message: "wake zone for a position with no enemy".into(),
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    while let Some((keyword, rest)) = parser.next_directive() {
+        match keyword {
+            "version" | "player_health" | "player_strength" | "player_sprite" | "initial_room"
+            | "inventory_capacity" | "victory" | "defeat" => {
+                header.insert(keyword, (parser.line, rest));
+            }
+            "item" => {
+                let words: Vec<&str> = rest.splitn(3, char::is_whitespace).collect();
+                if words.len() != 3 {
+                    return parser.error("expected `item <id> <sprite> <name>`");
+                }
+                let item = Item {
+                    name: words[2].trim().to_string(),
+                    sprite_index: parser.number(words[1])?,
+                    equipment: None,
+                };
+                if items
+                    .insert(ItemId(parser.number(words[0])?), item)
+                    .is_some()
+                {
+                    return parser.error(format!("item {} is defined twice", words[0]));
+                }
+            }
+            "weapon" | "armor" => {
+                let (id, equipment) = if keyword == "weapon" {
+                    let numbers: Vec<u64> = parser.numbers(rest, 3)?;
+                    (
+                        numbers[0],
+                        Equipment::Weapon {
+                            damage: numbers[1],
+                            reach: numbers[2],
+                            speed: 0,
+                        },
+                    )
+                } else {
+                    let numbers: Vec<u64> = parser.numbers(rest, 2)?;
+                    (
+                        numbers[0],
+                        Equipment::Armor {
+                            defense: numbers[1],
+                            speed: 0,
+                        },
+                    )
+                };
+                match items.get_mut(&ItemId(id)) {
+                    Some(item) if item.equipment.is_none() => item.equipment = Some(equipment),
+                    Some(_) => return parser.error(format!("item {} is equipped twice", id)),
+                    None => return parser.error(format!("item {} is not defined", id)),
+                }
+            }
+            "speed" => {
+                let numbers: Vec<i64> = parser.numbers(rest, 2)?;
+                let id = ItemId(numbers[0] as u64);
+                match items.get_mut(&id).map(|item| item.equipment.as_mut()) {
+                    Some(Some(equipment)) => *equipment.speed_mut() = numbers[1],
+                    Some(None) => {
+                        return parser.error(format!("item {} is not equipment", numbers[0]))
+                    }
+                    None => return parser.error(format!("item {} is not defined", numbers[0])),
+                }
+            }
+            "legend" => loop {
+                let Some(line) = parser.next_raw_line() else {
+                    return parser.error("legend is missing its `end`");
+                };
+                let line = line.trim();
+                if line == "end" {
+                    break;
+                }
+                let mut characters = line.chars();
+                let Some(character) = characters.next() else {
+                    continue;
+                };
+                let cell = parser.legend_entry(characters.as_str())?;
+                if legend.insert(character, cell).is_some() {
+                    return parser.error(format!("`{}` is defined twice", character));
+                }
+            },
+            "room" => {
+                finish_room(&mut rooms, &mut start, &mut wake_zones, parser.line)?;
+                let index: usize = parser.number(rest)?;
+                if index != rooms.len() {
+                    return parser.error(format!("expected room {}", rooms.len()));
+                }
+                rooms.push(Room::new(Position::new(0, 0, 0)));
+            }
+            "wake" | "connection" | "trigger" | "floor" if rooms.is_empty() => {
+                return parser.error(format!("`{}` outside of a room", keyword));
+            }
+            "wake" => {
+                let words: Vec<&str> = rest.splitn(4, char::is_whitespace).collect();
+                if words.len() != 4 {
+                    return parser.error("expected `wake x y z <zone>`");
+                }
+                let center = parser.position(&words[..3].join(" "))?;
+                let zone = words[3].trim();
+                let wake_zone = if let Some(radius) = zone.strip_prefix("circle") {
+                    Enemy::circular_wake_zone(center, parser.number(radius.trim())?)
+                } else if let Some(corners) = zone.strip_prefix("rect") {
+                    let corners: Vec<i64> = parser.numbers(corners, 4)?;
+                    rectangle(center.z, corners[0], corners[1], corners[2], corners[3])
+                } else {
+                    parser.json(zone)?
+                };
+                wake_zones.insert(center, (parser.line, wake_zone));
+            }
+            "connection" => {
+                let Some((from, to)) = rest.split_once("->") else {
+                    return parser.error("expected `connection x y z -> room x y z`");
+                };
+                let to: Vec<i64> = parser.numbers(to, 4)?;
+                let room = rooms.last_mut().expect("checked above");
+                room.add_connection(
+                    parser.position(from)?,
+                    Connection {
+                        room: to[0] as usize,
+                        position: Position::new(to[1], to[2], to[3]),
+                    },
+                );
+            }
+            "trigger" => {
+                let trigger = parser.json(rest)?;
+                let room = rooms.last_mut().expect("checked above");
+                room.triggers.push(trigger);
+            }
+            "floor" => {
+                let origin: Vec<i64> = parser.numbers(rest, 3)?;
+                let (z, x0, y0) = (origin[0], origin[1], origin[2]);
+                let mut rows = Vec::new();
+                loop {
+                    let Some(line) = parser.next_raw_line() else {
+                        return parser.error(format!("floor {} is missing its `end`", z));
+                    };
+                    if line.trim() == "end" {
+                        break;
+                    }
+                    rows.push((parser.line, line));
+                }
+                let height = rows.len() as i64;
+                for (i, (line, row)) in rows.into_iter().enumerate() {
+                    parser.line = line;
+                    for (j, character) in row.chars().enumerate() {
+                        if character == ' ' {
+                            continue;
+                        }
+                        let Some(cell) = legend.get(&character) else {
+                            return parser.error(format!("`{}` is not in the legend", character));
+                        };
+                        let position = Position::new(x0 + j as i64, y0 + height - 1 - i as i64, z);
+                        let room = rooms.last_mut().expect("checked above");
+                        if let Some(tile) = &cell.tile {
+                            room.add_tile(position, tile.clone());
+                        }
+                        if let Some(enemy) = &cell.enemy {
+                            room.add_enemy(position, enemy.clone());
+                        }
+                        if let Some(health) = &cell.health {
+                            room.add_health(position, health.clone());
+                        }
+                        if let Some(item) = cell.item {
+                            room.add_item(position, item);
+                        }
+                        if let Some(ally) = &cell.ally {
+                            room.add_ally(position, ally.clone());
+                        }
+                        if cell.start {
+                            if start.is_some() {
+                                return parser
+                                    .error(format!("room {} has two starts", rooms.len() - 1));
+                            }
+                            start = Some(position);
+                        }
+                        if cell.arrival {
+                            arrivals.push((rooms.len() - 1, position));
+                        }
+                    }
+                }
+            }
+            _ => return parser.error(format!("unknown directive `{}`", keyword)),
+        }
+    }
+    finish_room(&mut rooms, &mut start, &mut wake_zones, parser.line)?;
+
+    let header_value = |parser: &mut Parser, key: &str| -> Result<Option<u64>, TextMapError> {
+        match header.get(key) {
+            Some((line, value)) => {
+                parser.line = *line;
+                parser.number(value).map(Some)
+            }
+            None => Ok(None),
+        }
+    };
+    let required = |parser: &mut Parser, key: &str| -> Result<u64, TextMapError> {
+        header_value(parser, key)?.ok_or_else(|| TextMapError {
+            line: 0,
+            message: format!("missing `{}`", key),
+        })
+    };
+
+    let version = header_value(&mut parser, "version")?.unwrap_or(MAP_VERSION);
+    if version != MAP_VERSION {
+        return parser.error(format!(
+            "text maps must be version {}, found {}",
+            MAP_VERSION, version
+        ));
+    }
+    let victory_condition = match header.get("victory") {
+        Some((line, value)) => {
+            parser.line = *line;
+            parser.json(value)?
+        }
+        None => {
+            arrivals.sort();
+            arrivals.dedup();
+            if arrivals.is_empty() {
+                return parser.error("no `victory` line and no arrival cells");
+            }
+            VictoryCondition::arrival_at_any(&arrivals)
+        }
+    };
+    let defeat_condition = match header.get("defeat") {
+        Some((line, value)) => {
+            parser.line = *line;
+            parser.json(value)?
+        }
+        None => DefeatCondition::Never,
+    };
+    if rooms.is_empty() {
+        return parser.error("the map has no rooms");
+    }
+
+    Ok(Map {
+        version,
+        player_health: required(&mut parser, "player_health")?,
+        player_strength: required(&mut parser, "player_strength")?,
+        player_sprite: required(&mut parser, "player_sprite")?,
+        initial_room: header_value(&mut parser, "initial_room")?.unwrap_or(0) as usize,
+        rooms,
+        victory_condition,
+        items,
+        inventory_capacity: header_value(&mut parser, "inventory_capacity")?
+            .unwrap_or(DEFAULT_INVENTORY_CAPACITY),
+        defeat_condition,
+    })
+}
+
+#[test]
+fn test_text_round_trip() {
+    use crate::components::EnemyType;
+    use crate::map::{
+        Trigger, TriggerAction, TriggerCondition, DOWN_STAIRS_SPRITE, UP_STAIRS_SPRITE,
+    };
+
+    let mut first = Room::new(Position::new(1, 1, 0));
+    for x in 0..=4 {
+        for y in 0..=2 {
+            let tile = if x == 0 || x == 4 || y == 0 || y == 2 {
+                Tile::new(947, false)
+            } else {
+                Tile::new(960, true)
+            };
+            first.add_tile(Position::new(x, y, 0), tile);
+        }
+    }
+    first
+        .add_tile(
+            Position::new(1, 1, 0),
+            Tile {
+                sprite_index: UP_STAIRS_SPRITE,
+                kind: TileKind::UpStairs,
+                lock: None,
+            },
+        )
+        .add_tile(
+            Position::new(1, 1, 1),
+            Tile {
+                sprite_index: DOWN_STAIRS_SPRITE,
+                kind: TileKind::DownStairs,
+                lock: None,
+            },
+        )
+        .add_tile(
+            Position::new(2, 1, 1),
+            Tile {
+                sprite_index: 960,
+                kind: TileKind::Lava,
+                lock: None,
+            },
+        )
+        .add_enemy(
+            Position::new(2, 1, 0),
+            Enemy {
+                enemy_type: Some(EnemyType::Ghost),
+                drops: Some(ItemId(9)),
+                group: Some("guards".into()),
+                ..Enemy::new(
+                    74,
+                    5,
+                    2,
+                    Enemy::circular_wake_zone(Position::new(2, 1, 0), 3),
+                )
+            },
+        )
+        .add_enemy(
+            Position::new(3, 1, 0),
+            Enemy::new(
+                74,
+                5,
+                2,
+                [Position::new(1, 1, 0), Position::new(9, 9, 0)]
+                    .into_iter()
+                    .collect(),
+            ),
+        )
+        .add_health(
+            Position::new(1, 1, 1),
+            Health {
+                sprite_index: 1517,
+                health: 10,
+            },
+        );
+    let mut second = Room::new(Position::new(0, 0, 0));
+    second
+        .add_tile(Position::new(0, 0, 0), Tile::new(960, true))
+        .add_tile(
+            Position::new(1, 0, 0),
+            Tile {
+                lock: Some(ItemId(7)),
+                ..Tile::new(960, true)
+            },
+        )
+        .add_item(Position::new(0, 0, 0), ItemId(7))
+        .add_item(Position::new(1, 0, 0), ItemId(8))
+        .add_ally(
+            Position::new(0, 0, 0),
+            Ally {
+                sprite_index: 26,
+                health: 20,
+            },
+        );
+    second.triggers.push(Trigger {
+        condition: TriggerCondition::Defeated("guards".into()),
+        actions: vec![
+            TriggerAction::SetTile(Position::new(1, 1, 0), Tile::new(960, true)),
+            TriggerAction::Message("The way is open".into()),
+        ],
+    });
+
+    let mut map = Map {
+        player_health: 20,
+        player_strength: 3,
+        victory_condition: VictoryCondition::And(vec![
+            VictoryCondition::Extermination,
+            VictoryCondition::Arrival(1, Position::new(5, 5, 0)),
+        ]),
+        items: [
+            (
+                ItemId(7),
+                Item {
+                    name: "Rusty key".into(),
+                    sprite_index: 2936,
+                    equipment: None,
+                },
+            ),
+            (
+                ItemId(8),
+                Item {
+                    name: "Short sword".into(),
+                    sprite_index: 2900,
+                    equipment: Some(Equipment::Weapon {
+                        damage: 2,
+                        reach: 2,
+                        speed: 0,
+                    }),
+                },
+            ),
+            (
+                ItemId(9),
+                Item {
+                    name: "Leather armor".into(),
+                    sprite_index: 2910,
+                    equipment: Some(Equipment::Armor {
+                        defense: 1,
+                        speed: -20,
+                    }),
+                },
+            ),
+        ]
+        .into_iter()
+        .collect(),
+        inventory_capacity: 3,
+        defeat_condition: DefeatCondition::Or(vec![
+            DefeatCondition::TimeLimit(1800),
+            DefeatCondition::AllyDeath,
+        ]),
+        ..Map::single_room(first)
+    };
+    map.rooms.push(second);
+    map.connect((0, Position::new(3, 1, 0)), (1, Position::new(0, 0, 0)));
+
+    let text = to_text(&map);
+    let parsed = from_text(&text).unwrap_or_else(|error| panic!("{}\n{}", error, text));
+    assert!(parsed == map, "{}", text);
+}
+
+#[test]
+fn test_text_arrival_markers() {
+    let map = from_text(
+        "player_health 5\nplayer_strength 1\nplayer_sprite 71\n\
+         legend\n. tile 960 floor\n@ tile 960 floor; start\nX tile 960 floor; arrival\nend\n\
+         room 0\nfloor 0 0 0\n@.X\nend\n",
+    )
+    .unwrap_or_else(|error| panic!("{}", error));
+    assert!(map.victory_condition == VictoryCondition::Arrival(0, Position::new(2, 0, 0)));
+    assert!(map.rooms[0].initial_position == Position::new(0, 0, 0));
+}
+
+#[test]
+fn test_text_round_trip_awkward_legends() {
+    // A room for each of enough different walls to use up the ASCII legend
+    // characters up to `n`, and one lining three of them up in a row
+    let wall = |i: u64| Tile::new(1000 + i, false);
+    let rooms: Vec<Room> = (0..77)
+        .map(|i| {
+            let mut room = Room::new(Position::new(5, 5, 5));
+            room.add_tile(Position::new(0, 0, 0), wall(i));
+            room
+        })
+        .collect();
+    let mut last = Room::new(Position::new(5, 5, 5));
+    for (x, i) in [67, 76, 66].into_iter().enumerate() {
+        last.add_tile(Position::new(x as i64, 0, 0), wall(i));
+    }
+    last.add_enemy(
+        Position::new(0, 1, 0),
+        Enemy {
+            group: Some("guards; \"elite\"; night".into()),
+            ..Enemy::new(74, 5, 2, BTreeSet::new())
+        },
+    );
+    let mut map = Map::single_room(last);
+    map.rooms.splice(0..0, rooms);
+
+    let text = to_text(&map);
+    let parsed = from_text(&text).unwrap_or_else(|error| panic!("{}\n{}", error, text));
+    assert!(parsed == map, "{}", text);
+}
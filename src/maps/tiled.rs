@@ -0,0 +1,690 @@
+//! Imports maps exported from the Tiled editor as JSON (`.tmj`).
+//!
+//! Each tile layer is one floor of a single room, at the `z` given by the
+//! layer's `z` property or else its order among the tile layers. Tile ids map
+//! straight onto `Tile::sprite_index`, so tilesets must be cut from `tiles.png`
+//! in 32x32 tiles. A tile's kind comes from a `kind` property (any
+//! `map::TileKind`, such as `Floor`, `Wall`, `Stairs` or `Lava`) or a
+//! `passable` bool on its tileset tile, or failing that on its layer, and is
+//! otherwise `Wall`.
+//!
+//! Objects are placed by their class (`type` in older versions of Tiled) on the
+//! floor of the layer's `z` property or of the tile layer beneath them:
+//!
+//! - `start`: where the player starts. There must be exactly one.
+//! - `enemy`: takes the properties of `map::Enemy` (`enemy_type`,
+//!   `ai_behavior`, `sprite_index`, `health`, `strength`, `random_type`,
+//!   `drops`, `group`) and a `wake_radius`, defaulting to `DEFAULT_WAKE_RADIUS`.
+//! - `health`: restores its `health` property.
+//! - `ally`: an ally to escort, with its `health` property.
+//! - `arrival`: a target for `VictoryCondition::Arrival`.
+//! - `item`: the item with the id in its `item` property. The first object
+//!   for each item also names it, after the object's name, and makes it a
+//!   weapon if it has a `damage` property (with a `reach`, defaulting to 1)
+//!   or armor if it has a `defense` property. Either may have a `speed`
+//!   property, possibly negative, which it adds to its wearer's speed.
+//! - `lock`: locks the tile beneath it, which opens for a player carrying the
+//!   item in its `item` property.
+//! - `trigger`: runs the `TriggerAction` list in its `actions` property, as
+//!   JSON, once its `condition` property holds, or else once the player steps
+//!   onto a tile the object covers.
+//!
+//! Tile objects take their sprite from their tile unless `sprite_index` is set.
+//! The map's `player_health`, `player_strength` and `player_sprite` properties
+//! are required, and `inventory_capacity` is optional. Its `victory` property
+//! holds a `VictoryCondition` as JSON, or a bare name such as `Extermination`;
+//! without it, reaching any `arrival` object wins. A `defeat` property holds a
+//! `DefeatCondition` the same way.
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::components::Position;
+use crate::map::{
+    Ally, DefeatCondition, Enemy, Equipment, Health, Item, ItemId, Map, Room, Tile, TileKind,
+    Trigger, TriggerCondition, VictoryCondition, ATLAS_COLUMNS, DEFAULT_INVENTORY_CAPACITY,
+    MAP_VERSION,
+};
+
+pub const DEFAULT_WAKE_RADIUS: i64 = 5;
+
+/// Tiled stores flips and rotations in the top bits of a global tile id.
+const FLIP_FLAGS: u64 = 0xF000_0000;
+
+#[derive(Debug)]
+pub struct TiledError(pub String);
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+fn error<T>(message: impl Into<String>) -> Result<T, TiledError> {
+    Err(TiledError(message.into()))
+}
+
+#[derive(Deserialize)]
+struct TiledMap {
+    height: i64,
+    tilewidth: f64,
+    tileheight: f64,
+    layers: Vec<Layer>,
+    tilesets: Vec<Tileset>,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Layer {
+    #[serde(rename = "tilelayer")]
+    Tiles {
+        name: String,
+        #[serde(default)]
+        width: i64,
+        #[serde(default)]
+        data: Option<Value>,
+        #[serde(default)]
+        chunks: Vec<Chunk>,
+        #[serde(default)]
+        properties: Vec<Property>,
+    },
+    #[serde(rename = "objectgroup")]
+    Objects {
+        name: String,
+        objects: Vec<Object>,
+        #[serde(default)]
+        properties: Vec<Property>,
+    },
+    #[serde(rename = "group")]
+    Group { layers: Vec<Layer> },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct Chunk {
+    x: i64,
+    y: i64,
+    width: i64,
+    data: Value,
+}
+
+#[derive(Deserialize)]
+struct Object {
+    #[serde(default)]
+    id: u64,
+    #[serde(default, alias = "type")]
+    class: String,
+    #[serde(default)]
+    name: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+    #[serde(default)]
+    gid: Option<u64>,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+#[derive(Deserialize)]
+struct Tileset {
+    firstgid: u64,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    columns: Option<u32>,
+    #[serde(default)]
+    tiles: Vec<TilesetTile>,
+}
+
+#[derive(Deserialize)]
+struct TilesetTile {
+    id: u64,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+#[derive(Deserialize)]
+struct Property {
+    name: String,
+    value: Value,
+}
+
+fn property<'a>(properties: &'a [Property], name: &str) -> Option<&'a Value> {
+    properties
+        .iter()
+        .find(|property| property.name == name)
+        .map(|property| &property.value)
+}
+
+fn integer_property(properties: &[Property], name: &str) -> Result<Option<i64>, TiledError> {
+    match property(properties, name) {
+        None => Ok(None),
+        Some(value) => match value.as_i64() {
+            Some(n) => Ok(Some(n)),
+            None => error(format!("property `{}` must be an integer", name)),
+        },
+    }
+}
+
+fn bool_property(properties: &[Property], name: &str) -> Result<Option<bool>, TiledError> {
+    match property(properties, name) {
+        None => Ok(None),
+        Some(value) => match value.as_bool() {
+            Some(b) => Ok(Some(b)),
+            None => error(format!("property `{}` must be a bool", name)),
+        },
+    }
+}
+
+fn kind_property(properties: &[Property]) -> Result<Option<TileKind>, TiledError> {
+    if let Some(kind) = property(properties, "kind") {
+        return serde_json::from_value(kind.clone())
+            .map(Some)
+            .map_err(|e| TiledError(format!("property `kind`: {}", e)));
+    }
+    Ok(bool_property(properties, "passable")?.map(|passable| {
+        if passable {
+            TileKind::Floor
+        } else {
+            TileKind::Wall
+        }
+    }))
+}
+
+fn tile_data(layer: &str, data: &Value) -> Result<Vec<u64>, TiledError> {
+    match data.as_array() {
+        Some(gids) => gids
+            .iter()
+            .map(|gid| {
+                gid.as_u64()
+                    .ok_or_else(|| TiledError(format!("layer `{}` has a bad tile id", layer)))
+            })
+            .collect(),
+        None => error(format!(
+            "layer `{}` is encoded; export it with the CSV tile layer format",
+            layer
+        )),
+    }
+}
+
+/// Whether a JSON document is a Tiled map rather than a `map::Map`.
+pub fn is_tiled(document: &Value) -> bool {
+    document.get("tiledversion").is_some()
+        || (document.get("type").and_then(Value::as_str) == Some("map")
+            && document.get("layers").is_some())
+}
+
+struct Importer<'a> {
+    tiled: &'a TiledMap,
+    room: Option<Room>,
+    start: Option<Position>,
+    arrivals: Vec<Position>,
+    /// Locks to put on tiles once every layer is in
+    locks: Vec<(Position, ItemId)>,
+    /// Each item as described by the first object placing it
+    items: BTreeMap<ItemId, Item>,
+    /// `z` for the next tile layer without a `z` property
+    next_z: i64,
+    /// `z` of the last tile layer, for object layers without a `z` property
+    current_z: i64,
+}
+
+impl<'a> Importer<'a> {
+    fn room(&mut self) -> &mut Room {
+        self.room
+            .get_or_insert_with(|| Room::new(Position::new(0, 0, 0)))
+    }
+
+    /// The tileset a global tile id belongs to and the id within it.
+    fn tile_id(&self, gid: u64) -> Result<(&'a Tileset, u64), TiledError> {
+        let gid = gid & !FLIP_FLAGS;
+        match self
+            .tiled
+            .tilesets
+            .iter()
+            .filter(|tileset| tileset.firstgid <= gid)
+            .max_by_key(|tileset| tileset.firstgid)
+        {
+            Some(tileset) => Ok((tileset, gid - tileset.firstgid)),
+            None => error(format!("tile {} is not in any tileset", gid)),
+        }
+    }
+
+    fn sprite_index(&self, gid: u64) -> Result<u64, TiledError> {
+        self.tile_id(gid).map(|(_, id)| id)
+    }
+
+    fn import_layers(&mut self, layers: &'a [Layer]) -> Result<(), TiledError> {
+        for layer in layers {
+            match layer {
+                Layer::Tiles {
+                    name,
+                    width,
+                    data,
+                    chunks,
+                    properties,
+                } => {
+                    let z = integer_property(properties, "z")?.unwrap_or(self.next_z);
+                    self.next_z = z + 1;
+                    self.current_z = z;
+                    let kind = kind_property(properties)?.unwrap_or(TileKind::Wall);
+                    if let Some(data) = data {
+                        self.import_tiles(name, (0, 0, *width), data, z, kind)?;
+                    }
+                    for chunk in chunks {
+                        self.import_tiles(
+                            name,
+                            (chunk.x, chunk.y, chunk.width),
+                            &chunk.data,
+                            z,
+                            kind,
+                        )?;
+                    }
+                }
+                Layer::Objects {
+                    name,
+                    objects,
+                    properties,
+                } => {
+                    let z = integer_property(properties, "z")?.unwrap_or(self.current_z);
+                    for object in objects {
+                        self.import_object(object, z).map_err(|e| {
+                            TiledError(format!("layer `{}`, object {}: {}", name, object.id, e))
+                        })?;
+                    }
+                }
+                Layer::Group { layers } => self.import_layers(layers)?,
+                Layer::Other => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn import_tiles(
+        &mut self,
+        layer: &str,
+        (x0, y0, width): (i64, i64, i64),
+        data: &Value,
+        z: i64,
+        layer_kind: TileKind,
+    ) -> Result<(), TiledError> {
+        if width <= 0 {
+            return error(format!("layer `{}` has no width", layer));
+        }
+        for (i, gid) in tile_data(layer, data)?.into_iter().enumerate() {
+            if gid == 0 {
+                continue;
+            }
+            let (tileset, id) = self.tile_id(gid)?;
+            if tileset
+                .columns
+                .is_some_and(|columns| columns != ATLAS_COLUMNS)
+            {
+                return error(format!(
+                    "tileset `{}` must be {} tiles wide, like tiles.png",
+                    tileset.name, ATLAS_COLUMNS
+                ));
+            }
+            let kind = match tileset.tiles.iter().find(|tile| tile.id == id) {
+                Some(tile) => kind_property(&tile.properties)?,
+                None => None,
+            }
+            .unwrap_or(layer_kind);
+            let (column, row) = (x0 + i as i64 % width, y0 + i as i64 / width);
+            let position = Position::new(column, self.tiled.height - 1 - row, z);
+            self.room().add_tile(
+                position,
+                Tile {
+                    sprite_index: id,
+                    kind,
+                    lock: None,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Opposite corners of the tiles an object covers, at least the one it
+    /// sits on.
+    fn covered(&self, object: &Object, z: i64) -> (Position, Position) {
+        let top = if object.gid.is_some() {
+            object.y - object.height
+        } else {
+            object.y
+        };
+        let first_column = (object.x / self.tiled.tilewidth).floor() as i64;
+        let last_column = ((object.x + object.width) / self.tiled.tilewidth).ceil() as i64 - 1;
+        let first_row = (top / self.tiled.tileheight).floor() as i64;
+        let last_row = ((top + object.height) / self.tiled.tileheight).ceil() as i64 - 1;
+        let y = |row: i64| self.tiled.height - 1 - row;
+        (
+            Position::new(first_column, y(first_row), z),
+            Position::new(last_column.max(first_column), y(last_row.max(first_row)), z),
+        )
+    }
+
+    fn import_object(&mut self, object: &Object, z: i64) -> Result<(), TiledError> {
+        // Tile objects are anchored at their bottom-left corner, others at
+        // their top-left
+        let center_y = if object.gid.is_some() {
+            object.y - object.height / 2.
+        } else {
+            object.y + object.height / 2.
+        };
+        let column = ((object.x + object.width / 2.) / self.tiled.tilewidth).floor() as i64;
+        let row = (center_y / self.tiled.tileheight).floor() as i64;
+        let position = Position::new(column, self.tiled.height - 1 - row, z);
+
+        let sprite_index = match integer_property(&object.properties, "sprite_index")? {
+            Some(sprite_index) => Some(sprite_index as u64),
+            None => object.gid.map(|gid| self.sprite_index(gid)).transpose()?,
+        };
+        match object.class.as_str() {
+            "start" => {
+                if self.start.replace(position).is_some() {
+                    return error("there is more than one start");
+                }
+            }
+            "arrival" => self.arrivals.push(position),
+            "enemy" => {
+                let mut fields: serde_json::Map<String, Value> = object
+                    .properties
+                    .iter()
+                    .filter(|property| property.name != "wake_radius")
+                    .map(|property| (property.name.clone(), property.value.clone()))
+                    .collect();
+                if let Some(sprite_index) = sprite_index {
+                    fields.insert("sprite_index".into(), sprite_index.into());
+                }
+                fields.insert("wake_zone".into(), Value::Array(Vec::new()));
+                let mut enemy: Enemy = serde_json::from_value(Value::Object(fields))
+                    .map_err(|e| TiledError(e.to_string()))?;
+                let radius = integer_property(&object.properties, "wake_radius")?
+                    .unwrap_or(DEFAULT_WAKE_RADIUS);
+                enemy.wake_zone = Enemy::circular_wake_zone(position, radius);
+                self.room().add_enemy(position, enemy);
+            }
+            "health" => {
+                let Some(health) = integer_property(&object.properties, "health")? else {
+                    return error("health pickups need a `health` property");
+                };
+                let Some(sprite_index) = sprite_index else {
+                    return error("health pickups need a tile or a `sprite_index` property");
+                };
+                self.room().add_health(
+                    position,
+                    Health {
+                        sprite_index,
+                        health: health as u64,
+                    },
+                );
+            }
+            "ally" => {
+                let Some(health) = integer_property(&object.properties, "health")? else {
+                    return error("allies need a `health` property");
+                };
+                let Some(sprite_index) = sprite_index else {
+                    return error("allies need a tile or a `sprite_index` property");
+                };
+                self.room().add_ally(
+                    position,
+                    Ally {
+                        sprite_index,
+                        health: health.max(1) as u64,
+                    },
+                );
+            }
+            "item" => {
+                let item = item_property(object)?;
+                if let Entry::Vacant(entry) = self.items.entry(item) {
+                    let Some(sprite_index) = sprite_index else {
+                        return error("items need a tile or a `sprite_index` property");
+                    };
+                    let name = if object.name.is_empty() {
+                        format!("Item {}", item.0)
+                    } else {
+                        object.name.clone()
+                    };
+                    let equipment = equipment_properties(&object.properties)?;
+                    entry.insert(Item {
+                        name,
+                        sprite_index,
+                        equipment,
+                    });
+                }
+                self.room().add_item(position, item);
+            }
+            "lock" => {
+                let item = item_property(object)?;
+                self.locks.push((position, item));
+            }
+            "trigger" => {
+                let Some(actions) = property(&object.properties, "actions") else {
+                    return error("triggers need an `actions` property");
+                };
+                let actions = json_property(actions, "actions")?;
+                let condition = match property(&object.properties, "condition") {
+                    Some(value) => json_property(value, "condition")?,
+                    None => {
+                        let (from, to) = self.covered(object, z);
+                        TriggerCondition::Enter(from, to)
+                    }
+                };
+                self.room().triggers.push(Trigger { condition, actions });
+            }
+            other => return error(format!("unknown object class `{}`", other)),
+        }
+        Ok(())
+    }
+}
+
+fn item_property(object: &Object) -> Result<ItemId, TiledError> {
+    match integer_property(&object.properties, "item")? {
+        Some(item) if item >= 0 => Ok(ItemId(item as u64)),
+        _ => error(format!("{} objects need an `item` property", object.class)),
+    }
+}
+
+fn equipment_properties(properties: &[Property]) -> Result<Option<Equipment>, TiledError> {
+    let number = |name| -> Result<Option<u64>, TiledError> {
+        match integer_property(properties, name)? {
+            Some(value) if value < 0 => error(format!("property `{}` must not be negative", name)),
+            value => Ok(value.map(|value| value as u64)),
+        }
+    };
+    let speed = integer_property(properties, "speed")?;
+    Ok(match (number("damage")?, number("defense")?) {
+        (Some(_), Some(_)) => return error("an item can't have both `damage` and `defense`"),
+        (Some(damage), None) => Some(Equipment::Weapon {
+            damage,
+            reach: number("reach")?.unwrap_or(1),
+            speed: speed.unwrap_or(0),
+        }),
+        (None, Some(defense)) => Some(Equipment::Armor {
+            defense,
+            speed: speed.unwrap_or(0),
+        }),
+        (None, None) if speed.is_some() => {
+            return error("a `speed` property needs a `damage` or `defense` property")
+        }
+        (None, None) => None,
+    })
+}
+
+/// A property holding JSON, either as a string or as a bare name.
+fn json_property<T: serde::de::DeserializeOwned>(
+    value: &Value,
+    name: &str,
+) -> Result<T, TiledError> {
+    let parsed = match value.as_str() {
+        Some(text) => serde_json::from_str(text).or_else(|_| serde_json::from_value(value.clone())),
+        None => serde_json::from_value(value.clone()),
+    };
+    parsed.map_err(|e| TiledError(format!("property `{}`: {}", name, e)))
+}
+
+pub fn from_tiled(document: Value) -> Result<Map, TiledError> {
+    let tiled: TiledMap =
+        serde_json::from_value(document).map_err(|e| TiledError(e.to_string()))?;
+    let mut importer = Importer {
+        tiled: &tiled,
+        room: None,
+        start: None,
+        arrivals: Vec::new(),
+        locks: Vec::new(),
+        items: BTreeMap::new(),
+        next_z: 0,
+        current_z: 0,
+    };
+    importer.import_layers(&tiled.layers)?;
+    for (position, item) in std::mem::take(&mut importer.locks) {
+        match importer.room().tiles.get_mut(&position) {
+            Some(tile) => tile.lock = Some(item),
+            None => {
+                return error(format!(
+                    "there is no tile to lock at ({}, {}, {})",
+                    position.x, position.y, position.z
+                ))
+            }
+        }
+    }
+
+    let Some(start) = importer.start else {
+        return error("there is no `start` object");
+    };
+    let mut room = importer.room.unwrap_or_else(|| Room::new(start));
+    room.initial_position = start;
+
+    let victory_condition = match property(&tiled.properties, "victory") {
+        Some(value) => json_property(value, "victory")?,
+        None if importer.arrivals.is_empty() => {
+            return error("there is no `victory` property and no `arrival` object")
+        }
+        // Tiled maps hold a single room
+        None => VictoryCondition::arrival_at_any(
+            &importer
+                .arrivals
+                .iter()
+                .map(|position| (0, *position))
+                .collect::<Vec<_>>(),
+        ),
+    };
+    let required = |name: &str| -> Result<u64, TiledError> {
+        match integer_property(&tiled.properties, name)? {
+            Some(n) if n >= 0 => Ok(n as u64),
+            _ => error(format!("the map needs a `{}` property", name)),
+        }
+    };
+
+    Ok(Map {
+        version: MAP_VERSION,
+        rooms: vec![room],
+        initial_room: 0,
+        player_health: required("player_health")?,
+        player_strength: required("player_strength")?,
+        player_sprite: required("player_sprite")?,
+        victory_condition,
+        items: importer.items,
+        inventory_capacity: match integer_property(&tiled.properties, "inventory_capacity")? {
+            Some(n) if n >= 0 => n as u64,
+            Some(_) => return error("`inventory_capacity` cannot be negative"),
+            None => DEFAULT_INVENTORY_CAPACITY,
+        },
+        defeat_condition: match property(&tiled.properties, "defeat") {
+            Some(value) => json_property(value, "defeat")?,
+            None => DefeatCondition::Never,
+        },
+    })
+}
+
+#[test]
+fn test_import_tiled_map() {
+    use crate::components::EnemyType;
+    use crate::map::TriggerAction;
+    use serde_json::json;
+
+    let document = json!({
+        "type": "map",
+        "tiledversion": "1.10.2",
+        "width": 3,
+        "height": 2,
+        "tilewidth": 32,
+        "tileheight": 32,
+        "infinite": false,
+        "tilesets": [{
+            "firstgid": 1,
+            "name": "tiles",
+            "columns": 64,
+            "tiles": [{ "id": 960, "properties": [{ "name": "passable", "type": "bool", "value": true }] }]
+        }],
+        "properties": [
+            { "name": "player_health", "type": "int", "value": 20 },
+            { "name": "player_strength", "type": "int", "value": 3 },
+            { "name": "player_sprite", "type": "int", "value": 71 },
+            { "name": "victory", "type": "string", "value": "Extermination" }
+        ],
+        "layers": [
+            { "type": "tilelayer", "name": "ground", "width": 3, "height": 2,
+              "data": [948, 961, 961, 948, 948, 0] },
+            { "type": "objectgroup", "name": "things", "objects": [
+                { "id": 1, "type": "start", "x": 32, "y": 0, "width": 32, "height": 32 },
+                { "id": 2, "class": "enemy", "x": 64, "y": 32, "width": 32, "height": 32, "gid": 75,
+                  "properties": [
+                      { "name": "enemy_type", "type": "string", "value": "Ghost" },
+                      { "name": "wake_radius", "type": "int", "value": 2 },
+                      { "name": "group", "type": "string", "value": "guards" }
+                  ] },
+                { "id": 3, "class": "trigger", "x": 0, "y": 0, "width": 64, "height": 32,
+                  "properties": [
+                      { "name": "actions", "type": "string", "value": "[{\"Wake\":\"guards\"}]" }
+                  ] }
+            ] }
+        ]
+    });
+    assert!(is_tiled(&document));
+    let map = from_tiled(document).unwrap_or_else(|e| panic!("{}", e));
+
+    let mut room = Room::new(Position::new(1, 1, 0));
+    room.add_tile(Position::new(0, 1, 0), Tile::new(947, false))
+        .add_tile(Position::new(1, 1, 0), Tile::new(960, true))
+        .add_tile(Position::new(2, 1, 0), Tile::new(960, true))
+        .add_tile(Position::new(0, 0, 0), Tile::new(947, false))
+        .add_tile(Position::new(1, 0, 0), Tile::new(947, false))
+        .add_enemy(
+            Position::new(2, 1, 0),
+            Enemy {
+                enemy_type: Some(EnemyType::Ghost),
+                random_type: false,
+                ai_behavior: None,
+                sprite_index: Some(74),
+                health: None,
+                strength: None,
+                wake_zone: Enemy::circular_wake_zone(Position::new(2, 1, 0), 2),
+                drops: None,
+                group: Some("guards".into()),
+            },
+        );
+    room.triggers.push(Trigger {
+        condition: TriggerCondition::Enter(Position::new(0, 1, 0), Position::new(1, 1, 0)),
+        actions: vec![TriggerAction::Wake("guards".into())],
+    });
+    assert!(
+        map == Map {
+            player_health: 20,
+            player_strength: 3,
+            ..Map::single_room(room)
+        }
+    );
+}
@@ -3,13 +3,32 @@ use crate::map;
 use itertools::Itertools;
 
 pub fn unbeatable() -> map::Map {
-    let border_tile = map::Tile {
-        sprite_index: 15 * 64 - 13,
-        passable: false,
+    let border_tile = map::Tile::new(15 * 64 - 13, false);
+    // A staircase through the middle of every floor
+    let tile_at = |position: &Position| {
+        let kind = match position.z {
+            _ if position.x != 0 || position.y != 0 => map::TileKind::Floor,
+            -9 => map::TileKind::UpStairs,
+            9 => map::TileKind::DownStairs,
+            _ => map::TileKind::Stairs,
+        };
+        map::Tile {
+            sprite_index: match kind {
+                map::TileKind::Floor => 960,
+                map::TileKind::DownStairs => map::DOWN_STAIRS_SPRITE,
+                _ => map::UP_STAIRS_SPRITE,
+            },
+            kind,
+            lock: None,
+        }
     };
     map::Map {
+        version: map::MAP_VERSION,
         player_sprite: 31 * 64 + 20,
-        room: map::Room {
+        items: Default::default(),
+        inventory_capacity: map::DEFAULT_INVENTORY_CAPACITY,
+        defeat_condition: Default::default(),
+        rooms: vec![map::Room {
             initial_position: Position { x: 0, y: 0, z: -9 },
             healths: vec![(
                 Position::new(1, 1, 0),
@@ -32,13 +51,8 @@ pub fn unbeatable() -> map::Map {
                         .collect::<Vec<_>>()
                 })
                 .map(|position| {
-                    (
-                        position,
-                        map::Tile {
-                            sprite_index: 960,
-                            passable: true,
-                        },
-                    )
+                    let tile = tile_at(&position);
+                    (position, tile)
                 })
                 .chain(
                     (-10i64..=10)
@@ -118,11 +132,11 @@ pub fn unbeatable() -> map::Map {
                     let make_enemy = |x: i64, y: i64, z: i64| {
                         (
                             Position { x, y, z },
-                            map::Enemy {
-                                sprite_index: 74,
-                                health: 100,
-                                strength: 10,
-                                wake_zone: (-6..=6)
+                            map::Enemy::new(
+                                74,
+                                100,
+                                10,
+                                (-6..=6)
                                     .cartesian_product(-6..=6)
                                     .cartesian_product(-6..=6)
                                     .map(|((dx, dy), dz)| Position {
@@ -131,7 +145,7 @@ pub fn unbeatable() -> map::Map {
                                         z: k + dz,
                                     })
                                     .collect(),
-                            },
+                            ),
                         )
                     };
                     vec![
@@ -150,12 +164,17 @@ pub fn unbeatable() -> map::Map {
                     ]
                 })
                 .collect(),
-        },
+            connections: Default::default(),
+            items: Default::default(),
+            allies: Default::default(),
+            triggers: Vec::new(),
+        }],
+        initial_room: 0,
         player_health: 4000,
         player_strength: 10,
         victory_condition: map::VictoryCondition::Or(vec![
             map::VictoryCondition::Extermination,
-            map::VictoryCondition::Arrival(Position { x: 0, y: 0, z: 9 }),
+            map::VictoryCondition::Arrival(0, Position { x: 0, y: 0, z: 9 }),
         ]),
     }
 }
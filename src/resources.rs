@@ -1,8 +1,12 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::PathBuf;
 
-use crate::components::Position;
+use crate::components::{EnemyType, Position};
+use crate::map::{ItemId, Map, TileKind, VictoryCondition};
 
 use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 #[derive(Debug, Resource)]
 pub struct Follow(pub bool);
@@ -13,16 +17,219 @@ pub struct Floor(pub i64);
 #[derive(Debug, Resource)]
 pub struct ScaleFactor(pub f32);
 
+/// Index into `map::Map::rooms` of the room the player is in.
 #[derive(Debug, Resource)]
-pub struct MousePosition(pub Vec2);
+pub struct CurrentRoom(pub usize);
 
+/// Fixed update ticks since the current map started.
+#[derive(Debug, Resource, Default)]
+pub struct Ticks(pub u64);
+
+/// Fixed update ticks since the player last entered the current room.
+#[derive(Debug, Resource, Default)]
+pub struct RoomTicks(pub u64);
+
+/// How the world keeps time while playing.
+#[derive(Debug, Resource, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pace {
+    /// Enemies move and combat resolves every fixed update tick.
+    #[default]
+    RealTime,
+    /// The world advances one turn for each move, attack or wait of the
+    /// player. Ticks, and so time limits and trigger delays, count turns.
+    TurnBased,
+}
+
+/// The energy cost of each player action the world has yet to answer with a
+/// turn, in turn-based play.
+#[derive(Debug, Resource, Default)]
+pub struct PendingTurns(pub VecDeque<u64>);
+
+/// The player's next attack, chosen by their input and struck by `combat` on
+/// the next tick or turn.
+#[derive(Debug, Resource, Default)]
+pub struct PlayerAttack {
+    pub target: Option<Entity>,
+    /// Ticks until the player may attack again, in real-time play.
+    pub cooldown: u64,
+}
+
+/// Allies killed since the current map started.
+#[derive(Debug, Resource, Default)]
+pub struct AlliesLost(pub u64);
+
+/// Enemies of each type killed since the current map started.
+#[derive(Debug, Resource, Default)]
+pub struct Kills(pub BTreeMap<EnemyType, u64>);
+
+/// Every random choice the game makes, drawn from streams seeded from one
+/// number so that a run can be replayed. Each part of the game has its own
+/// stream, so that, say, more particles on screen don't change how the
+/// enemies move.
+#[derive(Debug, Resource)]
+pub struct GameRng {
+    /// The seed of the run being played, or of the next one from the menu
+    pub seed: u64,
+    /// Generated maps and the types of randomly typed enemies
+    pub map: StdRng,
+    /// Enemy movement
+    pub ai: StdRng,
+    /// Particles and anything else that doesn't affect play
+    pub cosmetic: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        let stream = |n: u64| StdRng::seed_from_u64(stream_seed(seed, n));
+        GameRng {
+            seed,
+            map: stream(0),
+            ai: stream(1),
+            cosmetic: stream(2),
+        }
+    }
+}
+
+/// The seed of stream `n` of a run, mixed with splitmix64 so that no stream
+/// of one seed repeats another stream of a nearby seed.
+fn stream_seed(seed: u64, n: u64) -> u64 {
+    let mut z = seed.wrapping_add((n + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The tile under the mouse cursor on the floor on show, if the cursor is
+/// over the window.
+#[derive(Debug, Resource, Default)]
+pub struct HoveredTile(pub Option<Position>);
+
+/// Map files offered in the menu: those named on the command line, followed
+/// by the contents of `maps::MAPS_DIRECTORY`.
+#[derive(Debug, Resource)]
+pub struct MapFiles(pub Vec<PathBuf>);
+
+/// What a click does in the map editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorTool {
+    Tile,
+    /// Cycles the kind of the clicked tile
+    Kind,
+    Enemy,
+    Health,
+    Start,
+    Arrival,
+    /// Places the current item, adding it to the map's items if it is new
+    Item,
+    /// Locks or unlocks the clicked tile
+    Lock,
+}
+
+/// The shapes of victory condition the map editor builds from its arrival
+/// targets. The editor leaves conditions of any other shape alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VictoryMode {
+    Extermination,
+    Arrival,
+    ExterminationAndArrival,
+    ExterminationOrArrival,
+}
+
+impl VictoryMode {
+    /// The mode that builds `victory_condition` from its arrival targets, if
+    /// any does.
+    pub fn of(victory_condition: &VictoryCondition) -> Option<Self> {
+        let mode = match victory_condition {
+            VictoryCondition::Arrival(..) | VictoryCondition::Unwinnable => VictoryMode::Arrival,
+            VictoryCondition::And(cs) if cs.contains(&VictoryCondition::Extermination) => {
+                VictoryMode::ExterminationAndArrival
+            }
+            VictoryCondition::Or(cs) if cs.contains(&VictoryCondition::Extermination) => {
+                VictoryMode::ExterminationOrArrival
+            }
+            VictoryCondition::Or(_) => VictoryMode::Arrival,
+            _ => VictoryMode::Extermination,
+        };
+        (mode.build(&victory_condition.arrivals()) == *victory_condition).then_some(mode)
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            VictoryMode::Extermination => VictoryMode::Arrival,
+            VictoryMode::Arrival => VictoryMode::ExterminationAndArrival,
+            VictoryMode::ExterminationAndArrival => VictoryMode::ExterminationOrArrival,
+            VictoryMode::ExterminationOrArrival => VictoryMode::Extermination,
+        }
+    }
+
+    /// Reaching any of `arrivals` counts as arriving.
+    pub fn build(self, arrivals: &[(usize, Position)]) -> VictoryCondition {
+        let arrival = VictoryCondition::arrival_at_any(arrivals);
+        match self {
+            VictoryMode::Extermination => VictoryCondition::Extermination,
+            VictoryMode::Arrival => arrival,
+            VictoryMode::ExterminationAndArrival => {
+                VictoryCondition::And(vec![VictoryCondition::Extermination, arrival])
+            }
+            VictoryMode::ExterminationOrArrival => {
+                VictoryCondition::Or(vec![VictoryCondition::Extermination, arrival])
+            }
+        }
+    }
+}
+
+/// State of the map editor, which edits the `map::Map` resource in place.
 #[derive(Debug, Resource)]
+pub struct Editor {
+    /// Where the map is saved, as JSON
+    pub path: PathBuf,
+    pub room: usize,
+    pub tool: EditorTool,
+    pub sprite_index: u64,
+    pub kind: TileKind,
+    pub enemy_type: EnemyType,
+    pub wake_radius: i64,
+    pub health: u64,
+    /// The item placed or locked with next
+    pub item: ItemId,
+    /// `None` while the map's victory condition is one no mode builds
+    pub victory: Option<VictoryMode>,
+    /// The room and floor last drawn, if still up to date
+    pub drawn: Option<(usize, i64)>,
+    /// The tile under the mouse, if any
+    pub cursor: Option<Position>,
+    pub message: String,
+}
+
+impl Editor {
+    pub fn new(path: PathBuf, map: &Map) -> Self {
+        Editor {
+            path,
+            room: map.initial_room,
+            tool: EditorTool::Tile,
+            sprite_index: 960,
+            kind: TileKind::Floor,
+            enemy_type: EnemyType::default(),
+            wake_radius: 5,
+            health: 10,
+            item: ItemId(0),
+            victory: VictoryMode::of(&map.victory_condition),
+            drawn: None,
+            cursor: None,
+            message: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Resource)]
 pub struct Tiles(pub BTreeMap<Position, CachedTile>);
 
 #[derive(Debug, Copy, Clone)]
 pub struct CachedTile {
     pub entity: Entity,
     pub passable: bool,
+    pub kind: TileKind,
+    pub lock: Option<ItemId>,
 }
 
 impl Tiles {
@@ -37,6 +244,42 @@ impl Tiles {
     pub fn get(&self, key: &Position) -> Option<CachedTile> {
         self.0.get(key).copied()
     }
+
+    /// Whether stairs lead from `position` to the same spot on the floor `dz`
+    /// (1 or -1) away, as in `map::Room::has_stairs`.
+    pub fn has_stairs(&self, position: &Position, dz: i64) -> bool {
+        let other = Position::new(position.x, position.y, position.z + dz);
+        match (self.get(position), self.get(&other)) {
+            (Some(from), Some(to)) if dz > 0 => TileKind::connects(from.kind, to.kind),
+            (Some(from), Some(to)) => TileKind::connects(to.kind, from.kind),
+            _ => false,
+        }
+    }
+
+    /// Where something stepping onto `position` while moving by `(dx, dy)`
+    /// comes to rest: it slides over slippery tiles until the next tile is
+    /// impassable or not `free`.
+    pub fn slide(
+        &self,
+        mut position: Position,
+        (dx, dy): (i64, i64),
+        free: impl Fn(&Position) -> bool,
+    ) -> Position {
+        if (dx, dy) == (0, 0) {
+            return position;
+        }
+        while self
+            .get(&position)
+            .is_some_and(|cached_tile| cached_tile.kind.slippery())
+        {
+            let next = Position::new(position.x + dx, position.y + dy, position.z);
+            if !self.get(&next).is_some_and(|cached_tile| cached_tile.passable) || !free(&next) {
+                break;
+            }
+            position = next;
+        }
+        position
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -45,7 +288,7 @@ pub struct CachedHealth {
     pub health: i64,
 }
 
-#[derive(Debug, Resource)]
+#[derive(Debug, Default, Resource)]
 pub struct Healths(pub BTreeMap<Position, CachedHealth>);
 
 impl Healths {
@@ -62,7 +305,7 @@ impl Healths {
     }
 }
 
-#[derive(Debug, Resource)]
+#[derive(Debug, Default, Resource)]
 pub struct Enemies {
     entity_positions: BTreeMap<Entity, Position>,
     position_entities: BTreeMap<Position, BTreeSet<Entity>>,
@@ -71,13 +314,15 @@ pub struct Enemies {
 #[derive(Debug, Resource)]
 pub struct SpriteTexture(pub (Handle<Image>, Handle<TextureAtlasLayout>));
 
-#[derive(Debug, Resource, Clone)]
+#[derive(Debug, Default, Resource, Clone)]
 pub struct Statistics {
     pub enemies_killed: i64,
     pub floors_completed: i64,
     pub damage_taken: i64,
     pub damage_dealt: i64,
     pub health_collected: i64,
+    /// The seed of the last run started, which replays it
+    pub seed: u64,
 }
 
 impl Statistics {
@@ -88,6 +333,7 @@ impl Statistics {
             damage_taken: 0,
             damage_dealt: 0,
             health_collected: 0,
+            seed: 0,
         }
     }
 }
@@ -174,3 +420,31 @@ fn test_enemies() {
         );
     }
 }
+
+#[test]
+fn test_game_rng_streams() {
+    use rand::Rng;
+
+    let mut first = GameRng::new(7);
+    let mut second = GameRng::new(7);
+    // Drawing from one stream leaves the others as they were
+    let _: u64 = first.cosmetic.gen();
+    assert_eq!(first.ai.gen::<u64>(), second.ai.gen::<u64>());
+    assert_eq!(first.map.gen::<u64>(), second.map.gen::<u64>());
+    assert_ne!(second.cosmetic.gen::<u64>(), second.ai.gen::<u64>());
+    assert_ne!(
+        GameRng::new(8).map.gen::<u64>(),
+        GameRng::new(7).map.gen::<u64>()
+    );
+    // Nor does a stream of one seed replay another stream of the next
+    for n in [0, 7, u64::MAX - 1] {
+        assert_ne!(
+            GameRng::new(n).ai.gen::<u64>(),
+            GameRng::new(n + 1).map.gen::<u64>()
+        );
+        assert_ne!(
+            GameRng::new(n).cosmetic.gen::<u64>(),
+            GameRng::new(n + 1).ai.gen::<u64>()
+        );
+    }
+}
@@ -7,4 +7,5 @@ pub enum GameState {
     Playing,
     Victory,
     Defeat,
+    Editor,
 }
@@ -0,0 +1,172 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::events::Attack;
+use crate::map;
+use crate::resources::*;
+use crate::systems::combat::within_reach;
+use crate::systems::energy::{act, ATTACK_COST};
+
+/// Ticks the player waits between attacks in real-time play.
+pub const ATTACK_COOLDOWN: u64 = 15;
+
+/// A left click, and the enemy the target indicator marks.
+#[derive(SystemParam)]
+pub struct Click<'w, 's> {
+    mouse_input: Res<'w, ButtonInput<MouseButton>>,
+    hovered_tile: Res<'w, HoveredTile>,
+    targeted: Query<'w, 's, (Entity, &'static Position), With<TargetedEnemy>>,
+}
+
+impl Click<'_, '_> {
+    /// The targeted enemy, if the player just clicked on its tile and it
+    /// stands within `reach` of `player`.
+    fn target(&self, player: Position, reach: i64) -> Option<Entity> {
+        if !self.mouse_input.just_pressed(MouseButton::Left) {
+            return None;
+        }
+        self.targeted
+            .iter()
+            .find(|(_, position)| {
+                self.hovered_tile.0 == Some(**position) && within_reach(player, **position, reach)
+            })
+            .map(|(enemy, _)| enemy)
+    }
+}
+
+/// Chooses the player's next attack: the enemy the target indicator marks
+/// when they click on it while it is within their weapon's reach, or the one
+/// they walk into. Clicks anywhere else are ignored. An attack costs energy,
+/// and in real-time play the player must also wait out a cooldown after each
+/// one.
+pub fn player_attacks(
+    mut bumps: MessageReader<Attack>,
+    click: Click,
+    mut player_query: Query<(&mut Energy, &Position, &Equipped), With<Player>>,
+    map: Res<map::Map>,
+    pace: Res<Pace>,
+    mut pending_turns: ResMut<PendingTurns>,
+    mut player_attack: ResMut<PlayerAttack>,
+) {
+    let bumped = bumps.read().last().map(|Attack(enemy)| *enemy);
+    let Some((mut energy, player_position, equipped)) = player_query.iter_mut().next() else {
+        return;
+    };
+    let (_, reach) = equipped.weapon(&map.items);
+    let Some(target) = bumped.or_else(|| click.target(*player_position, reach)) else {
+        return;
+    };
+    let turn_based = *pace == Pace::TurnBased;
+    if player_attack.target.is_some()
+        || energy.0 < ATTACK_COST
+        || (!turn_based && player_attack.cooldown > 0)
+    {
+        return;
+    }
+    act(ATTACK_COST, &mut energy, turn_based, &mut pending_turns);
+    player_attack.target = Some(target);
+    if !turn_based {
+        player_attack.cooldown = ATTACK_COOLDOWN;
+    }
+}
+
+#[test]
+fn test_attacks_wait_out_the_cooldown() {
+    let mut world = World::new();
+    world.insert_resource(Pace::RealTime);
+    world.init_resource::<PendingTurns>();
+    world.init_resource::<PlayerAttack>();
+    world.init_resource::<Messages<Attack>>();
+    world.insert_resource(ButtonInput::<MouseButton>::default());
+    world.init_resource::<HoveredTile>();
+    world.insert_resource(crate::systems::editor::new_map());
+    let player = world
+        .spawn((
+            Player,
+            Energy(ATTACK_COST),
+            Position::new(0, 0, 0),
+            Equipped::default(),
+        ))
+        .id();
+    let enemy = world.spawn(Enemy).id();
+    let mut schedule = Schedule::default();
+    schedule.add_systems(player_attacks);
+
+    world.write_message(Attack(enemy));
+    schedule.run(&mut world);
+    let mut player_attack = world.resource_mut::<PlayerAttack>();
+    assert_eq!(player_attack.target.take(), Some(enemy));
+    assert_eq!(player_attack.cooldown, ATTACK_COOLDOWN);
+
+    // Struck, but still cooling down
+    world.get_mut::<Energy>(player).unwrap().0 = ATTACK_COST;
+    world.write_message(Attack(enemy));
+    schedule.run(&mut world);
+    assert_eq!(world.resource::<PlayerAttack>().target, None);
+
+    world.insert_resource(Pace::TurnBased);
+    world.write_message(Attack(enemy));
+    schedule.run(&mut world);
+    assert_eq!(world.resource::<PlayerAttack>().target, Some(enemy));
+    assert_eq!(world.resource::<PendingTurns>().0, [ATTACK_COST]);
+}
+
+#[test]
+fn test_clicks_attack_the_hovered_target_within_reach() {
+    use crate::map::{Equipment, Item, ItemId};
+
+    let spear = |reach| Item {
+        name: "spear".into(),
+        sprite_index: 0,
+        equipment: Some(Equipment::Weapon {
+            damage: 1,
+            reach,
+            speed: 0,
+        }),
+    };
+    let mut map = crate::systems::editor::new_map();
+    map.items.insert(ItemId(1), spear(2));
+    let mut world = World::new();
+    world.insert_resource(Pace::TurnBased);
+    world.init_resource::<PendingTurns>();
+    world.init_resource::<PlayerAttack>();
+    world.init_resource::<Messages<Attack>>();
+    world.insert_resource(ButtonInput::<MouseButton>::default());
+    world.init_resource::<HoveredTile>();
+    world.insert_resource(map);
+    world.spawn((
+        Player,
+        Energy(3 * ATTACK_COST),
+        Position::new(0, 0, 0),
+        Equipped {
+            weapon: Some(ItemId(1)),
+            armor: None,
+        },
+    ));
+    let enemy = world
+        .spawn((Enemy, TargetedEnemy, Position::new(2, 0, 0)))
+        .id();
+    let mut schedule = Schedule::default();
+    schedule.add_systems(player_attacks);
+    let mut click = |world: &mut World, hovered: Position| {
+        world.resource_mut::<HoveredTile>().0 = Some(hovered);
+        let mut mouse_input = world.resource_mut::<ButtonInput<MouseButton>>();
+        mouse_input.release(MouseButton::Left);
+        mouse_input.clear();
+        mouse_input.press(MouseButton::Left);
+        schedule.run(world);
+        world.resource_mut::<PlayerAttack>().target.take()
+    };
+
+    // Clicking beside the target doesn't attack it
+    assert_eq!(click(&mut world, Position::new(2, 1, 0)), None);
+    assert_eq!(click(&mut world, Position::new(2, 0, 0)), Some(enemy));
+
+    // A shorter weapon doesn't reach it
+    world
+        .resource_mut::<map::Map>()
+        .items
+        .insert(ItemId(1), spear(1));
+    assert_eq!(click(&mut world, Position::new(2, 0, 0)), None);
+}
@@ -0,0 +1,139 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::events::EnterConnection;
+use crate::map;
+use crate::resources::*;
+use crate::systems::follow::PlayerCamera;
+use crate::systems::setup_play::RoomSpawner;
+
+/// Swaps the room on screen when the player walks through a connection. The
+/// room being left is written back into the map, so enemies that were killed
+/// and health and items that were collected stay gone if the player returns,
+/// while allies stay where they were left.
+pub fn change_room(
+    mut commands: Commands,
+    mut connections: MessageReader<EnterConnection>,
+    mut map: ResMut<map::Map>,
+    mut player_query: Query<&mut Position, With<Player>>,
+    mut camera: PlayerCamera,
+    mut spawner: RoomSpawner,
+    contents: RoomContents,
+) {
+    let Some(EnterConnection(connection)) = connections.read().last().copied() else {
+        return;
+    };
+    let Some(mut position) = player_query.iter_mut().next() else {
+        return;
+    };
+    if connection.room >= map.rooms.len() {
+        warn!("connection leads to missing room {}", connection.room);
+        return;
+    }
+
+    contents.write_back(&mut map.rooms[spawner.current_room.0]);
+
+    for entity in contents.members.iter() {
+        commands.entity(entity).despawn();
+    }
+    spawner.clear();
+    commands.insert_resource(RoomTicks::default());
+
+    *position = connection.position;
+    camera.center_on(connection.position);
+
+    spawner.spawn_room(&mut commands, &map, connection.room, camera.floor.0);
+}
+
+type EnemyContents = (
+    &'static Position,
+    &'static WakeZone,
+    &'static SpriteIndex,
+    &'static Health,
+    &'static Strength,
+    &'static EnemyType,
+    &'static AIBehavior,
+    Option<&'static Drops>,
+    Option<&'static Group>,
+);
+type Placed = (&'static Position, &'static SpriteIndex, &'static Health);
+
+/// What is left of the room on screen, to be written back into the map.
+#[derive(SystemParam)]
+pub struct RoomContents<'w, 's> {
+    members: Query<'w, 's, Entity, With<RoomMember>>,
+    enemies: Query<'w, 's, EnemyContents, (With<Enemy>, Without<Player>)>,
+    healths: Query<'w, 's, Placed, (With<HealthGain>, Without<Player>)>,
+    items: Query<'w, 's, (&'static Position, &'static ItemPickup), Without<Player>>,
+    allies: Query<'w, 's, Placed, (With<Ally>, Without<Player>)>,
+}
+
+impl RoomContents<'_, '_> {
+    fn write_back(&self, room: &mut map::Room) {
+        room.enemies = self
+            .enemies
+            .iter()
+            .map(
+                |(
+                    position,
+                    wake_zone,
+                    sprite_index,
+                    health,
+                    strength,
+                    enemy_type,
+                    ai_behavior,
+                    drops,
+                    group,
+                )| {
+                    (
+                        *position,
+                        map::Enemy {
+                            enemy_type: Some(*enemy_type),
+                            ai_behavior: Some(*ai_behavior),
+                            drops: drops.map(|Drops(item)| *item),
+                            group: group.map(|Group(group)| group.clone()),
+                            ..map::Enemy::new(
+                                sprite_index.0 as u64,
+                                health.0.max(0) as u64,
+                                strength.0.max(0) as u64,
+                                wake_zone.0.clone(),
+                            )
+                        },
+                    )
+                },
+            )
+            .collect();
+        room.healths = self
+            .healths
+            .iter()
+            .map(|(position, sprite_index, health)| {
+                (
+                    *position,
+                    map::Health {
+                        sprite_index: sprite_index.0 as u64,
+                        health: health.0.max(0) as u64,
+                    },
+                )
+            })
+            .collect();
+        room.items = self
+            .items
+            .iter()
+            .map(|(position, ItemPickup(item))| (*position, *item))
+            .collect();
+        room.allies = self
+            .allies
+            .iter()
+            .map(|(position, sprite_index, health)| {
+                (
+                    *position,
+                    map::Ally {
+                        sprite_index: sprite_index.0 as u64,
+                        health: health.0.max(1) as u64,
+                    },
+                )
+            })
+            .collect();
+    }
+}
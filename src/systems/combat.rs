@@ -1,96 +1,198 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 
 use crate::components::*;
+use crate::map;
 use crate::resources::*;
+use crate::systems::energy::{ATTACK_COST, CHILL};
 use crate::systems::particle_system::spawn_particle;
+use crate::systems::setup_play::spawn_item;
 
+/// Adjacent enemies strike the player, and the player strikes the enemy their
+/// input chose, if it is still within reach.
 pub fn combat(
     mut commands: Commands,
     mut player_query: Query<
-        (Entity, &Position, &Strength, &mut Health, &Transform),
+        (
+            Entity,
+            &Position,
+            &Strength,
+            &Equipped,
+            &mut Health,
+            &Transform,
+            Option<&mut SpeedEffects>,
+        ),
         (With<Player>, Without<Enemy>),
     >,
     mut enemy_query: Query<
-        (Entity, &Strength, &Position, &mut Health, &Transform),
+        (
+            Entity,
+            &Strength,
+            &Position,
+            &mut Health,
+            &Transform,
+            &EnemyType,
+            Option<&Drops>,
+            &mut Energy,
+        ),
         (With<Enemy>, Without<Player>),
     >,
-    text_query: Query<(Entity, &HealthBar)>,
-    targeted_query: Query<Entity, With<TargetedEnemy>>,
-    mut statistics: ResMut<Statistics>,
+    mut player_attack: ResMut<PlayerAttack>,
+    mut deaths: EnemyDeaths,
     scale_factor: Res<ScaleFactor>,
+    mut rng: ResMut<GameRng>,
 ) {
-    let (player_entity, player_position, player_strength, mut player_health, player_transform) =
-        if let Some((player_entity, position, strength, health, transform)) = player_query.iter_mut().next() {
-            (player_entity, position, strength, health, transform)
-        } else {
-            return;
-        };
+    player_attack.cooldown = player_attack.cooldown.saturating_sub(1);
+    let target = player_attack.target.take();
+    let Some((
+        player_entity,
+        player_position,
+        player_strength,
+        equipped,
+        mut player_health,
+        player_transform,
+        speed_effects,
+    )) = player_query.iter_mut().next()
+    else {
+        return;
+    };
+    let (weapon_damage, reach) = equipped.weapon(&deaths.map.items);
+    let defense = equipped.defense(&deaths.map.items);
 
     let mut enemies: Vec<_> = enemy_query
         .iter_mut()
-        .filter(|(_, _, enemy_position, _, _)| enemy_position.is_adjacent_to(*player_position))
+        .filter(|(_, _, enemy_position, ..)| {
+            within_reach(*player_position, **enemy_position, reach)
+        })
         .collect();
 
-    let m = enemies.len();
-
-    if m == 0 {
+    if enemies.is_empty() {
         return;
     }
 
-    // Track damage taken
-    let total_damage_from_enemies = enemies.iter().map(|(_, s, _, _, _)| s.0).sum::<i64>();
+    // Only adjacent enemies hit back, once for every attack they have the
+    // energy for, and the player's armor blunts each blow
+    let mut attacks: Vec<i64> = Vec::new();
+    let mut chilled = false;
+    for (_, strength, enemy_position, _, _, enemy_type, _, energy) in enemies.iter_mut() {
+        if !enemy_position.is_adjacent_to(*player_position) {
+            continue;
+        }
+        while energy.0 >= ATTACK_COST {
+            energy.0 -= ATTACK_COST;
+            attacks.push((strength.0 - defense).max(0));
+            chilled |= **enemy_type == EnemyType::Ghost;
+        }
+    }
+    let total_damage_from_enemies = attacks.iter().sum::<i64>();
 
-    for (_entity, enemy_strength, _enemy_position, _enemy_health, _) in enemies.iter_mut() {
-        player_health.0 -= enemy_strength.0;
+    // A ghost's touch slows the player down for a while; a fresh chill
+    // replaces the old one rather than adding to it
+    if let (true, Some(mut effects)) = (chilled, speed_effects) {
+        effects.0.retain(|effect| effect.change != CHILL.change);
+        effects.0.push(CHILL);
     }
 
-    // Spawn hit particles on player
-    spawn_particle(&mut commands, ParticleType::HitSpark, player_transform.translation);
+    if !attacks.is_empty() {
+        player_health.0 -= total_damage_from_enemies;
+
+        // Spawn hit particles on player
+        spawn_particle(&mut commands, ParticleType::HitSpark, player_transform.translation, &mut rng.cosmetic);
+    }
 
-    statistics.damage_taken += total_damage_from_enemies;
+    deaths.statistics.damage_taken += total_damage_from_enemies;
 
     if player_health.0 <= 0 {
         commands.entity(player_entity).despawn();
     }
 
-    // Prefer attacking the targeted enemy if there is one
-    let targeted_entity = targeted_query.iter().next();
-    let targeted_enemy_idx = if let Some(target_entity) = targeted_entity {
-        enemies
-            .iter()
-            .enumerate()
-            .find(|(_, (e, _, _, _, _))| *e == target_entity)
-            .map(|(idx, _)| idx)
-    } else {
-        None
-    };
-
-    let target_idx = if let Some(idx) = targeted_enemy_idx {
-        idx
-    } else {
-        // Fall back to random selection
-        let i: usize = rand::random();
-        i % m
+    // The target may have died or moved out of reach since it was chosen
+    let Some(target_idx) =
+        target.and_then(|target| enemies.iter().position(|(e, ..)| *e == target))
+    else {
+        return;
     };
 
-    let (ref entity, _strength, _position, ref mut health, enemy_transform) = enemies[target_idx];
+    let (ref entity, _strength, enemy_position, ref mut health, enemy_transform, enemy_type, drops, _) =
+        enemies[target_idx];
 
-    health.0 -= player_strength.0;
-    statistics.damage_dealt += player_strength.0;
+    let damage = player_strength.0 + weapon_damage;
+    health.0 -= damage;
+    deaths.statistics.damage_dealt += damage;
 
     // Spawn hit particles on enemy
-    spawn_particle(&mut commands, ParticleType::HitSpark, enemy_transform.translation);
+    spawn_particle(&mut commands, ParticleType::HitSpark, enemy_transform.translation, &mut rng.cosmetic);
 
     if health.0 <= 0 {
         // Spawn death particles
-        spawn_particle(&mut commands, ParticleType::Death, enemy_transform.translation);
+        spawn_particle(&mut commands, ParticleType::Death, enemy_transform.translation, &mut rng.cosmetic);
+
+        deaths.kill(
+            &mut commands,
+            *entity,
+            *enemy_position,
+            Some(enemy_type),
+            drops,
+        );
+    }
+}
+
+/// Whether an enemy at `enemy` is on the player's floor and no more than
+/// `reach` steps from them.
+pub fn within_reach(player: Position, enemy: Position, reach: i64) -> bool {
+    player.z == enemy.z && (player.x - enemy.x).abs() + (player.y - enemy.y).abs() <= reach
+}
+
+/// What it takes to clear away an enemy that died, whether in combat or on
+/// damaging terrain.
+#[derive(SystemParam)]
+pub struct EnemyDeaths<'w, 's> {
+    pub statistics: ResMut<'w, Statistics>,
+    pub map: Res<'w, map::Map>,
+    kills: ResMut<'w, Kills>,
+    sprite_texture: Res<'w, SpriteTexture>,
+    floor: Res<'w, Floor>,
+    health_bars: Query<'w, 's, (Entity, &'static HealthBar)>,
+}
 
-        commands.entity(*entity).despawn();
-        statistics.enemies_killed += 1;
-        for (health_bar_entity, HealthBar(other_entity)) in text_query.iter() {
-            if other_entity == entity {
+impl EnemyDeaths<'_, '_> {
+    /// Despawns the enemy and its health bar, counts the kill and leaves the
+    /// item it drops where it died.
+    pub fn kill(
+        &mut self,
+        commands: &mut Commands,
+        entity: Entity,
+        position: Position,
+        enemy_type: Option<&EnemyType>,
+        drops: Option<&Drops>,
+    ) {
+        commands.entity(entity).despawn();
+        self.statistics.enemies_killed += 1;
+        if let Some(enemy_type) = enemy_type {
+            *self.kills.0.entry(*enemy_type).or_default() += 1;
+        }
+        for (health_bar_entity, HealthBar(other_entity)) in self.health_bars.iter() {
+            if *other_entity == entity {
                 commands.entity(health_bar_entity).despawn();
             }
         }
+        let Some(Drops(id)) = drops else {
+            return;
+        };
+        let texture = &self.sprite_texture.0;
+        match self.map.items.get(id) {
+            Some(item) => spawn_item(commands, position, *id, item, texture, self.floor.0),
+            None => warn!("enemy drops unknown item {}", id.0),
+        }
     }
 }
+
+#[test]
+fn test_within_reach() {
+    let player = Position::new(0, 0, 0);
+    assert!(within_reach(player, Position::new(1, 0, 0), 1));
+    assert!(!within_reach(player, Position::new(1, 1, 0), 1));
+    assert!(within_reach(player, Position::new(1, 1, 0), 2));
+    assert!(within_reach(player, Position::new(0, -2, 0), 2));
+    assert!(!within_reach(player, Position::new(0, 1, 1), 2));
+}
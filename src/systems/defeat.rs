@@ -1,13 +1,90 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 
+use crate::map::{DefeatCondition, Map};
+use crate::resources::{AlliesLost, CurrentRoom, Ticks};
 use crate::{components::*, state::GameState};
 
-pub fn defeat(player_query: Query<&Position, With<Player>>, state: Res<State<GameState>>, mut next_state: ResMut<NextState<GameState>>) {
-    if *state.get() != GameState::Victory
-        && *state.get() != GameState::Menu
-        && *state.get() != GameState::Defeat
-        && player_query.iter().next().is_none()
+/// How long the player has lasted, in which room, and the allies lost so far.
+#[derive(SystemParam)]
+pub struct Record<'w> {
+    current_room: Res<'w, CurrentRoom>,
+    ticks: Res<'w, Ticks>,
+    allies_lost: Res<'w, AlliesLost>,
+}
+
+pub fn defeat(
+    map: Res<Map>,
+    record: Record,
+    player_query: Query<&Position, With<Player>>,
+    enemy_query: Query<&Position, (With<Enemy>, Without<Player>)>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if *state.get() == GameState::Victory
+        || *state.get() == GameState::Menu
+        || *state.get() == GameState::Defeat
     {
+        return;
+    }
+    let threats = Threats {
+        ticks: record.ticks.0,
+        allies_lost: record.allies_lost.0,
+        current_room: record.current_room.0,
+        enemies: enemy_query.iter().copied().collect(),
+    };
+    if player_query.iter().next().is_none() || determine_defeat(&map.defeat_condition, &threats) {
         next_state.set(GameState::Defeat);
     }
 }
+
+/// Everything a defeat condition can depend on.
+struct Threats {
+    ticks: u64,
+    allies_lost: u64,
+    current_room: usize,
+    /// Where the living enemies of the current room are
+    enemies: Vec<Position>,
+}
+
+fn determine_defeat(defeat_condition: &DefeatCondition, threats: &Threats) -> bool {
+    match defeat_condition {
+        DefeatCondition::TimeLimit(ticks) => threats.ticks >= *ticks,
+        DefeatCondition::AllyDeath => threats.allies_lost > 0,
+        DefeatCondition::EnemyArrival(room, position) => {
+            threats.current_room == *room && threats.enemies.contains(position)
+        }
+        DefeatCondition::And(cs) => cs.iter().all(|c| determine_defeat(c, threats)),
+        DefeatCondition::Or(cs) => cs.iter().any(|c| determine_defeat(c, threats)),
+        DefeatCondition::Never => false,
+    }
+}
+
+#[test]
+fn test_determine_defeat() {
+    let threats = Threats {
+        ticks: 300,
+        allies_lost: 0,
+        current_room: 0,
+        enemies: vec![Position::new(1, 2, 0)],
+    };
+    let loses = |condition: DefeatCondition| determine_defeat(&condition, &threats);
+
+    assert!(loses(DefeatCondition::TimeLimit(300)));
+    assert!(!loses(DefeatCondition::TimeLimit(301)));
+    assert!(!loses(DefeatCondition::AllyDeath));
+    let (below, above) = (Position::new(1, 2, 0), Position::new(1, 2, 1));
+    assert!(loses(DefeatCondition::EnemyArrival(0, below)));
+    assert!(!loses(DefeatCondition::EnemyArrival(0, above)));
+    // Enemies of the room on screen don't reach a target in another room
+    assert!(!loses(DefeatCondition::EnemyArrival(1, below)));
+    assert!(loses(DefeatCondition::Or(vec![
+        DefeatCondition::AllyDeath,
+        DefeatCondition::TimeLimit(10),
+    ])));
+    assert!(!loses(DefeatCondition::And(vec![
+        DefeatCondition::AllyDeath,
+        DefeatCondition::TimeLimit(10),
+    ])));
+    assert!(!loses(DefeatCondition::Never));
+}
@@ -8,11 +8,11 @@ pub fn display_health(
     floor: Res<Floor>,
     mut position_health_query: Query<
         (Entity, &Position, &Health, &OriginalHealth),
-        Or<(With<Enemy>, With<Player>)>,
+        Or<(With<Enemy>, With<Player>, With<Ally>)>,
     >,
     mut health_query: Query<
         (&HealthBar, &mut Visibility, &mut Sprite, &mut Transform),
-        (Without<Enemy>, Without<Player>, Without<CameraMarker>),
+        (Without<Enemy>, Without<Player>, Without<Ally>, Without<CameraMarker>),
     >,
 ) {
     for (entity, position, health, original_health) in position_health_query.iter_mut() {
@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::events::OpenDoor;
+use crate::map;
+use crate::resources::*;
+
+/// Unlocks doors the player opened, in the map so they stay open and in the
+/// tile cache so enemies can path through them.
+pub fn open_doors(
+    mut doors: MessageReader<OpenDoor>,
+    mut map: ResMut<map::Map>,
+    current_room: Res<CurrentRoom>,
+    mut tiles: ResMut<Tiles>,
+    mut passables: Query<&mut Passable, With<Tile>>,
+) {
+    for OpenDoor(position) in doors.read() {
+        if let Some(tile) = map.rooms[current_room.0].tiles.get_mut(position) {
+            tile.lock = None;
+        }
+        if let Some(cached_tile) = tiles.0.get_mut(position) {
+            cached_tile.lock = None;
+            cached_tile.passable = cached_tile.kind.passable();
+            if let Ok(mut passable) = passables.get_mut(cached_tile.entity) {
+                passable.0 = cached_tile.passable;
+            }
+        }
+    }
+}
@@ -0,0 +1,668 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::components::*;
+use crate::map::{self, ATLAS_COLUMNS, ATLAS_ROWS};
+use crate::resources::*;
+use crate::state::GameState;
+use crate::systems::setup_play::INITIAL_SCALE_FACTOR;
+use crate::utils::hovered_tile;
+
+/// Size in pixels of a sprite in the tile atlas.
+const TILE_SIZE: f32 = 32.;
+
+const HEALTH_SPRITE: u64 = 64 * 23 + 45;
+const ITEM_SPRITE: u64 = 64 * 45 + 56;
+
+const TOOL_KEYS: [(KeyCode, EditorTool); 8] = [
+    (KeyCode::Digit1, EditorTool::Tile),
+    (KeyCode::Digit2, EditorTool::Kind),
+    (KeyCode::Digit3, EditorTool::Enemy),
+    (KeyCode::Digit4, EditorTool::Health),
+    (KeyCode::Digit5, EditorTool::Start),
+    (KeyCode::Digit6, EditorTool::Arrival),
+    (KeyCode::Digit7, EditorTool::Item),
+    (KeyCode::Digit8, EditorTool::Lock),
+];
+
+const CONTROLS: &str = "Left click=Use tool  Right click=Erase  Middle click=Pick tile\n\
+1-8=Tool  Q/E=Sprite (Shift: row)  K=Tile kind  T=Enemy type  [/]=Wake radius, health or item\n\
+V=Victory  Tab=Next room  N=New room  ,/.=Floor  Arrows=Pan  F2=Save  Esc=Menu";
+
+const CUSTOM_VICTORY: &str =
+    "The victory condition is not one the editor builds, change it in the map file instead";
+
+/// A map with nothing but a floor tile to start on.
+pub fn new_map() -> map::Map {
+    let mut room = map::Room::new(Position::new(0, 0, 0));
+    room.add_tile(Position::new(0, 0, 0), map::Tile::new(960, true));
+    map::Map {
+        version: map::MAP_VERSION,
+        rooms: vec![room],
+        initial_room: 0,
+        player_health: 100,
+        player_strength: 10,
+        player_sprite: 31 * 64 + 20,
+        items: Default::default(),
+        inventory_capacity: map::DEFAULT_INVENTORY_CAPACITY,
+        defeat_condition: Default::default(),
+        victory_condition: map::VictoryCondition::Extermination,
+    }
+}
+
+/// The first `untitled*.json` in `directory` that does not exist yet.
+pub fn untitled_path(directory: impl AsRef<Path>) -> PathBuf {
+    let directory = directory.as_ref();
+    (1..)
+        .map(|i| {
+            if i == 1 {
+                directory.join("untitled.json")
+            } else {
+                directory.join(format!("untitled_{}.json", i))
+            }
+        })
+        .find(|path| !path.exists())
+        .expect("some name is free")
+}
+
+fn save(map: &map::Map, path: &Path) -> std::io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(
+        path,
+        serde_json::to_string_pretty(map).expect("maps serialize"),
+    )
+}
+
+fn next_enemy_type(enemy_type: EnemyType) -> EnemyType {
+    match enemy_type {
+        EnemyType::Skeleton => EnemyType::Orc,
+        EnemyType::Orc => EnemyType::Ghost,
+        EnemyType::Ghost => EnemyType::Skeleton,
+    }
+}
+
+fn next_kind(kind: map::TileKind) -> map::TileKind {
+    let kinds = map::TileKind::ALL;
+    let i = kinds.iter().position(|k| *k == kind).unwrap_or(0);
+    kinds[(i + 1) % kinds.len()]
+}
+
+fn translation(position: Position, z: f32) -> Transform {
+    Transform::from_xyz(
+        (position.x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+        (position.y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+        z,
+    )
+}
+
+fn square(color: Color) -> Sprite {
+    Sprite {
+        color,
+        custom_size: Some(Vec2::splat(TILE_SIZE)),
+        ..default()
+    }
+}
+
+type CameraView = (
+    &'static Camera,
+    &'static GlobalTransform,
+    &'static mut Transform,
+);
+
+/// The camera over the room being edited, and the floor of it shown.
+#[derive(SystemParam)]
+pub struct EditorCamera<'w, 's> {
+    floor: ResMut<'w, Floor>,
+    window: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    camera: Query<'w, 's, CameraView, With<CameraMarker>>,
+}
+
+impl EditorCamera<'_, '_> {
+    /// Shows the floor `position` is on, centred on it.
+    fn look_at(&mut self, position: Position) {
+        self.floor.0 = position.z;
+        if let Some((_, _, mut transform)) = self.camera.iter_mut().next() {
+            transform.translation.x = position.x as f32 * INITIAL_SCALE_FACTOR;
+            transform.translation.y = position.y as f32 * INITIAL_SCALE_FACTOR;
+        }
+    }
+
+    /// The tile of the floor shown under the mouse cursor.
+    fn hovered(&self) -> Option<Position> {
+        self.window
+            .single()
+            .ok()
+            .zip(self.camera.single().ok())
+            .and_then(|(window, (camera, camera_transform, _))| {
+                hovered_tile(
+                    window,
+                    camera,
+                    camera_transform,
+                    INITIAL_SCALE_FACTOR,
+                    self.floor.0,
+                )
+            })
+    }
+}
+
+pub fn enter_editor(
+    mut commands: Commands,
+    map: Res<map::Map>,
+    mut editor: ResMut<Editor>,
+    mut camera: EditorCamera,
+    sprite_texture: Res<SpriteTexture>,
+    asset_server: Res<AssetServer>,
+    mut menu: Query<&mut Visibility, With<Menu>>,
+) {
+    for mut visibility in menu.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+    let start = map.rooms[editor.room].initial_position;
+    camera.look_at(start);
+    editor.drawn = None;
+
+    let (image, layout) = sprite_texture.0.clone();
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font: asset_server.load("fonts/FreeMono.ttf"),
+            font_size: 20.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.0, 1.0, 0.0)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            left: Val::Px(10.),
+            ..default()
+        },
+        EditorUi,
+    ));
+    commands.spawn((
+        ImageNode::from_atlas_image(
+            image,
+            TextureAtlas {
+                layout,
+                index: editor.sprite_index as usize,
+            },
+        ),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            right: Val::Px(10.),
+            width: Val::Px(64.),
+            height: Val::Px(64.),
+            ..default()
+        },
+        EditorUi,
+        EditorPreview,
+    ));
+    commands.spawn((
+        square(Color::srgba(1., 1., 1., 0.3)),
+        translation(start, 0.1),
+        EditorCursor,
+    ));
+}
+
+type EditorEntity = Or<(With<EditorView>, With<EditorUi>, With<EditorCursor>)>;
+
+pub fn exit_editor(mut commands: Commands, entities: Query<Entity, EditorEntity>) {
+    for entity in entities.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+pub fn editor(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut camera: EditorCamera,
+    mut map: ResMut<map::Map>,
+    mut editor: ResMut<Editor>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for (key, tool) in TOOL_KEYS {
+        if keyboard_input.just_pressed(key) {
+            editor.tool = tool;
+        }
+    }
+
+    let atlas_size = (ATLAS_COLUMNS * ATLAS_ROWS) as u64;
+    let step = if shift { ATLAS_COLUMNS as u64 } else { 1 };
+    if keyboard_input.just_pressed(KeyCode::KeyQ) {
+        editor.sprite_index = (editor.sprite_index + atlas_size - step) % atlas_size;
+    }
+    if keyboard_input.just_pressed(KeyCode::KeyE) {
+        editor.sprite_index = (editor.sprite_index + step) % atlas_size;
+    }
+    if keyboard_input.just_pressed(KeyCode::KeyK) {
+        editor.kind = next_kind(editor.kind);
+    }
+    if keyboard_input.just_pressed(KeyCode::KeyT) {
+        editor.enemy_type = next_enemy_type(editor.enemy_type);
+    }
+    for (key, change) in [(KeyCode::BracketLeft, -1), (KeyCode::BracketRight, 1)] {
+        if keyboard_input.just_pressed(key) {
+            match editor.tool {
+                EditorTool::Health => {
+                    editor.health = (editor.health as i64 + change).max(1) as u64;
+                }
+                EditorTool::Item | EditorTool::Lock => {
+                    editor.item = map::ItemId((editor.item.0 as i64 + change).max(0) as u64);
+                }
+                _ => editor.wake_radius = (editor.wake_radius + change).max(0),
+            }
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::KeyV) {
+        editor.victory = VictoryMode::of(&map.victory_condition).map(VictoryMode::next);
+        match editor.victory {
+            Some(mode) => {
+                map.victory_condition = mode.build(&map.victory_condition.arrivals());
+                editor.drawn = None;
+            }
+            None => editor.message = CUSTOM_VICTORY.into(),
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        editor.room = (editor.room + 1) % map.rooms.len();
+        camera.floor.0 = map.rooms[editor.room].initial_position.z;
+    }
+    if keyboard_input.just_pressed(KeyCode::KeyN) {
+        map.rooms
+            .push(map::Room::new(Position::new(0, 0, camera.floor.0)));
+        editor.room = map.rooms.len() - 1;
+        editor.drawn = None;
+    }
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        editor.message = match save(&map, &editor.path) {
+            Ok(()) => {
+                let problems = map.validate();
+                for problem in &problems {
+                    warn!("{}", problem);
+                }
+                if problems.is_empty() {
+                    format!("Saved {}", editor.path.display())
+                } else {
+                    format!(
+                        "Saved {} with {} problems, see the log",
+                        editor.path.display(),
+                        problems.len()
+                    )
+                }
+            }
+            Err(error) => format!("Could not save {}: {}", editor.path.display(), error),
+        };
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::Menu);
+        return;
+    }
+
+    editor.cursor = camera.hovered();
+    let Some(position) = editor.cursor else {
+        return;
+    };
+
+    let room_index = editor.room;
+    let room = &mut map.rooms[room_index];
+    let mut changed = false;
+    if mouse_input.pressed(MouseButton::Left) {
+        let clicked = mouse_input.just_pressed(MouseButton::Left);
+        match editor.tool {
+            EditorTool::Tile => {
+                let tile = map::Tile {
+                    sprite_index: editor.sprite_index,
+                    kind: editor.kind,
+                    lock: None,
+                };
+                if room.tiles.get(&position) != Some(&tile) {
+                    room.add_tile(position, tile);
+                    changed = true;
+                }
+            }
+            EditorTool::Kind if clicked => {
+                if let Some(tile) = room.tiles.get_mut(&position) {
+                    tile.kind = next_kind(tile.kind);
+                    changed = true;
+                }
+            }
+            EditorTool::Enemy if clicked => {
+                room.add_enemy(
+                    position,
+                    map::Enemy {
+                        enemy_type: Some(editor.enemy_type),
+                        random_type: false,
+                        ai_behavior: None,
+                        sprite_index: None,
+                        health: None,
+                        strength: None,
+                        wake_zone: map::Enemy::circular_wake_zone(position, editor.wake_radius),
+                        drops: None,
+                        group: None,
+                    },
+                );
+                changed = true;
+            }
+            EditorTool::Health if clicked => {
+                room.add_health(
+                    position,
+                    map::Health {
+                        sprite_index: HEALTH_SPRITE,
+                        health: editor.health,
+                    },
+                );
+                changed = true;
+            }
+            EditorTool::Item if clicked => {
+                room.add_item(position, editor.item);
+                map.items.entry(editor.item).or_insert_with(|| map::Item {
+                    name: format!("Item {}", editor.item.0),
+                    sprite_index: ITEM_SPRITE,
+                    equipment: None,
+                });
+                changed = true;
+            }
+            EditorTool::Lock if clicked => {
+                if let Some(tile) = room.tiles.get_mut(&position) {
+                    tile.lock = match tile.lock {
+                        Some(_) => None,
+                        None => Some(editor.item),
+                    };
+                    changed = true;
+                }
+            }
+            EditorTool::Start if room.initial_position != position => {
+                room.initial_position = position;
+                map.initial_room = room_index;
+                changed = true;
+            }
+            EditorTool::Arrival if clicked => {
+                let arrival = (room_index, position);
+                changed = edit_arrivals(&mut map, &mut editor, |arrivals| {
+                    if arrivals.contains(&arrival) {
+                        arrivals.retain(|other| *other != arrival);
+                    } else {
+                        arrivals.push(arrival);
+                    }
+                });
+            }
+            _ => {}
+        }
+    } else if mouse_input.pressed(MouseButton::Right) {
+        changed = match editor.tool {
+            EditorTool::Tile | EditorTool::Kind => room.tiles.remove(&position).is_some(),
+            EditorTool::Enemy => room.enemies.remove(&position).is_some(),
+            EditorTool::Health => room.healths.remove(&position).is_some(),
+            EditorTool::Item => room.items.remove(&position).is_some(),
+            EditorTool::Lock => room
+                .tiles
+                .get_mut(&position)
+                .is_some_and(|tile| tile.lock.take().is_some()),
+            EditorTool::Arrival => {
+                let arrival = (room_index, position);
+                map.victory_condition.arrivals().contains(&arrival)
+                    && edit_arrivals(&mut map, &mut editor, |arrivals| {
+                        arrivals.retain(|other| *other != arrival)
+                    })
+            }
+            EditorTool::Start => false,
+        };
+    } else if mouse_input.just_pressed(MouseButton::Middle) {
+        if let Some(tile) = room.tiles.get(&position) {
+            editor.sprite_index = tile.sprite_index;
+            editor.kind = tile.kind;
+            editor.tool = EditorTool::Tile;
+        }
+    }
+    if changed {
+        editor.drawn = None;
+    }
+}
+
+/// Changes the arrival targets of the map's victory condition and rebuilds it
+/// in the editor's mode, which becomes `Arrival` if it was `Extermination`.
+/// Conditions the editor did not build are left alone, with a message.
+fn edit_arrivals(
+    map: &mut map::Map,
+    editor: &mut Editor,
+    edit: impl FnOnce(&mut Vec<(usize, Position)>),
+) -> bool {
+    let Some(mode) = VictoryMode::of(&map.victory_condition) else {
+        editor.victory = None;
+        editor.message = CUSTOM_VICTORY.into();
+        return false;
+    };
+    let mut arrivals = map.victory_condition.arrivals();
+    edit(&mut arrivals);
+    let mode = match mode {
+        VictoryMode::Extermination => VictoryMode::Arrival,
+        mode => mode,
+    };
+    editor.victory = Some(mode);
+    map.victory_condition = mode.build(&arrivals);
+    true
+}
+
+/// Redraws the room and floor being edited whenever either, or the map, changes.
+pub fn draw_editor(
+    mut commands: Commands,
+    map: Res<map::Map>,
+    floor: Res<Floor>,
+    mut editor: ResMut<Editor>,
+    sprite_texture: Res<SpriteTexture>,
+    view: Query<Entity, With<EditorView>>,
+) {
+    if editor.drawn == Some((editor.room, floor.0)) {
+        return;
+    }
+    editor.drawn = Some((editor.room, floor.0));
+    for entity in view.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let (image, layout) = &sprite_texture.0;
+    let atlas_sprite = |index: u64| {
+        Sprite::from_atlas_image(
+            image.clone(),
+            TextureAtlas {
+                layout: layout.clone(),
+                index: index as usize,
+            },
+        )
+    };
+    let on_floor = |position: &&Position| position.z == floor.0;
+    let room = &map.rooms[editor.room];
+
+    for (position, tile) in (&room.tiles).into_iter().filter(|(p, _)| on_floor(p)) {
+        let mut sprite = atlas_sprite(tile.sprite_index);
+        // Tint doors, walls, stairs and terrain so they stand out while editing
+        if tile.lock.is_some() {
+            sprite.color = Color::srgb(1., 0.9, 0.3);
+        } else if !tile.passable() {
+            sprite.color = Color::srgb(1., 0.6, 0.6);
+        } else if tile.kind.is_stairs() {
+            sprite.color = Color::srgb(0.6, 0.8, 1.);
+        } else if tile.kind.damage() > 0 {
+            sprite.color = Color::srgb(1., 0.7, 0.3);
+        } else if tile.kind.movement_cost() > 1 || tile.kind.slippery() {
+            sprite.color = Color::srgb(0.6, 1., 1.);
+        }
+        commands.spawn((sprite, translation(*position, 0.), EditorView));
+    }
+    for (position, enemy) in (&room.enemies).into_iter().filter(|(p, _)| on_floor(p)) {
+        let sprite_index = enemy
+            .sprite_index
+            .unwrap_or_else(|| enemy.enemy_type.unwrap_or_default().sprite_index() as u64);
+        commands.spawn((
+            atlas_sprite(sprite_index),
+            translation(*position, 0.01),
+            EditorView,
+        ));
+        for woken in enemy.wake_zone.iter().filter(on_floor) {
+            commands.spawn((
+                square(Color::srgba(1., 0., 0., 0.15)),
+                translation(*woken, 0.03),
+                EditorView,
+            ));
+        }
+    }
+    for (position, health) in (&room.healths).into_iter().filter(|(p, _)| on_floor(p)) {
+        commands.spawn((
+            atlas_sprite(health.sprite_index),
+            translation(*position, 0.01),
+            EditorView,
+        ));
+    }
+    for (position, item) in (&room.items).into_iter().filter(|(p, _)| on_floor(p)) {
+        let sprite_index = map.items.get(item).map_or(ITEM_SPRITE, |item| item.sprite_index);
+        commands.spawn((
+            atlas_sprite(sprite_index),
+            translation(*position, 0.01),
+            EditorView,
+        ));
+    }
+    for (position, ally) in (&room.allies).into_iter().filter(|(p, _)| on_floor(p)) {
+        commands.spawn((
+            atlas_sprite(ally.sprite_index),
+            translation(*position, 0.01),
+            EditorView,
+        ));
+    }
+    for (position, _) in (&room.connections).into_iter().filter(|(p, _)| on_floor(p)) {
+        commands.spawn((
+            square(Color::srgba(0., 0.5, 1., 0.4)),
+            translation(*position, 0.04),
+            EditorView,
+        ));
+    }
+    let arrivals = map.victory_condition.arrivals();
+    let in_room = arrivals
+        .iter()
+        .filter(|(room, _)| *room == editor.room)
+        .map(|(_, position)| position);
+    for position in in_room.filter(on_floor) {
+        commands.spawn((
+            square(Color::srgba(1., 1., 0., 0.4)),
+            translation(*position, 0.04),
+            EditorView,
+        ));
+    }
+    if room.initial_position.z == floor.0 {
+        commands.spawn((
+            atlas_sprite(map.player_sprite),
+            translation(room.initial_position, 0.02),
+            EditorView,
+        ));
+    }
+}
+
+pub fn update_editor_ui(
+    map: Res<map::Map>,
+    floor: Res<Floor>,
+    editor: Res<Editor>,
+    mut text: Query<&mut Text, With<EditorUi>>,
+    mut preview: Query<&mut ImageNode, With<EditorPreview>>,
+    mut cursor: Query<(&mut Transform, &mut Visibility), With<EditorCursor>>,
+) {
+    if let Ok(mut text) = text.single_mut() {
+        let cursor = editor.cursor.map_or_else(String::new, |position| {
+            format!("  Cursor ({}, {})", position.x, position.y)
+        });
+        **text = format!(
+            "{}  Tool: {:?}  Sprite: {} ({:?})  Enemy: {:?}, wake radius {}  Health: {}  Item: {}\n\
+             Room {}/{}  Floor {}{}  Victory: {}\n{}\n{}",
+            editor.path.display(),
+            editor.tool,
+            editor.sprite_index,
+            editor.kind,
+            editor.enemy_type,
+            editor.wake_radius,
+            editor.health,
+            editor.item.0,
+            editor.room + 1,
+            map.rooms.len(),
+            floor.0,
+            cursor,
+            editor
+                .victory
+                .map_or_else(|| "custom".into(), |mode| format!("{:?}", mode)),
+            CONTROLS,
+            editor.message,
+        );
+    }
+    if let Ok(mut image) = preview.single_mut() {
+        if let Some(atlas) = &mut image.texture_atlas {
+            atlas.index = editor.sprite_index as usize;
+        }
+    }
+    if let Ok((mut transform, mut visibility)) = cursor.single_mut() {
+        match editor.cursor {
+            Some(position) => {
+                *transform = translation(position, 0.1);
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+#[test]
+fn test_editor_keeps_compound_victory_conditions() {
+    use crate::map::{ItemId, VictoryCondition};
+
+    let compound = VictoryCondition::And(vec![
+        VictoryCondition::Region(0, Position::new(0, 0, 0), Position::new(2, 2, 0)),
+        VictoryCondition::Or(vec![
+            VictoryCondition::Arrival(0, Position::new(1, 0, 0)),
+            VictoryCondition::Collect(ItemId(1)),
+        ]),
+        VictoryCondition::Not(Box::new(VictoryCondition::Survive(900))),
+    ]);
+    let map = map::Map {
+        victory_condition: compound.clone(),
+        ..new_map()
+    };
+    let mut world = World::new();
+    world.insert_resource(Editor::new(PathBuf::from("compound.json"), &map));
+    world.insert_resource(map);
+    world.insert_resource(Floor(0));
+    world.insert_resource(ButtonInput::<MouseButton>::default());
+    world.init_resource::<NextState<GameState>>();
+    let mut keyboard_input = ButtonInput::<KeyCode>::default();
+    keyboard_input.press(KeyCode::KeyV);
+    world.insert_resource(keyboard_input);
+    let mut schedule = Schedule::default();
+    schedule.add_systems(editor);
+
+    schedule.run(&mut world);
+    assert!(world.resource::<map::Map>().victory_condition == compound);
+    assert_eq!(world.resource::<Editor>().message, CUSTOM_VICTORY);
+
+    // The Arrival tool leaves it alone too
+    world.resource_scope(|world, mut map: Mut<map::Map>| {
+        let mut editor = world.resource_mut::<Editor>();
+        assert!(!edit_arrivals(&mut map, &mut editor, |arrivals| {
+            arrivals.push((0, Position::new(2, 0, 0)))
+        }));
+    });
+    assert!(world.resource::<map::Map>().victory_condition == compound);
+
+    // Conditions the editor builds still cycle
+    world.resource_mut::<map::Map>().victory_condition =
+        VictoryCondition::Arrival(0, Position::new(1, 0, 0));
+    schedule.run(&mut world);
+    assert!(
+        world.resource::<map::Map>().victory_condition
+            == VictoryCondition::And(vec![
+                VictoryCondition::Extermination,
+                VictoryCondition::Arrival(0, Position::new(1, 0, 0)),
+            ])
+    );
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::map::{self, Item, ItemId};
+use crate::resources::*;
+
+/// The player's speed, which a turn of turn-based play is measured against.
+pub const NORMAL_SPEED: u64 = 100;
+pub const MOVE_COST: u64 = 100;
+pub const ATTACK_COST: u64 = 100;
+pub const WAIT_COST: u64 = 100;
+/// Energy stops building up here, so that idle actors can't save up a flurry
+/// of actions.
+const MAX_ENERGY: u64 = 2 * MOVE_COST;
+/// Nothing slows anyone to a standstill.
+const MIN_SPEED: u64 = 10;
+
+/// What a ghost's blow does to the speed of whoever it hits.
+pub const CHILL: SpeedEffect = SpeedEffect {
+    change: -25,
+    ticks_left: 30,
+};
+
+/// Spends the energy an action costs, and in turn-based play has the world
+/// answer it with a turn.
+pub fn act(cost: u64, energy: &mut Energy, turn_based: bool, pending_turns: &mut PendingTurns) {
+    energy.0 = energy.0.saturating_sub(cost);
+    if turn_based {
+        pending_turns.0.push_back(cost);
+    }
+}
+
+type Actor<'a> = (
+    &'a Speed,
+    &'a mut Energy,
+    Option<&'a mut SpeedEffects>,
+    Option<&'a Equipped>,
+    Has<Player>,
+);
+
+/// An actor's speed with its equipment and speed effects applied.
+pub fn effective_speed(
+    speed: &Speed,
+    effects: Option<&SpeedEffects>,
+    equipped: Option<&Equipped>,
+    items: &BTreeMap<ItemId, Item>,
+) -> u64 {
+    let change = effects.map_or(0, |effects| {
+        effects.0.iter().map(|effect| effect.change).sum::<i64>()
+    }) + equipped.map_or(0, |equipped| equipped.speed(items));
+    speed.0.saturating_add_signed(change).max(MIN_SPEED)
+}
+
+/// Gives the player and enemies their speed in energy and wears speed
+/// effects off. A turn of turn-based play lasts as long as the player takes
+/// to earn back what their action cost, so a faster player gives enemies less
+/// time to act.
+pub fn gain_energy(
+    pace: Res<Pace>,
+    pending_turns: Res<PendingTurns>,
+    map: Res<map::Map>,
+    mut actors: Query<Actor>,
+) {
+    let turn = match *pace {
+        Pace::RealTime => None,
+        Pace::TurnBased => {
+            let player_speed = actors.iter().find(|(.., is_player)| *is_player).map_or(
+                NORMAL_SPEED,
+                |(speed, _, effects, equipped, _)| {
+                    effective_speed(speed, effects, equipped, &map.items)
+                },
+            );
+            let cost = pending_turns.0.front().copied().unwrap_or_default();
+            Some((cost, player_speed))
+        }
+    };
+    for (speed, mut energy, effects, equipped, _) in actors.iter_mut() {
+        let speed = effective_speed(speed, effects.as_deref(), equipped, &map.items);
+        let gained = match turn {
+            None => speed,
+            Some((cost, player_speed)) => speed * cost / player_speed,
+        };
+        energy.0 = (energy.0 + gained).min(MAX_ENERGY);
+        if let Some(mut effects) = effects.filter(|effects| !effects.0.is_empty()) {
+            for effect in effects.0.iter_mut() {
+                effect.ticks_left = effect.ticks_left.saturating_sub(1);
+            }
+            effects.0.retain(|effect| effect.ticks_left > 0);
+        }
+    }
+}
+
+#[test]
+fn test_effective_speed() {
+    use crate::map::Equipment;
+
+    let items = BTreeMap::from([(
+        ItemId(1),
+        Item {
+            name: "Plate armor".into(),
+            sprite_index: 2910,
+            equipment: Some(Equipment::Armor {
+                defense: 3,
+                speed: -20,
+            }),
+        },
+    )]);
+    let speed = Speed(NORMAL_SPEED);
+    let equipped = Equipped {
+        weapon: None,
+        armor: Some(ItemId(1)),
+    };
+    let chilled = SpeedEffects(vec![CHILL]);
+
+    assert_eq!(effective_speed(&speed, None, None, &items), 100);
+    assert_eq!(effective_speed(&speed, None, Some(&equipped), &items), 80);
+    assert_eq!(
+        effective_speed(&speed, Some(&chilled), Some(&equipped), &items),
+        55
+    );
+    let frozen = SpeedEffects(vec![CHILL; 5]);
+    assert_eq!(
+        effective_speed(&speed, Some(&frozen), None, &items),
+        MIN_SPEED
+    );
+}
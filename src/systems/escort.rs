@@ -0,0 +1,114 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::resources::*;
+use crate::systems::energy::ATTACK_COST;
+use crate::systems::terrain::slowdown;
+use crate::systems::walk_enemies::find_shortest_path;
+
+/// Ticks an ally waits between steps.
+const ALLY_STEP_TICKS: u64 = 6;
+
+type Escort<'a> = (Entity, &'a mut Position, &'a mut Health, &'a mut Slowed);
+type Escorting = (With<Ally>, Without<Player>, Without<Enemy>);
+type Hostile = (With<Enemy>, Without<Ally>);
+
+/// Has every enemy next to an ally hurt it once for every attack it has the
+/// energy for, as they do the player in `combat`, and moves allies that fell
+/// behind a step closer to the player.
+pub fn escort(
+    mut commands: Commands,
+    tiles: Res<Tiles>,
+    enemies: Res<Enemies>,
+    player_query: Query<&Position, With<Player>>,
+    mut allies: Query<Escort, Escorting>,
+    mut enemy_query: Query<(&Position, &Strength, &mut Energy), Hostile>,
+    mut losses: AllyLosses,
+) {
+    let Some(player_position) = player_query.iter().next() else {
+        return;
+    };
+    for (entity, mut position, mut health, mut slowed) in allies.iter_mut() {
+        for (enemy_position, strength, mut energy) in enemy_query.iter_mut() {
+            if !enemy_position.is_adjacent_to(*position) {
+                continue;
+            }
+            while energy.0 >= ATTACK_COST {
+                energy.0 -= ATTACK_COST;
+                health.0 -= strength.0;
+            }
+        }
+        if health.0 <= 0 {
+            losses.lose(&mut commands, entity);
+            continue;
+        }
+
+        if slowed.0 > 0 {
+            slowed.0 -= 1;
+            continue;
+        }
+        if position.z != player_position.z
+            || *position == *player_position
+            || position.is_adjacent_to(*player_position)
+        {
+            continue;
+        }
+        let next = find_shortest_path(&tiles, &enemies, *position, *player_position)
+            .and_then(|path| path.front().copied())
+            .filter(|next| next != player_position);
+        if let Some(next) = next {
+            *position = next;
+            slowed.0 = ALLY_STEP_TICKS + slowdown(&tiles, &position);
+        }
+    }
+}
+
+/// The allies lost on the way, and the health bars they leave behind.
+#[derive(SystemParam)]
+pub struct AllyLosses<'w, 's> {
+    allies_lost: ResMut<'w, AlliesLost>,
+    health_bars: Query<'w, 's, (Entity, &'static HealthBar)>,
+}
+
+impl AllyLosses<'_, '_> {
+    fn lose(&mut self, commands: &mut Commands, entity: Entity) {
+        commands.entity(entity).despawn();
+        self.allies_lost.0 += 1;
+        for (health_bar_entity, HealthBar(other_entity)) in self.health_bars.iter() {
+            if *other_entity == entity {
+                commands.entity(health_bar_entity).despawn();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_enemies_spend_energy_to_hurt_allies() {
+    let mut world = World::new();
+    world.insert_resource(Tiles::new());
+    world.insert_resource(Enemies::new());
+    world.init_resource::<AlliesLost>();
+    world.spawn((Player, Position::new(5, 0, 0)));
+    let ally = world
+        .spawn((Ally, Position::new(0, 0, 0), Health(10), Slowed(1)))
+        .id();
+    let enemy = world
+        .spawn((
+            Enemy,
+            Position::new(1, 0, 0),
+            Strength(3),
+            Energy(2 * ATTACK_COST + 1),
+        ))
+        .id();
+    let mut schedule = Schedule::default();
+    schedule.add_systems(escort);
+
+    schedule.run(&mut world);
+    assert_eq!(world.get::<Health>(ally).unwrap().0, 4);
+    assert_eq!(world.get::<Energy>(enemy).unwrap().0, 1);
+
+    // Out of energy, it waits before striking again
+    schedule.run(&mut world);
+    assert_eq!(world.get::<Health>(ally).unwrap().0, 4);
+}
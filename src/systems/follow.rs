@@ -1,30 +1,41 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 
 use crate::components::*;
 use crate::resources::*;
 
-pub fn follow(
-    follow: Res<Follow>,
-    scale_factor: Res<ScaleFactor>,
-    mut floor: ResMut<Floor>,
-    mut player_query: Query<&Position, With<Player>>,
-    mut camera_query: Query<&mut Transform, With<CameraMarker>>,
-) {
-    if let Some((mut transform, position)) = if follow.0 {
-        camera_query.iter_mut().next().and_then(|transform| {
-            player_query
-                .iter_mut()
-                .next()
-                .map(|position| (transform, position))
-        })
-    } else {
-        None
-    } {
-        floor.0 = position.z;
-        *transform = transform.with_translation(Vec3::new(
-            (position.x as f32) * scale_factor.0,
-            (position.y as f32) * scale_factor.0,
-            1.,
-        ));
+/// The camera, and the floor it shows, as they keep up with the player.
+#[derive(SystemParam)]
+pub struct PlayerCamera<'w, 's> {
+    follow: Res<'w, Follow>,
+    scale_factor: Res<'w, ScaleFactor>,
+    pub floor: ResMut<'w, Floor>,
+    transforms: Query<'w, 's, &'static mut Transform, With<CameraMarker>>,
+}
+
+impl PlayerCamera<'_, '_> {
+    /// Shows the floor `position` is on, centred on it.
+    pub fn center_on(&mut self, position: Position) {
+        self.floor.0 = position.z;
+        if let Some(mut transform) = self.transforms.iter_mut().next() {
+            transform.translation = Vec3::new(
+                position.x as f32 * self.scale_factor.0,
+                position.y as f32 * self.scale_factor.0,
+                transform.translation.z,
+            );
+        }
+    }
+
+    /// Centres on `position` unless the player has moved the camera away.
+    pub fn follow(&mut self, position: Position) {
+        if self.follow.0 {
+            self.center_on(position);
+        }
+    }
+}
+
+pub fn follow(mut camera: PlayerCamera, player_query: Query<&Position, With<Player>>) {
+    if let Some(position) = player_query.iter().next() {
+        camera.follow(*position);
     }
 }
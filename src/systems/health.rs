@@ -9,6 +9,7 @@ pub fn health(
     mut healths: ResMut<Healths>,
     mut player_query: Query<(&Position, &mut Health, &Transform), With<Player>>,
     mut statistics: ResMut<Statistics>,
+    mut rng: ResMut<GameRng>,
 ) {
     if let Some((position, mut health, transform)) = player_query.iter_mut().next() {
         if let Some(cached_health) = healths.remove(*position) {
@@ -16,7 +17,12 @@ pub fn health(
             statistics.health_collected += cached_health.health;
 
             // Spawn health pickup particles
-            spawn_particle(&mut commands, ParticleType::HealthPickup, transform.translation);
+            spawn_particle(
+                &mut commands,
+                ParticleType::HealthPickup,
+                transform.translation,
+                &mut rng.cosmetic,
+            );
 
             commands.entity(cached_health.entity).despawn();
         }
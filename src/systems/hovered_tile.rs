@@ -0,0 +1,24 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::components::CameraMarker;
+use crate::resources::*;
+use crate::utils::hovered_tile;
+
+/// Keeps `HoveredTile` on the tile under the mouse cursor, on the floor on
+/// show, for targeting and anything else the player points at.
+pub fn pick_hovered_tile(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<CameraMarker>>,
+    scale_factor: Res<ScaleFactor>,
+    floor: Res<Floor>,
+    mut hovered: ResMut<HoveredTile>,
+) {
+    hovered.0 = window_query
+        .single()
+        .ok()
+        .zip(camera_query.single().ok())
+        .and_then(|(window, (camera, camera_transform))| {
+            hovered_tile(window, camera, camera_transform, scale_factor.0, floor.0)
+        });
+}
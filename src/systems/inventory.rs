@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::map;
+use crate::systems::energy::effective_speed;
+
+/// Picks up the item the player is standing on when they press G, as long as
+/// there's room for it in their inventory.
+pub fn pick_up_items(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut player_query: Query<(&Position, &mut Inventory), With<Player>>,
+    items: Query<(Entity, &Position, &ItemPickup), Without<Player>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::KeyG) {
+        return;
+    }
+    let Some((position, mut inventory)) = player_query.iter_mut().next() else {
+        return;
+    };
+    for (entity, item_position, ItemPickup(item)) in items.iter() {
+        if item_position == position && !inventory.is_full() {
+            inventory.items.push(*item);
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Number keys equip or take off the item in that place in the inventory.
+const EQUIP_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// Equips the weapon or armor picked with a number key, replacing whatever
+/// was in its slot, or takes it off if it was already equipped.
+pub fn equip_items(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    map: Res<map::Map>,
+    mut player_query: Query<(&Inventory, &mut Equipped), With<Player>>,
+) {
+    let Some((inventory, mut equipped)) = player_query.iter_mut().next() else {
+        return;
+    };
+    for (key, item) in EQUIP_KEYS.iter().zip(inventory.items.iter()) {
+        if !keyboard_input.just_pressed(*key) {
+            continue;
+        }
+        if let Some(equipment) = map.items.get(item).and_then(|item| item.equipment) {
+            equipped.toggle(*item, equipment);
+        }
+    }
+}
+
+/// Lists the items the player carries in the inventory panel, numbered for
+/// equipping, along with what their equipment adds up to.
+pub fn display_inventory(
+    map: Res<map::Map>,
+    player_query: Query<(&Inventory, &Equipped, &Speed, &SpeedEffects), With<Player>>,
+    mut text: Query<&mut Text, With<InventoryUi>>,
+) {
+    let (Some((inventory, equipped, speed, effects)), Some(mut text)) =
+        (player_query.iter().next(), text.iter_mut().next())
+    else {
+        return;
+    };
+    let (damage, reach) = equipped.weapon(&map.items);
+    let mut contents = format!(
+        "Inventory {}/{}\nDamage +{}  Reach {}  Defense {}  Speed {}",
+        inventory.items.len(),
+        inventory.capacity,
+        damage,
+        reach,
+        equipped.defense(&map.items),
+        effective_speed(speed, Some(effects), Some(equipped), &map.items)
+    );
+    for (i, item) in inventory.items.iter().enumerate() {
+        let name = map
+            .items
+            .get(item)
+            .map_or_else(|| format!("item {}", item.0), |item| item.name.clone());
+        let marker = if equipped.contains(*item) {
+            " (equipped)"
+        } else {
+            ""
+        };
+        contents.push_str(&format!("\n{} {}{}", i + 1, name, marker));
+    }
+    **text = contents;
+}
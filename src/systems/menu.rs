@@ -1,21 +1,72 @@
+use std::path::PathBuf;
+
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 
-use crate::{components::Menu, map, maps, state::GameState};
+use crate::{
+    components::{Menu, PaceUi},
+    map, maps,
+    resources::{Editor, GameRng, MapFiles, Pace},
+    state::GameState,
+    systems::editor::{new_map, untitled_path},
+};
+
+/// Keys used to pick one of the `MapFiles`, in order.
+pub const MAP_FILE_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
 
+/// Picking a map file with shift held opens it in the editor instead.
 pub fn menu(
     keyboard_input: Res<ButtonInput<KeyCode>>,
     state: Res<State<GameState>>,
-    mut next_state: ResMut<NextState<GameState>>,
-    mut map: ResMut<map::Map>,
+    mut next_game: NextGame,
+    map_files: Res<MapFiles>,
     mut query: Query<&mut Visibility, With<Menu>>,
+    rng: Res<GameRng>,
+    mut pace: PaceChoice,
 ) {
     if state.get() == &GameState::Menu {
+        if keyboard_input.just_pressed(KeyCode::KeyT) {
+            pace.toggle();
+        }
         if keyboard_input.just_pressed(KeyCode::KeyU) {
-            *map = maps::unbeatable();
-            next_state.set(GameState::Playing);
+            next_game.play(maps::unbeatable());
         } else if keyboard_input.just_pressed(KeyCode::KeyV) {
-            *map = maps::avoidance();
-            next_state.set(GameState::Playing);
+            next_game.play(maps::avoidance());
+        } else if keyboard_input.just_pressed(KeyCode::KeyP) {
+            info!("generating a dungeon with seed {}", rng.seed);
+            next_game.play(maps::procedural(rng.seed));
+        } else if keyboard_input.just_pressed(KeyCode::KeyC) {
+            info!("generating a cave with seed {}", rng.seed);
+            next_game.play(maps::caves(rng.seed));
+        } else if keyboard_input.just_pressed(KeyCode::KeyE) {
+            next_game.edit(new_map(), untitled_path(maps::MAPS_DIRECTORY));
+        } else if let Some(path) = MAP_FILE_KEYS
+            .iter()
+            .zip(map_files.0.iter())
+            .find(|(key, _)| keyboard_input.just_pressed(**key))
+            .map(|(_, path)| path)
+        {
+            match maps::from_file(path) {
+                Ok(loaded) => {
+                    if keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+                        // Maps from other formats are saved alongside as JSON
+                        next_game.edit(loaded, path.with_extension("json"));
+                    } else {
+                        next_game.play(loaded);
+                    }
+                }
+                Err(error) => error!("{}", error),
+            }
         }
         for mut visibility in query.iter_mut() {
             *visibility = Visibility::Visible;
@@ -26,3 +77,52 @@ pub fn menu(
         }
     }
 }
+
+/// The map the next game is played or edited on.
+#[derive(SystemParam)]
+pub struct NextGame<'w, 's> {
+    commands: Commands<'w, 's>,
+    map: ResMut<'w, map::Map>,
+    next_state: ResMut<'w, NextState<GameState>>,
+}
+
+impl NextGame<'_, '_> {
+    fn play(&mut self, map: map::Map) {
+        *self.map = map;
+        self.next_state.set(GameState::Playing);
+    }
+
+    /// Opens `map` in the editor, to be saved to `path`.
+    fn edit(&mut self, map: map::Map, path: PathBuf) {
+        self.commands.insert_resource(Editor::new(path, &map));
+        *self.map = map;
+        self.next_state.set(GameState::Editor);
+    }
+}
+
+/// The pace the next game will be played at, and the menu line showing it.
+#[derive(SystemParam)]
+pub struct PaceChoice<'w, 's> {
+    pace: ResMut<'w, Pace>,
+    text: Query<'w, 's, &'static mut Text, With<PaceUi>>,
+}
+
+impl PaceChoice<'_, '_> {
+    fn toggle(&mut self) {
+        *self.pace = match *self.pace {
+            Pace::RealTime => Pace::TurnBased,
+            Pace::TurnBased => Pace::RealTime,
+        };
+        for mut text in self.text.iter_mut() {
+            text.0 = pace_line(*self.pace).into();
+        }
+    }
+}
+
+/// The menu line saying how the next game will be played.
+pub fn pace_line(pace: Pace) -> &'static str {
+    match pace {
+        Pace::RealTime => "Playing in real time, press t for turns",
+        Pace::TurnBased => "Playing turn by turn, press t for real time",
+    }
+}
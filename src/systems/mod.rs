@@ -1,10 +1,18 @@
 mod animate_sprites;
+mod attack;
+mod change_room;
 mod cleanup;
 mod combat;
 mod defeat;
 mod display_health;
+mod doors;
+pub mod editor;
+mod energy;
+mod escort;
 mod follow;
 mod health;
+mod hovered_tile;
+mod inventory;
 mod menu;
 mod move_camera;
 mod move_player;
@@ -17,17 +25,27 @@ mod set_visibility;
 mod setup;
 mod setup_play;
 mod target_indicator;
-mod track_mouse_movement;
+mod terrain;
+mod triggers;
+mod turns;
 mod victory;
 mod walk_enemies;
 
 pub use animate_sprites::animate_sprites;
+pub use attack::player_attacks;
+pub use change_room::change_room;
 pub use cleanup::{cleanup_collected_health, cleanup_dead_enemies};
 pub use combat::combat;
 pub use defeat::defeat;
 pub use display_health::display_health;
+pub use doors::open_doors;
+pub use editor::{draw_editor, editor, enter_editor, exit_editor, update_editor_ui};
+pub use energy::gain_energy;
+pub use escort::escort;
 pub use follow::follow;
 pub use health::health;
+pub use hovered_tile::pick_hovered_tile;
+pub use inventory::{display_inventory, equip_items, pick_up_items};
 pub use menu::menu;
 pub use move_camera::move_camera;
 pub use move_player::move_player;
@@ -38,8 +56,12 @@ pub use restart::restart;
 pub use set_follow::set_follow;
 pub use set_visibility::set_visibility;
 pub use setup::setup;
-pub use setup_play::setup_play;
+pub use setup_play::{roll_seed, setup_play};
 pub use target_indicator::update_target_indicator;
-pub use track_mouse_movement::track_mouse_movement;
-pub use victory::victory;
+pub use terrain::terrain;
+pub use triggers::{
+    change_tiles, fire_triggers, give_items, show_messages, spawn_enemies, wake_groups,
+};
+pub use turns::{end_turn, world_advances};
+pub use victory::{count_ticks, victory};
 pub use walk_enemies::walk_enemies;
@@ -1,62 +1,129 @@
 use bevy::prelude::*;
 
 use crate::components::*;
+use crate::events::{Attack, EnterConnection, OpenDoor};
+use crate::map::Map;
 use crate::resources::*;
+use crate::systems::energy::{act, MOVE_COST, WAIT_COST};
+use crate::systems::terrain::slowdown;
 
+/// Keys that spend the player's turn, in turn-based play.
+const ACTION_KEYS: [KeyCode; 7] = [
+    KeyCode::KeyA,
+    KeyCode::KeyD,
+    KeyCode::KeyW,
+    KeyCode::KeyS,
+    KeyCode::KeyE,
+    KeyCode::KeyQ,
+    KeyCode::Space,
+];
+
+/// Moving and opening doors spend the player's energy, so they can only act
+/// as fast as their speed allows. In turn-based play, moving, opening a door
+/// and waiting with space each take a turn. Walking into an enemy attacks it
+/// instead, which `player_attacks` deals with.
 pub fn move_player(
-    mut query: Query<(Entity, &mut Position), With<Player>>,
+    mut query: Query<(Entity, &mut Position, &mut Slowed, &mut Energy, &Inventory), With<Player>>,
     mut enemies: Query<(&WakeZone, &mut Awake), With<Enemy>>,
     follow: Res<Follow>,
     scale_factor: Res<ScaleFactor>,
     tiles: Res<Tiles>,
     mut floor: ResMut<Floor>,
     mut camera_query: Query<&mut Transform, With<CameraMarker>>,
-    entities: Query<(Entity, &Position, &Passable), Without<Player>>,
+    entities: Query<(Entity, &Position, &Passable, Has<Enemy>), Without<Player>>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
+    map: Res<Map>,
+    current_room: Res<CurrentRoom>,
+    mut connections: MessageWriter<EnterConnection>,
+    mut doors: MessageWriter<OpenDoor>,
+    mut attacks: MessageWriter<Attack>,
+    pace: Res<Pace>,
+    mut pending_turns: ResMut<PendingTurns>,
 ) {
-    if let Some((entity, mut position)) = query.iter_mut().next() {
+    if let Some((entity, mut position, mut slowed, mut energy, inventory)) = query.iter_mut().next()
+    {
+        let turn_based = *pace == Pace::TurnBased;
+        let waits = if slowed.0 > 0 {
+            // Every action waits out the slowdown instead
+            keyboard_input.any_just_pressed(ACTION_KEYS)
+        } else {
+            keyboard_input.just_pressed(KeyCode::Space)
+        };
+        if turn_based && waits && energy.0 >= WAIT_COST {
+            act(WAIT_COST, &mut energy, turn_based, &mut pending_turns);
+        }
+        if slowed.0 > 0 || waits || energy.0 < MOVE_COST {
+            return;
+        }
+        // The player, and the floor shown, only move once the target is known
+        // to be free
         let old_position = *position;
+        let mut target = old_position;
         if keyboard_input.just_pressed(KeyCode::KeyA) {
-            position.x -= 1;
+            target.x -= 1;
         } else if keyboard_input.just_pressed(KeyCode::KeyD) {
-            position.x += 1;
+            target.x += 1;
         } else if keyboard_input.just_pressed(KeyCode::KeyW) {
-            position.y += 1;
+            target.y += 1;
         } else if keyboard_input.just_pressed(KeyCode::KeyS) {
-            position.y -= 1;
-        } else if keyboard_input.just_pressed(KeyCode::KeyE) {
-            position.z += 1;
-            if follow.0 {
-                floor.0 += 1;
-            }
-        } else if keyboard_input.just_pressed(KeyCode::KeyQ) {
-            position.z -= 1;
-            if follow.0 {
-                floor.0 -= 1;
-            }
+            target.y -= 1;
+        } else if keyboard_input.just_pressed(KeyCode::KeyE) && tiles.has_stairs(&target, 1) {
+            target.z += 1;
+        } else if keyboard_input.just_pressed(KeyCode::KeyQ) && tiles.has_stairs(&target, -1) {
+            target.z -= 1;
         }
 
         if tiles
-            .get(&position)
+            .get(&target)
             .map_or_else(|| true, |cached_tile| !cached_tile.passable)
         {
-            *position = old_position;
+            // Walking into a locked door opens it if the player carries its item
+            if let Some(lock) = tiles.get(&target).and_then(|cached_tile| cached_tile.lock) {
+                if inventory.contains(lock) {
+                    doors.write(OpenDoor(target));
+                    act(MOVE_COST, &mut energy, turn_based, &mut pending_turns);
+                }
+            }
             return;
         }
 
-        for (other_entity, other_position, passable) in entities.iter() {
-            if other_entity != entity && *other_position == *position && !passable.0 {
-                *position = old_position;
+        for (other_entity, other_position, passable, is_enemy) in entities.iter() {
+            if other_entity != entity && *other_position == target && !passable.0 {
+                // Walking into an enemy attacks it
+                if is_enemy {
+                    attacks.write(Attack(other_entity));
+                }
                 return;
             }
         }
 
+        // Ice carries the player on until something stops them
+        *position = tiles.slide(
+            target,
+            (target.x - old_position.x, target.y - old_position.y),
+            |next| {
+                !entities.iter().any(|(other_entity, other_position, passable, _)| {
+                    other_entity != entity && other_position == next && !passable.0
+                })
+            },
+        );
+        if *position != old_position {
+            slowed.0 = slowdown(&tiles, &position);
+            act(MOVE_COST, &mut energy, turn_based, &mut pending_turns);
+        }
+
         for (wake_zone, mut wake) in enemies.iter_mut() {
             if wake_zone.0.contains(&position) {
                 wake.0 = true;
             }
         }
 
+        if *position != old_position {
+            if let Some(connection) = map.rooms[current_room.0].connections.get(&position) {
+                connections.write(EnterConnection(*connection));
+            }
+        }
+
         if *position != old_position && follow.0 {
             floor.0 = position.z;
             camera_query.iter_mut().next().map(|mut transform| {
@@ -35,12 +35,13 @@ pub fn on_defeat(
 
         // Statistics display
         let stats_text = format!(
-            "Floors Completed: {}\nEnemies Killed: {}\nDamage Dealt: {}\nDamage Taken: {}\nHealth Collected: {}",
+            "Floors Completed: {}\nEnemies Killed: {}\nDamage Dealt: {}\nDamage Taken: {}\nHealth Collected: {}\nSeed: {}",
             statistics.floors_completed,
             statistics.enemies_killed,
             statistics.damage_dealt,
             statistics.damage_taken,
-            statistics.health_collected
+            statistics.health_collected,
+            statistics.seed
         );
 
         commands.spawn((
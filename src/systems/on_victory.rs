@@ -7,7 +7,7 @@ pub fn on_victory(
     mut commands: Commands,
     window_query: Query<&Window, With<bevy::window::PrimaryWindow>>,
     asset_server: Res<AssetServer>,
-    entities: Query<Entity, Or<(With<Position>, With<HealthBar>)>>,
+    entities: Query<Entity, Or<(With<Position>, With<HealthBar>, With<InventoryUi>, With<MessageUi>)>>,
     statistics: Res<Statistics>,
 ) {
     for entity in entities.iter() {
@@ -35,12 +35,13 @@ pub fn on_victory(
 
         // Statistics display
         let stats_text = format!(
-            "Floors Completed: {}\nEnemies Killed: {}\nDamage Dealt: {}\nDamage Taken: {}\nHealth Collected: {}",
+            "Floors Completed: {}\nEnemies Killed: {}\nDamage Dealt: {}\nDamage Taken: {}\nHealth Collected: {}\nSeed: {}",
             statistics.floors_completed,
             statistics.enemies_killed,
             statistics.damage_dealt,
             statistics.damage_taken,
-            statistics.health_collected
+            statistics.health_collected,
+            statistics.seed
         );
 
         commands.spawn((
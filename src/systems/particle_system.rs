@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+use rand::Rng;
 
 use crate::components::*;
 
@@ -32,6 +33,7 @@ pub fn spawn_particle(
     commands: &mut Commands,
     particle_type: ParticleType,
     position: Vec3,
+    rng: &mut impl Rng,
 ) {
     let (color, size, lifetime, count) = match particle_type {
         ParticleType::HitSpark => (Color::srgb(1.0, 1.0, 0.0), 5.0, 0.3, 3),
@@ -40,8 +42,8 @@ pub fn spawn_particle(
     };
 
     for _ in 0..count {
-        let angle = rand::random::<f32>() * std::f32::consts::TAU;
-        let speed = 50.0 + rand::random::<f32>() * 50.0;
+        let angle = rng.gen::<f32>() * std::f32::consts::TAU;
+        let speed = 50.0 + rng.gen::<f32>() * 50.0;
         let velocity = Vec2::new(angle.cos() * speed, angle.sin() * speed);
 
         commands.spawn((
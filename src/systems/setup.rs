@@ -1,16 +1,26 @@
 use bevy::prelude::*;
 
-use crate::{components::Menu, maps, systems::setup_play::*};
+use crate::{
+    components::{Menu, PaceUi},
+    maps,
+    resources::{MapFiles, Pace},
+    systems::{
+        menu::{pace_line, MAP_FILE_KEYS},
+        setup_play::*,
+    },
+};
 
 pub fn setup(
     mut commands: Commands,
     window_query: Query<&Window, With<bevy::window::PrimaryWindow>>,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    map_files: Res<MapFiles>,
+    pace: Res<Pace>,
 ) {
     if let Ok(window) = window_query.single() {
         let map = maps::unbeatable();
-        let initial_position = map.room.initial_position;
+        let initial_position = map.starting_room().initial_position;
         let tiles_texture_handle = get_tiles_texture_handle(&asset_server, &mut texture_atlases);
         initialize_resources(&mut commands, &map, initial_position, &tiles_texture_handle, None);
         commands
@@ -64,11 +74,87 @@ pub fn setup(
                 },
                 Menu,
             ));
+        commands
+            .spawn((
+                Text::new("Press p for a generated dungeon"),
+                TextFont {
+                    font: asset_server.load("fonts/FreeMono.ttf"),
+                    font_size: 80.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.0, 1.0, 0.0)),
+                Node {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(window.height() - 400.),
+                    left: Val::Px(100.),
+                    ..default()
+                },
+                Menu,
+            ));
+        commands
+            .spawn((
+                Text::new("Press c for a generated cave"),
+                TextFont {
+                    font: asset_server.load("fonts/FreeMono.ttf"),
+                    font_size: 80.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.0, 1.0, 0.0)),
+                Node {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(window.height() - 500.),
+                    left: Val::Px(100.),
+                    ..default()
+                },
+                Menu,
+            ));
+
+        // Maps loaded from disk
+        for (i, path) in map_files.0.iter().take(MAP_FILE_KEYS.len()).enumerate() {
+            let name = path
+                .file_stem()
+                .map_or_else(|| path.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+            commands
+                .spawn((
+                    Text::new(format!("Press {} for {}", i + 1, name)),
+                    TextFont {
+                        font: asset_server.load("fonts/FreeMono.ttf"),
+                        font_size: 40.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.0, 1.0, 0.0)),
+                    Node {
+                        position_type: PositionType::Absolute,
+                        bottom: Val::Px(window.height() - 570. - 50. * i as f32),
+                        left: Val::Px(100.),
+                        ..default()
+                    },
+                    Menu,
+                ));
+        }
+
+        commands.spawn((
+            Text::new(pace_line(*pace)),
+            TextFont {
+                font: asset_server.load("fonts/FreeMono.ttf"),
+                font_size: 40.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.0, 1.0, 0.0)),
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(120.),
+                left: Val::Px(100.),
+                ..default()
+            },
+            PaceUi,
+            Menu,
+        ));
 
         // Controls explanation
         commands
             .spawn((
-                Text::new("Controls: WASD=Move  Mouse=Target Enemy  Click=Attack  V=Avoidance"),
+                Text::new("Controls: WASD=Move  Space=Wait  Mouse=Target Enemy  Click=Attack  V=Avoidance\nP=Generated Dungeon  C=Generated Cave  T=Turns  E=Map Editor  Shift+Number=Edit Map"),
                 TextFont {
                     font: asset_server.load("fonts/FreeMono.ttf"),
                     font_size: 40.0,
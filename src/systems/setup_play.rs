@@ -1,10 +1,14 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use rand::rngs::StdRng;
 
 use crate::components::*;
 use crate::map;
 use crate::resources::*;
+use crate::systems::energy::{MOVE_COST, NORMAL_SPEED};
+use crate::systems::follow::PlayerCamera;
 
-const INITIAL_SCALE_FACTOR: f32 = 50.;
+pub const INITIAL_SCALE_FACTOR: f32 = 50.;
 
 pub fn initialize_resources(
     mut commands: &mut Commands,
@@ -14,10 +18,11 @@ pub fn initialize_resources(
     existing_statistics: Option<Statistics>,
 ) {
     commands.insert_resource(ScaleFactor(INITIAL_SCALE_FACTOR));
-    commands.insert_resource(MousePosition(Vec2::new(0., 0.)));
+    commands.insert_resource(HoveredTile::default());
     commands.insert_resource(ClearColor(Color::srgb(0., 0., 0.)));
     commands.insert_resource(Follow(false));
     commands.insert_resource(Floor(initial_position.z));
+    commands.insert_resource(CurrentRoom(map.initial_room));
     commands.insert_resource(Tiles::new());
     commands.insert_resource(Enemies::new());
     commands.insert_resource(Healths::new());
@@ -54,7 +59,13 @@ pub fn get_tiles_texture_handle(
 ) -> (Handle<Image>, Handle<TextureAtlasLayout>) {
     let tiles_texture_handle = asset_server.load("tiles.png");
     let tiles_texture_atlas =
-        TextureAtlasLayout::from_grid(UVec2::new(32, 32), 64, 48, None, None);
+        TextureAtlasLayout::from_grid(
+            UVec2::new(32, 32),
+            map::ATLAS_COLUMNS,
+            map::ATLAS_ROWS,
+            None,
+            None,
+        );
     let atlas_layout_handle = texture_atlases.add(tiles_texture_atlas);
     (tiles_texture_handle, atlas_layout_handle)
 }
@@ -63,204 +74,28 @@ pub fn setup_play(
     mut commands: Commands,
     test_map: Res<map::Map>,
     asset_server: Res<AssetServer>,
-    scale_factor: Res<ScaleFactor>,
-    mut floor: ResMut<Floor>,
-    mut camera: Query<&mut Transform, With<CameraMarker>>,
-    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
-    mut tiles: ResMut<Tiles>,
-    mut enemies: ResMut<Enemies>,
-    mut healths: ResMut<Healths>,
+    mut camera: PlayerCamera,
+    mut spawner: RoomSpawner,
     statistics: Option<Res<Statistics>>,
 ) {
-    let initial_position = test_map.room.initial_position;
-
-    let (tiles_texture_image, tiles_texture_layout) = get_tiles_texture_handle(&asset_server, &mut texture_atlases);
-
-    let room = test_map.room.clone();
-
-    if let Some(mut transform) = camera.iter_mut().next() {
-        transform.translation = Vec3::new(
-            room.initial_position.x as f32 * scale_factor.0,
-            room.initial_position.y as f32 * scale_factor.0,
-            transform.translation.z,
-        )
-    } else {
-        panic!("no camera");
-    }
+    // Every run starts its random streams afresh, so that its seed replays it
+    let seed = spawner.rng.seed;
+    *spawner.rng = GameRng::new(seed);
+    info!("playing with seed {}", seed);
+    let room = test_map.starting_room().clone();
+    let initial_position = room.initial_position;
 
-    floor.0 = room.initial_position.z;
+    let (tiles_texture_image, tiles_texture_layout) = spawner.sprite_texture.0.clone();
 
-    for (Position { x, y, z }, tile) in (&room.tiles).into_iter() {
-        let entity = commands
-            .spawn((
-                Sprite::from_atlas_image(
-                    tiles_texture_image.clone(),
-                    TextureAtlas {
-                        layout: tiles_texture_layout.clone(),
-                        index: tile.sprite_index as usize,
-                    },
-                ),
-                Transform::from_xyz(
-                    (*x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
-                    (*y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
-                    0.,
-                ),
-                if *z == initial_position.z {
-                    Visibility::Visible
-                } else {
-                    Visibility::Hidden
-                },
-                Position {
-                    x: *x,
-                    y: *y,
-                    z: *z,
-                },
-                Passable(tile.passable),
-                Tile,
-                SpriteIndex(tile.sprite_index as usize),
-                ZLevel(0.),
-            ))
-            .id();
-        tiles.insert(
-            Position {
-                x: *x,
-                y: *y,
-                z: *z,
-            },
-            CachedTile {
-                entity,
-                passable: tile.passable,
-            },
-        );
-    }
+    camera.center_on(initial_position);
 
-    for (Position { x, y, z }, enemy) in (&room.enemies).into_iter() {
-        // Randomize enemy type for variety, scale stats by floor
-        let enemy_type = EnemyType::random();
-        let (health, strength) = enemy_type.get_stats(floor.0.abs());
-        let sprite_idx = enemy_type.sprite_index();
-
-        let mut enemy_entity = commands.spawn((
-            Sprite::from_atlas_image(
-                tiles_texture_image.clone(),
-                TextureAtlas {
-                    layout: tiles_texture_layout.clone(),
-                    index: sprite_idx,
-                },
-            ),
-            Transform::from_xyz(
-                (*x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
-                (*y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
-                0.01,
-            ),
-            if *z == initial_position.z {
-                Visibility::Visible
-            } else {
-                Visibility::Hidden
-            },
-            Position {
-                x: *x,
-                y: *y,
-                z: *z,
-            },
-            Passable(false),
-            WakeZone(enemy.wake_zone.clone()),
-            Awake(false),
-            Health(health),
-            OriginalHealth(health),
-            Strength(strength),
-            Enemy,
-        ));
-
-        // Add remaining components
-        enemy_entity.insert((
-            enemy_type,
-            AIBehavior::for_enemy_type(enemy_type),
-            MovementPath {
-                age: 20,
-                path: None,
-            },
-            SpriteIndex(sprite_idx),
-            ZLevel(0.01),
-        ));
-
-        let enemy_id = enemy_entity.id();
-
-        commands
-            .spawn((
-                Sprite {
-                    color: Color::srgb(0., 1., 0.),
-                    custom_size: Some(Vec2::new(
-                        INITIAL_SCALE_FACTOR as f32 / 2.,
-                        INITIAL_SCALE_FACTOR as f32 / 8.,
-                    )),
-                    ..default()
-                },
-                Transform::from_xyz(
-                    (*x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
-                    (*y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
-                    0.05,
-                ),
-                HealthBar(enemy_id),
-            ));
-        enemies.insert(
-            Position {
-                x: *x,
-                y: *y,
-                z: *z,
-            },
-            enemy_id,
-        );
-    }
-
-    for (Position { x, y, z }, health) in (&room.healths).into_iter() {
-        let health_id = commands
-            .spawn((
-                Sprite::from_atlas_image(
-                    tiles_texture_image.clone(),
-                    TextureAtlas {
-                        layout: tiles_texture_layout.clone(),
-                        index: health.sprite_index as usize,
-                    },
-                ),
-                Transform::from_xyz(
-                    (*x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
-                    (*y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
-                    0.01,
-                ),
-                if *z == initial_position.z {
-                    Visibility::Visible
-                } else {
-                    Visibility::Hidden
-                },
-                Position {
-                    x: *x,
-                    y: *y,
-                    z: *z,
-                },
-                Passable(true),
-                Health(health.health as i64),
-                HealthGain,
-                MovementPath {
-                    age: 20,
-                    path: None,
-                },
-                SpriteIndex(health.sprite_index as usize),
-                ZLevel(0.005),
-            ))
-            .id();
-        healths.insert(
-            Position {
-                x: *x,
-                y: *y,
-                z: *z,
-            },
-            CachedHealth {
-                entity: health_id,
-                health: health.health as i64,
-            },
-        );
-    }
+    commands.insert_resource(Ticks::default());
+    commands.insert_resource(PendingTurns::default());
+    commands.insert_resource(PlayerAttack::default());
+    commands.insert_resource(RoomTicks::default());
+    commands.insert_resource(Kills::default());
+    commands.insert_resource(AlliesLost::default());
+    spawner.spawn_room(&mut commands, &test_map, test_map.initial_room, camera.floor.0);
 
     let player_id = commands
         .spawn((
@@ -283,6 +118,14 @@ pub fn setup_play(
             OriginalHealth(test_map.player_health as i64),
             Strength(test_map.player_strength as i64),
             Passable(false),
+            Slowed::default(),
+            (
+                Speed(NORMAL_SPEED),
+                Energy(MOVE_COST),
+                SpeedEffects::default(),
+            ),
+            Inventory::new(test_map.inventory_capacity as usize),
+            Equipped::default(),
             SpriteIndex(test_map.player_sprite as usize),
             ZLevel(0.02),
         ))
@@ -306,12 +149,392 @@ pub fn setup_play(
             HealthBar(player_id),
         ));
 
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font: asset_server.load("fonts/FreeMono.ttf"),
+            font_size: 20.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.0, 1.0, 0.0)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            right: Val::Px(10.),
+            ..default()
+        },
+        InventoryUi,
+    ));
+
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font: asset_server.load("fonts/FreeMono.ttf"),
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(Color::srgb(1.0, 1.0, 0.6)),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(40.),
+            left: Val::Px(10.),
+            ..default()
+        },
+        MessageUi,
+    ));
+
     // Initialize or update statistics
-    if let Some(stats) = statistics {
+    let mut new_stats = if let Some(stats) = statistics {
         let mut new_stats = stats.clone();
         new_stats.floors_completed += 1;
-        commands.insert_resource(new_stats);
+        new_stats
     } else {
-        commands.insert_resource(Statistics::new());
+        Statistics::new()
+    };
+    new_stats.seed = seed;
+    commands.insert_resource(new_stats);
+}
+
+/// Gives the next run a seed of its own once a run is over. The seed of the
+/// run just played stays in `Statistics` for the end screen.
+pub fn roll_seed(mut rng: ResMut<GameRng>) {
+    *rng = GameRng::new(rand::random());
+}
+
+/// What putting a room on screen takes: the sprites, the position caches it
+/// fills and the random stream that rolls its enemies.
+#[derive(SystemParam)]
+pub struct RoomSpawner<'w> {
+    pub current_room: ResMut<'w, CurrentRoom>,
+    pub sprite_texture: Res<'w, SpriteTexture>,
+    pub rng: ResMut<'w, GameRng>,
+    tiles: ResMut<'w, Tiles>,
+    enemies: ResMut<'w, Enemies>,
+    healths: ResMut<'w, Healths>,
+}
+
+impl RoomSpawner<'_> {
+    /// Empties the position caches of the room being left.
+    pub fn clear(&mut self) {
+        *self.tiles = Tiles::new();
+        *self.enemies = Enemies::new();
+        *self.healths = Healths::new();
+    }
+
+    /// Spawns the tiles, enemies, health pickups, items and allies of room
+    /// `index` of `map`, registering them in the position caches, and makes
+    /// it the current room. Everything spawned is tagged `RoomMember` so it
+    /// can be torn down when the player leaves the room.
+    pub fn spawn_room(
+        &mut self,
+        commands: &mut Commands,
+        map: &map::Map,
+        index: usize,
+        visible_floor: i64,
+    ) {
+        self.current_room.0 = index;
+        let room = &map.rooms[index];
+        let texture = &self.sprite_texture.0;
+        let (tiles_texture_image, tiles_texture_layout) = texture;
+        for (position, tile) in (&room.tiles).into_iter() {
+            spawn_tile(commands, *position, tile, texture, visible_floor, &mut self.tiles);
+        }
+        for (position, enemy) in (&room.enemies).into_iter() {
+            spawn_enemy(
+                commands,
+                *position,
+                enemy,
+                texture,
+                visible_floor,
+                &mut self.enemies,
+                &mut self.rng.map,
+            );
+        }
+
+        for (Position { x, y, z }, health) in (&room.healths).into_iter() {
+            let health_id = commands
+                .spawn((
+                    Sprite::from_atlas_image(
+                        tiles_texture_image.clone(),
+                        TextureAtlas {
+                            layout: tiles_texture_layout.clone(),
+                            index: health.sprite_index as usize,
+                        },
+                    ),
+                    Transform::from_xyz(
+                        (*x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+                        (*y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+                        0.01,
+                    ),
+                    if *z == visible_floor {
+                        Visibility::Visible
+                    } else {
+                        Visibility::Hidden
+                    },
+                    Position {
+                        x: *x,
+                        y: *y,
+                        z: *z,
+                    },
+                    Passable(true),
+                    Health(health.health as i64),
+                    HealthGain,
+                    MovementPath {
+                        age: 20,
+                        path: None,
+                    },
+                    SpriteIndex(health.sprite_index as usize),
+                    ZLevel(0.005),
+                    RoomMember,
+                ))
+                .id();
+            self.healths.insert(
+                Position {
+                    x: *x,
+                    y: *y,
+                    z: *z,
+                },
+                CachedHealth {
+                    entity: health_id,
+                    health: health.health as i64,
+                },
+            );
+        }
+
+        for (position, id) in (&room.items).into_iter() {
+            let Some(item) = map.items.get(id) else {
+                warn!("room holds unknown item {}", id.0);
+                continue;
+            };
+            spawn_item(commands, *position, *id, item, texture, visible_floor);
+        }
+
+        for (position, ally) in (&room.allies).into_iter() {
+            let ally_id = commands
+                .spawn((
+                    Sprite::from_atlas_image(
+                        tiles_texture_image.clone(),
+                        TextureAtlas {
+                            layout: tiles_texture_layout.clone(),
+                            index: ally.sprite_index as usize,
+                        },
+                    ),
+                    Transform::from_xyz(
+                        (position.x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+                        (position.y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+                        0.01,
+                    ),
+                    if position.z == visible_floor {
+                        Visibility::Visible
+                    } else {
+                        Visibility::Hidden
+                    },
+                    *position,
+                    Passable(true),
+                    Health(ally.health as i64),
+                    OriginalHealth(ally.health as i64),
+                    Slowed::default(),
+                    Ally,
+                    SpriteIndex(ally.sprite_index as usize),
+                    ZLevel(0.01),
+                    RoomMember,
+                ))
+                .id();
+            commands.spawn((
+                Sprite {
+                    color: Color::srgb(0., 1., 0.),
+                    custom_size: Some(Vec2::new(
+                        INITIAL_SCALE_FACTOR / 2.,
+                        INITIAL_SCALE_FACTOR / 8.,
+                    )),
+                    ..default()
+                },
+                Transform::from_xyz(
+                    (position.x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+                    (position.y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+                    0.05,
+                ),
+                HealthBar(ally_id),
+                RoomMember,
+            ));
+        }
+    }
+}
+
+/// Spawns a tile of the current room and registers it in the tile cache.
+pub fn spawn_tile(
+    commands: &mut Commands,
+    position: Position,
+    tile: &map::Tile,
+    (tiles_texture_image, tiles_texture_layout): &(Handle<Image>, Handle<TextureAtlasLayout>),
+    visible_floor: i64,
+    tiles: &mut Tiles,
+) {
+    let entity = commands
+        .spawn((
+            Sprite::from_atlas_image(
+                tiles_texture_image.clone(),
+                TextureAtlas {
+                    layout: tiles_texture_layout.clone(),
+                    index: tile.sprite_index as usize,
+                },
+            ),
+            Transform::from_xyz(
+                (position.x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+                (position.y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+                0.,
+            ),
+            if position.z == visible_floor {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            },
+            position,
+            Passable(tile.passable()),
+            Tile,
+            SpriteIndex(tile.sprite_index as usize),
+            ZLevel(0.),
+            RoomMember,
+        ))
+        .id();
+    tiles.insert(
+        position,
+        CachedTile {
+            entity,
+            passable: tile.passable(),
+            kind: tile.kind,
+            lock: tile.lock,
+        },
+    );
+}
+
+/// Spawns an enemy of the current room, with its health bar, and registers
+/// it in the enemy cache.
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    position: Position,
+    enemy: &map::Enemy,
+    (tiles_texture_image, tiles_texture_layout): &(Handle<Image>, Handle<TextureAtlasLayout>),
+    visible_floor: i64,
+    enemies: &mut Enemies,
+    rng: &mut StdRng,
+) {
+    // Authored values win over the type's floor-scaled defaults
+    let enemy_type = enemy.spawn_type(rng);
+    let (type_health, type_strength) = enemy_type.get_stats(visible_floor.abs());
+    let health = enemy.health.map_or(type_health, |health| health as i64);
+    let strength = enemy.strength.map_or(type_strength, |strength| strength as i64);
+    let sprite_idx = enemy
+        .sprite_index
+        .map_or_else(|| enemy_type.sprite_index(), |sprite_index| sprite_index as usize);
+    let ai_behavior = enemy
+        .ai_behavior
+        .unwrap_or_else(|| AIBehavior::for_enemy_type(enemy_type));
+
+    let mut enemy_entity = commands.spawn((
+        Sprite::from_atlas_image(
+            tiles_texture_image.clone(),
+            TextureAtlas {
+                layout: tiles_texture_layout.clone(),
+                index: sprite_idx,
+            },
+        ),
+        Transform::from_xyz(
+            (position.x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+            (position.y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+            0.01,
+        ),
+        if position.z == visible_floor {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        },
+        position,
+        Passable(false),
+        WakeZone(enemy.wake_zone.clone()),
+        Awake(false),
+        Health(health),
+        OriginalHealth(health),
+        Strength(strength),
+        Enemy,
+    ));
+
+    // Add remaining components
+    enemy_entity.insert((
+        enemy_type,
+        ai_behavior,
+        MovementPath {
+            age: 20,
+            path: None,
+        },
+        Slowed::default(),
+        Speed(enemy_type.speed()),
+        Energy::default(),
+        SpriteIndex(sprite_idx),
+        ZLevel(0.01),
+        RoomMember,
+    ));
+    if let Some(item) = enemy.drops {
+        enemy_entity.insert(Drops(item));
     }
+    if let Some(group) = &enemy.group {
+        enemy_entity.insert(Group(group.clone()));
+    }
+
+    let enemy_id = enemy_entity.id();
+
+    commands.spawn((
+        Sprite {
+            color: Color::srgb(0., 1., 0.),
+            custom_size: Some(Vec2::new(
+                INITIAL_SCALE_FACTOR / 2.,
+                INITIAL_SCALE_FACTOR / 8.,
+            )),
+            ..default()
+        },
+        Transform::from_xyz(
+            (position.x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+            (position.y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+            0.05,
+        ),
+        HealthBar(enemy_id),
+        RoomMember,
+    ));
+    enemies.insert(position, enemy_id);
+}
+
+/// Spawns a pickup for `item` lying at `position`, as part of the current room.
+pub fn spawn_item(
+    commands: &mut Commands,
+    position: Position,
+    id: map::ItemId,
+    item: &map::Item,
+    (tiles_texture_image, tiles_texture_layout): &(Handle<Image>, Handle<TextureAtlasLayout>),
+    visible_floor: i64,
+) {
+    commands.spawn((
+        Sprite::from_atlas_image(
+            tiles_texture_image.clone(),
+            TextureAtlas {
+                layout: tiles_texture_layout.clone(),
+                index: item.sprite_index as usize,
+            },
+        ),
+        Transform::from_xyz(
+            (position.x as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+            (position.y as f32 - 0.5) * INITIAL_SCALE_FACTOR,
+            0.01,
+        ),
+        if position.z == visible_floor {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        },
+        position,
+        Passable(true),
+        ItemPickup(id),
+        SpriteIndex(item.sprite_index as usize),
+        ZLevel(0.005),
+        RoomMember,
+    ));
 }
@@ -1,17 +1,22 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 
 use crate::components::*;
+use crate::map;
 use crate::resources::*;
+use crate::systems::combat::within_reach;
 
 /// Updates the target indicator to show which enemy the player will attack
-/// based on mouse position. Highlights the closest adjacent enemy to the cursor.
+/// based on mouse position. Highlights the enemy within the player's reach
+/// closest to the hovered tile, or to the player while the cursor is outside
+/// the window.
 pub fn update_target_indicator(
     mut commands: Commands,
-    player_query: Query<&Position, With<Player>>,
+    map: Res<map::Map>,
+    player_query: Query<(&Position, &Equipped), With<Player>>,
     enemy_query: Query<(Entity, &Position, &Transform), With<Enemy>>,
-    mouse_position: Res<MousePosition>,
-    scale_factor: Res<ScaleFactor>,
-    mut existing_indicator: Query<(Entity, &mut Transform, &mut Visibility), With<TargetIndicator>>,
+    hovered_tile: Res<HoveredTile>,
+    mut indicator: Indicator,
     mut targeted_enemies: Query<Entity, With<TargetedEnemy>>,
 ) {
     // Clear previous targeted markers
@@ -19,67 +24,78 @@ pub fn update_target_indicator(
         commands.entity(entity).remove::<TargetedEnemy>();
     }
 
-    let Some(player_pos) = player_query.iter().next() else {
+    let Some((player_pos, equipped)) = player_query.iter().next() else {
         // Hide indicator if no player
-        if let Some((_, _, mut vis)) = existing_indicator.iter_mut().next() {
-            *vis = Visibility::Hidden;
-        }
+        indicator.hide();
         return;
     };
 
-    // Find adjacent enemies
-    let adjacent_enemies: Vec<(Entity, Position, Vec3)> = enemy_query
+    // Find enemies within reach
+    let (_, reach) = equipped.weapon(&map.items);
+    let enemies_in_reach: Vec<(Entity, Position, Vec3)> = enemy_query
         .iter()
-        .filter(|(_, enemy_pos, _)| enemy_pos.is_adjacent_to(*player_pos))
+        .filter(|(_, enemy_pos, _)| within_reach(*player_pos, **enemy_pos, reach))
         .map(|(e, p, t)| (e, *p, t.translation))
         .collect();
 
-    if adjacent_enemies.is_empty() {
-        // Hide indicator if no adjacent enemies
-        if let Some((_, _, mut vis)) = existing_indicator.iter_mut().next() {
-            *vis = Visibility::Hidden;
-        }
+    if enemies_in_reach.is_empty() {
+        // Hide indicator if no enemies are within reach
+        indicator.hide();
         return;
     }
 
-    // Find closest enemy to mouse cursor
-    let mouse_world_pos = Vec2::new(mouse_position.0.x, mouse_position.0.y);
-    let closest_enemy = adjacent_enemies
+    // Find closest enemy to the hovered tile
+    let pointed_at = hovered_tile.0.unwrap_or(*player_pos);
+    let closest_enemy = enemies_in_reach
         .iter()
-        .min_by(|(_, _, pos_a), (_, _, pos_b)| {
-            let dist_a = mouse_world_pos.distance(Vec2::new(pos_a.x, pos_a.y));
-            let dist_b = mouse_world_pos.distance(Vec2::new(pos_b.x, pos_b.y));
-            dist_a.partial_cmp(&dist_b).unwrap()
-        });
+        .min_by_key(|(_, enemy_pos, _)| distance_squared(pointed_at, *enemy_pos));
 
     if let Some((target_entity, _, target_transform)) = closest_enemy {
         // Mark the targeted enemy
         commands.entity(*target_entity).insert(TargetedEnemy);
 
-        // Update or spawn indicator
-        if let Some((_, mut transform, mut vis)) = existing_indicator.iter_mut().next() {
-            transform.translation = Vec3::new(
-                target_transform.x,
-                target_transform.y + scale_factor.0 * 0.6,
-                0.06,
-            );
+        indicator.show_over(&mut commands, *target_transform);
+    }
+}
+
+type IndicatorOnly = (With<TargetIndicator>, Without<Enemy>);
+
+/// The marker over the targeted enemy.
+#[derive(SystemParam)]
+pub struct Indicator<'w, 's> {
+    scale_factor: Res<'w, ScaleFactor>,
+    existing: Query<'w, 's, (&'static mut Transform, &'static mut Visibility), IndicatorOnly>,
+}
+
+impl Indicator<'_, '_> {
+    fn hide(&mut self) {
+        if let Some((_, mut vis)) = self.existing.iter_mut().next() {
+            *vis = Visibility::Hidden;
+        }
+    }
+
+    /// Moves the indicator over `target`, spawning it the first time.
+    fn show_over(&mut self, commands: &mut Commands, target: Vec3) {
+        let scale_factor = self.scale_factor.0;
+        if let Some((mut transform, mut vis)) = self.existing.iter_mut().next() {
+            transform.translation = Vec3::new(target.x, target.y + scale_factor * 0.6, 0.06);
             *vis = Visibility::Visible;
         } else {
-            // Spawn new indicator
             commands.spawn((
                 Sprite {
                     color: Color::srgb(1.0, 1.0, 0.0),
-                    custom_size: Some(Vec2::new(scale_factor.0 * 0.3, scale_factor.0 * 0.1)),
+                    custom_size: Some(Vec2::new(scale_factor * 0.3, scale_factor * 0.1)),
                     ..default()
                 },
-                Transform::from_xyz(
-                    target_transform.x,
-                    target_transform.y + scale_factor.0 * 0.6,
-                    0.06,
-                ),
+                Transform::from_xyz(target.x, target.y + scale_factor * 0.6, 0.06),
                 Visibility::Visible,
                 TargetIndicator,
             ));
         }
     }
 }
+
+/// The squared distance between two tiles, ignoring their floors.
+fn distance_squared(a: Position, b: Position) -> i64 {
+    (a.x - b.x).pow(2) + (a.y - b.y).pow(2)
+}
@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::resources::*;
+use crate::systems::combat::EnemyDeaths;
+
+/// Ticks spent waiting for each point of movement cost past the first.
+pub const SLOW_TICKS: u64 = 5;
+/// Fixed update ticks in each second of real-time play.
+const TICKS_PER_SECOND: u64 = 30;
+
+/// How long something that just stepped onto `position` has to wait before
+/// moving on.
+pub fn slowdown(tiles: &Tiles, position: &Position) -> u64 {
+    tiles.get(position).map_or(0, |cached_tile| {
+        (cached_tile.kind.movement_cost() - 1) * SLOW_TICKS
+    })
+}
+
+/// The share of a tile's damage per second dealt on `tick`, spread so that
+/// every second deals it in full.
+fn damage_on_tick(damage: i64, tick: u64) -> i64 {
+    let second = TICKS_PER_SECOND as i64;
+    let tick = (tick % TICKS_PER_SECOND) as i64;
+    damage * (tick + 1) / second - damage * tick / second
+}
+
+type Movers<'a> = (
+    Entity,
+    &'a Position,
+    &'a mut Health,
+    &'a mut Slowed,
+    Has<Player>,
+    Option<&'a EnemyType>,
+    Option<&'a Drops>,
+);
+type Movable = Or<(With<Player>, With<Enemy>)>;
+
+/// Applies the terrain under the player and enemies each tick: damaging tiles
+/// hurt whoever stands on them, and anyone slowed gets a tick closer to moving
+/// again. A turn of turn-based play waits out one point of movement cost and
+/// deals a second's damage.
+pub fn terrain(
+    mut commands: Commands,
+    tiles: Res<Tiles>,
+    mut movers: Query<Movers, Movable>,
+    mut deaths: EnemyDeaths,
+    pace: Res<Pace>,
+    ticks: Res<Ticks>,
+) {
+    let waited = match *pace {
+        Pace::RealTime => 1,
+        Pace::TurnBased => SLOW_TICKS,
+    };
+    for (entity, position, mut health, mut slowed, is_player, enemy_type, drops) in
+        movers.iter_mut()
+    {
+        slowed.0 = slowed.0.saturating_sub(waited);
+
+        let damage = tiles
+            .get(position)
+            .map_or(0, |cached_tile| cached_tile.kind.damage());
+        let damage = match *pace {
+            Pace::RealTime => damage_on_tick(damage, ticks.0),
+            Pace::TurnBased => damage,
+        };
+        if damage == 0 {
+            continue;
+        }
+        health.0 -= damage;
+        if is_player {
+            deaths.statistics.damage_taken += damage;
+        }
+        if health.0 <= 0 {
+            if is_player {
+                commands.entity(entity).despawn();
+            } else {
+                deaths.kill(&mut commands, entity, *position, enemy_type, drops);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_damage_is_spread_over_each_second() {
+    for damage in [0, 5, 20, 45] {
+        let dealt: Vec<i64> = (0..2 * TICKS_PER_SECOND)
+            .map(|tick| damage_on_tick(damage, tick))
+            .collect();
+        let most = damage / TICKS_PER_SECOND as i64 + 1;
+        assert!(dealt.iter().all(|dealt| *dealt <= most));
+        for second in dealt.chunks(TICKS_PER_SECOND as usize) {
+            assert_eq!(second.iter().sum::<i64>(), damage);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::events::{ChangeTile, GiveItem, ShowMessage, SpawnEnemy, WakeGroup};
+use crate::map::{self, in_region, TriggerAction, TriggerCondition};
+use crate::resources::*;
+use crate::systems::setup_play::{spawn_enemy, spawn_item, spawn_tile};
+
+/// How long a trigger's message stays on screen, in ticks.
+const MESSAGE_TICKS: u64 = 150;
+
+/// Runs the actions of the current room's triggers whose condition holds,
+/// as messages for the systems below, and removes those triggers from the
+/// map so they never run again.
+pub fn fire_triggers(
+    mut map: ResMut<map::Map>,
+    current_room: Res<CurrentRoom>,
+    room_ticks: Res<RoomTicks>,
+    player_query: Query<&Position, With<Player>>,
+    enemy_query: Query<(&Group, &Health), With<Enemy>>,
+    mut actions: TriggerActions,
+) {
+    let Some(position) = player_query.iter().next() else {
+        return;
+    };
+    let room = &mut map.rooms[current_room.0];
+    let situation = Situation {
+        position: *position,
+        room_ticks: room_ticks.0,
+        living_groups: enemy_query
+            .iter()
+            .filter(|(_, health)| health.0 > 0)
+            .map(|(Group(group), _)| group.as_str())
+            .collect(),
+    };
+    let fired: Vec<usize> = (0..room.triggers.len())
+        .filter(|i| holds(&room.triggers, *i, &situation))
+        .collect();
+    for i in fired.into_iter().rev() {
+        for action in room.triggers.remove(i).actions {
+            actions.run(action);
+        }
+    }
+}
+
+/// The messages trigger actions are carried out through.
+#[derive(SystemParam)]
+pub struct TriggerActions<'w> {
+    spawn_enemies: MessageWriter<'w, SpawnEnemy>,
+    change_tiles: MessageWriter<'w, ChangeTile>,
+    wake_groups: MessageWriter<'w, WakeGroup>,
+    show_messages: MessageWriter<'w, ShowMessage>,
+    give_items: MessageWriter<'w, GiveItem>,
+}
+
+impl TriggerActions<'_> {
+    fn run(&mut self, action: TriggerAction) {
+        match action {
+            TriggerAction::SpawnEnemy(position, enemy) => {
+                self.spawn_enemies.write(SpawnEnemy(position, enemy));
+            }
+            TriggerAction::SetTile(position, tile) => {
+                self.change_tiles.write(ChangeTile(position, tile));
+            }
+            TriggerAction::Wake(group) => {
+                self.wake_groups.write(WakeGroup(group));
+            }
+            TriggerAction::Message(text) => {
+                self.show_messages.write(ShowMessage(text));
+            }
+            TriggerAction::Give(item) => {
+                self.give_items.write(GiveItem(item));
+            }
+        }
+    }
+}
+
+/// Everything a trigger condition can depend on.
+struct Situation<'a> {
+    position: Position,
+    room_ticks: u64,
+    /// The groups of the living enemies in the room
+    living_groups: Vec<&'a str>,
+}
+
+/// Whether the condition of the `i`th trigger holds. A group isn't defeated
+/// while another trigger has yet to spawn some of it.
+fn holds(triggers: &[map::Trigger], i: usize, situation: &Situation) -> bool {
+    match &triggers[i].condition {
+        TriggerCondition::Enter(from, to) => in_region(&situation.position, from, to),
+        TriggerCondition::Defeated(group) => {
+            let pending = triggers
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .flat_map(|(_, trigger)| &trigger.actions)
+                .any(|action| action.spawned_group() == Some(group.as_str()));
+            !pending && !situation.living_groups.contains(&group.as_str())
+        }
+        TriggerCondition::Delay(ticks) => situation.room_ticks >= *ticks,
+    }
+}
+
+pub fn spawn_enemies(
+    mut commands: Commands,
+    mut spawns: MessageReader<SpawnEnemy>,
+    sprite_texture: Res<SpriteTexture>,
+    floor: Res<Floor>,
+    mut enemies: ResMut<Enemies>,
+    mut rng: ResMut<GameRng>,
+) {
+    for SpawnEnemy(position, enemy) in spawns.read() {
+        spawn_enemy(
+            &mut commands,
+            *position,
+            enemy,
+            &sprite_texture.0,
+            floor.0,
+            &mut enemies,
+            &mut rng.map,
+        );
+    }
+}
+
+/// Replaces tiles in the map, so the change lasts, and on screen.
+pub fn change_tiles(
+    mut commands: Commands,
+    mut changes: MessageReader<ChangeTile>,
+    mut map: ResMut<map::Map>,
+    current_room: Res<CurrentRoom>,
+    sprite_texture: Res<SpriteTexture>,
+    floor: Res<Floor>,
+    mut tiles: ResMut<Tiles>,
+) {
+    for ChangeTile(position, tile) in changes.read() {
+        map.rooms[current_room.0].add_tile(*position, tile.clone());
+        if let Some(cached_tile) = tiles.get(position) {
+            commands.entity(cached_tile.entity).despawn();
+        }
+        spawn_tile(
+            &mut commands,
+            *position,
+            tile,
+            &sprite_texture.0,
+            floor.0,
+            &mut tiles,
+        );
+    }
+}
+
+pub fn wake_groups(
+    mut wakes: MessageReader<WakeGroup>,
+    mut enemy_query: Query<(&Group, &mut Awake), With<Enemy>>,
+) {
+    for WakeGroup(woken) in wakes.read() {
+        for (Group(group), mut awake) in enemy_query.iter_mut() {
+            if group == woken {
+                awake.0 = true;
+            }
+        }
+    }
+}
+
+/// Shows the latest trigger message until it times out.
+pub fn show_messages(
+    mut messages: MessageReader<ShowMessage>,
+    mut ticks_left: Local<u64>,
+    mut text: Query<&mut Text, With<MessageUi>>,
+) {
+    let Some(mut text) = text.iter_mut().next() else {
+        return;
+    };
+    if let Some(ShowMessage(message)) = messages.read().last() {
+        text.0 = message.clone();
+        *ticks_left = MESSAGE_TICKS;
+    } else if *ticks_left > 0 {
+        *ticks_left -= 1;
+        if *ticks_left == 0 {
+            text.0.clear();
+        }
+    }
+}
+
+/// Hands items to the player, dropping them at their feet when their
+/// inventory is full.
+pub fn give_items(
+    mut commands: Commands,
+    mut gifts: MessageReader<GiveItem>,
+    map: Res<map::Map>,
+    sprite_texture: Res<SpriteTexture>,
+    floor: Res<Floor>,
+    mut player_query: Query<(&Position, &mut Inventory), With<Player>>,
+) {
+    let Some((position, mut inventory)) = player_query.iter_mut().next() else {
+        return;
+    };
+    for GiveItem(id) in gifts.read() {
+        if !inventory.is_full() {
+            inventory.items.push(*id);
+            continue;
+        }
+        match map.items.get(id) {
+            Some(item) => spawn_item(
+                &mut commands,
+                *position,
+                *id,
+                item,
+                &sprite_texture.0,
+                floor.0,
+            ),
+            None => warn!("trigger gives unknown item {}", id.0),
+        }
+    }
+}
+
+#[test]
+fn test_trigger_conditions() {
+    use crate::map::{Enemy, Trigger};
+
+    let spawn = |group: &str| {
+        let mut enemy = Enemy::new(74, 5, 2, Default::default());
+        enemy.group = Some(group.into());
+        TriggerAction::SpawnEnemy(Position::new(1, 1, 0), enemy)
+    };
+    let triggers = vec![
+        Trigger {
+            condition: TriggerCondition::Enter(Position::new(0, 0, 0), Position::new(2, 2, 0)),
+            actions: vec![spawn("ambush")],
+        },
+        Trigger {
+            condition: TriggerCondition::Defeated("ambush".into()),
+            actions: vec![TriggerAction::Message("Well fought".into())],
+        },
+        Trigger {
+            condition: TriggerCondition::Defeated("guards".into()),
+            actions: vec![spawn("guards")],
+        },
+        Trigger {
+            condition: TriggerCondition::Delay(60),
+            actions: Vec::new(),
+        },
+    ];
+    let situation = Situation {
+        position: Position::new(3, 1, 0),
+        room_ticks: 30,
+        living_groups: vec!["guards"],
+    };
+    let fires = |i| holds(&triggers, i, &situation);
+
+    assert!(!fires(0));
+    // The ambush has yet to spawn, so it can't have been defeated
+    assert!(!fires(1));
+    assert!(!fires(2));
+    assert!(!fires(3));
+    assert!(holds(
+        &triggers[1..],
+        0,
+        &Situation {
+            living_groups: Vec::new(),
+            ..situation
+        }
+    ));
+}
@@ -0,0 +1,39 @@
+use bevy::prelude::*;
+
+use crate::resources::*;
+
+/// Run condition for the systems that move the world on: every tick in real
+/// time, and once for each player action in turn-based play.
+pub fn world_advances(pace: Res<Pace>, pending_turns: Res<PendingTurns>) -> bool {
+    *pace == Pace::RealTime || !pending_turns.0.is_empty()
+}
+
+/// Ends the turn the world has just played.
+pub fn end_turn(mut pending_turns: ResMut<PendingTurns>) {
+    pending_turns.0.pop_front();
+}
+
+#[test]
+fn test_world_advances_once_per_action() {
+    #[derive(Resource, Default)]
+    struct Played(u32);
+
+    fn play(mut played: ResMut<Played>) {
+        played.0 += 1;
+    }
+
+    let mut world = World::new();
+    world.insert_resource(Pace::TurnBased);
+    world.insert_resource(PendingTurns([100, 100].into()));
+    world.init_resource::<Played>();
+    let mut schedule = Schedule::default();
+    schedule.add_systems((play.run_if(world_advances), end_turn.after(play)));
+    for _ in 0..5 {
+        schedule.run(&mut world);
+    }
+    assert_eq!(world.resource::<Played>().0, 2);
+
+    world.insert_resource(Pace::RealTime);
+    schedule.run(&mut world);
+    assert_eq!(world.resource::<Played>().0, 3);
+}
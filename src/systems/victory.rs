@@ -1,41 +1,167 @@
+use std::collections::BTreeMap;
+
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 
 use crate::components::*;
-use crate::map::{Map, VictoryCondition};
+use crate::map::{in_region, ItemId, Map, VictoryCondition};
+use crate::resources::{CurrentRoom, Kills, RoomTicks, Ticks};
 use crate::state::GameState;
 
+pub fn count_ticks(mut ticks: ResMut<Ticks>, mut room_ticks: ResMut<RoomTicks>) {
+    ticks.0 += 1;
+    room_ticks.0 += 1;
+}
+
+/// How far the player has come besides where they stand and what they carry.
+#[derive(SystemParam)]
+pub struct Record<'w> {
+    current_room: Res<'w, CurrentRoom>,
+    ticks: Res<'w, Ticks>,
+    kills: Res<'w, Kills>,
+}
+
 pub fn victory(
     map: Res<Map>,
-    player_query: Query<&Position, With<Player>>,
-    enemy_query: Query<Entity, (With<Enemy>, Without<Player>)>,
+    record: Record,
+    player_query: Query<(&Position, &Inventory), With<Player>>,
+    enemy_query: Query<&Position, (With<Enemy>, Without<Player>)>,
     game_state: Res<State<GameState>>,
     mut next_state: ResMut<NextState<GameState>>,
 ) {
+    let Some((position, inventory)) = player_query.iter().next() else {
+        return;
+    };
+    let progress = Progress {
+        position: *position,
+        items: &inventory.items,
+        enemies: enemy_query.iter().copied().collect(),
+        map: &map,
+        current_room: record.current_room.0,
+        ticks: record.ticks.0,
+        kills: &record.kills.0,
+    };
     if *game_state.get() == GameState::Playing
-        && determine_victory(&map.victory_condition, &player_query, &enemy_query)
+        && determine_victory(&map.victory_condition, &progress)
     {
         next_state.set(GameState::Victory);
     }
 }
 
-fn determine_victory(
-    victory_condition: &VictoryCondition,
-    player: &Query<&Position, With<Player>>,
-    enemy_query: &Query<Entity, (With<Enemy>, Without<Player>)>,
-) -> bool {
-    if let Some(position) = player.iter().next() {
-        match *victory_condition {
-            VictoryCondition::Extermination => enemy_query.iter().next().is_none(),
-            VictoryCondition::Arrival(winning_pos) => position == &winning_pos,
-            VictoryCondition::And(ref cs) => {
-                cs.iter().all(|c| determine_victory(c, player, enemy_query))
-            }
-            VictoryCondition::Or(ref cs) => {
-                cs.iter().any(|c| determine_victory(c, player, enemy_query))
-            }
-            VictoryCondition::Unwinnable => false,
+/// Everything a victory condition can depend on.
+struct Progress<'a> {
+    position: Position,
+    items: &'a [ItemId],
+    /// Where the living enemies of the current room are. Enemies in other
+    /// rooms only live in the map.
+    enemies: Vec<Position>,
+    map: &'a Map,
+    current_room: usize,
+    ticks: u64,
+    kills: &'a BTreeMap<EnemyType, u64>,
+}
+
+impl Progress<'_> {
+    /// Whether every enemy on `floor` is dead, or every enemy at all without
+    /// a floor.
+    fn exterminated(&self, floor: Option<i64>) -> bool {
+        let on_floor = |position: &Position| floor.is_none_or(|z| position.z == z);
+        !self.enemies.iter().any(on_floor)
+            && self
+                .map
+                .rooms
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != self.current_room)
+                .all(|(_, room)| {
+                    !(&room.enemies)
+                        .into_iter()
+                        .any(|(position, _)| on_floor(position))
+                })
+    }
+}
+
+fn determine_victory(victory_condition: &VictoryCondition, progress: &Progress) -> bool {
+    match victory_condition {
+        VictoryCondition::Extermination => progress.exterminated(None),
+        VictoryCondition::FloorExtermination(floor) => progress.exterminated(Some(*floor)),
+        VictoryCondition::Arrival(room, winning_pos) => {
+            progress.current_room == *room && progress.position == *winning_pos
+        }
+        VictoryCondition::Region(room, from, to) => {
+            progress.current_room == *room && in_region(&progress.position, from, to)
+        }
+        VictoryCondition::Collect(item) => progress.items.contains(item),
+        VictoryCondition::Kill { enemy_type, count } => {
+            progress.kills.get(enemy_type).copied().unwrap_or(0) >= *count
         }
-    } else {
-        false
+        VictoryCondition::Survive(ticks) => progress.ticks >= *ticks,
+        VictoryCondition::And(cs) => cs.iter().all(|c| determine_victory(c, progress)),
+        VictoryCondition::Or(cs) => cs.iter().any(|c| determine_victory(c, progress)),
+        VictoryCondition::Not(c) => !determine_victory(c, progress),
+        VictoryCondition::Unwinnable => false,
     }
 }
+
+#[test]
+fn test_determine_victory() {
+    use crate::map::Enemy;
+    use crate::systems::editor::new_map;
+
+    let mut map = new_map();
+    let mut other = map.rooms[0].clone();
+    other.add_enemy(
+        Position::new(0, 0, 1),
+        Enemy::new(74, 5, 2, Default::default()),
+    );
+    map.rooms.push(other);
+    let kills = [(EnemyType::Orc, 2)].into_iter().collect();
+    let progress = Progress {
+        position: Position::new(2, 3, 0),
+        items: &[ItemId(4)],
+        enemies: vec![Position::new(5, 5, 0)],
+        map: &map,
+        current_room: 0,
+        ticks: 90,
+        kills: &kills,
+    };
+    let holds = |condition: VictoryCondition| determine_victory(&condition, &progress);
+
+    assert!(holds(VictoryCondition::Region(
+        0,
+        Position::new(3, 0, 0),
+        Position::new(0, 5, 0)
+    )));
+    assert!(!holds(VictoryCondition::Region(
+        0,
+        Position::new(3, 0, 1),
+        Position::new(0, 5, 1)
+    )));
+    // The same corners in a room the player isn't in
+    assert!(!holds(VictoryCondition::Region(
+        1,
+        Position::new(3, 0, 0),
+        Position::new(0, 5, 0)
+    )));
+    assert!(holds(VictoryCondition::Collect(ItemId(4))));
+    assert!(!holds(VictoryCondition::Collect(ItemId(5))));
+    assert!(holds(VictoryCondition::Kill {
+        enemy_type: EnemyType::Orc,
+        count: 2
+    }));
+    assert!(!holds(VictoryCondition::Kill {
+        enemy_type: EnemyType::Ghost,
+        count: 1
+    }));
+    assert!(holds(VictoryCondition::Arrival(0, Position::new(2, 3, 0))));
+    assert!(!holds(VictoryCondition::Arrival(1, Position::new(2, 3, 0))));
+    assert!(holds(VictoryCondition::Survive(90)));
+    assert!(!holds(VictoryCondition::Survive(91)));
+    assert!(!holds(VictoryCondition::Extermination));
+    assert!(!holds(VictoryCondition::FloorExtermination(0)));
+    assert!(!holds(VictoryCondition::FloorExtermination(1)));
+    assert!(holds(VictoryCondition::FloorExtermination(2)));
+    assert!(holds(VictoryCondition::Not(Box::new(
+        VictoryCondition::Unwinnable
+    ))));
+}
@@ -1,30 +1,56 @@
 use std::collections::BTreeMap;
-use std::collections::BTreeSet;
 use std::collections::VecDeque;
 use std::ops::Add;
 
 use bevy::prelude::*;
-use positioning::pathfinding;
-use positioning::pathfinding::Heuristic;
 use priority_queue::DoublePriorityQueue;
+use rand::Rng;
 
 use crate::components::*;
+use crate::map::TileKind;
 use crate::resources::*;
+use crate::systems::energy::{MOVE_COST, WAIT_COST};
+use crate::systems::terrain::slowdown;
 
 // TODO Make sure enemies don't collide, cause if they do they'll never come unstuck
 // NB Maybe they can't already?
+/// Moves each awake enemy for as long as it has the energy, so fast enemies
+/// may take several steps at once. Enemies next to the player keep their
+/// energy for `combat`.
 pub fn walk_enemies(
     tiles: Res<Tiles>,
     mut enemies_query: Query<
-        (Entity, &mut Position, &Awake, &mut MovementPath, &AIBehavior, &Health, &OriginalHealth),
+        (
+            Entity,
+            &mut Position,
+            &Awake,
+            &mut MovementPath,
+            &AIBehavior,
+            &Health,
+            &OriginalHealth,
+            &mut Slowed,
+            &mut Energy,
+        ),
         (With<Enemy>, Without<Player>),
     >,
     mut enemies: ResMut<Enemies>,
     player: Query<&Position, With<Player>>,
+    mut rng: ResMut<GameRng>,
 ) {
     if let Some(player_position) = player.iter().next() {
-        for (entity, mut position, awake, mut movement_path, ai_behavior, health, original_health) in enemies_query.iter_mut() {
-            if awake.0 {
+        for (
+            entity,
+            mut position,
+            awake,
+            mut movement_path,
+            ai_behavior,
+            health,
+            original_health,
+            mut slowed,
+            mut energy,
+        ) in enemies_query.iter_mut()
+        {
+            while awake.0 && slowed.0 == 0 && energy.0 >= MOVE_COST {
                 let health_fraction = health.0 as f32 / original_health.0 as f32;
                 let distance_to_player = ((*player_position - *position).x.abs() + (*player_position - *position).y.abs()) as f32;
 
@@ -40,16 +66,18 @@ pub fn walk_enemies(
 
                 // attack!
                 if position.is_adjacent_to(*player_position) && !should_retreat {
-                    return;
+                    break;
                 }
                 // random motion
-                if rand::random() && rand::random() && rand::random() {
+                if rng.ai.gen_ratio(1, 8) {
                     let potential_positions: Vec<Position> = position
                         .adjacent()
                         .filter(|neighbor| {
                             tiles
                                 .get(&neighbor)
-                                .map_or_else(|| false, |cached_tile| cached_tile.passable)
+                                .map_or_else(|| false, |cached_tile| {
+                                    cached_tile.passable && cached_tile.kind.damage() == 0
+                                })
                                 && enemies
                                     .enemies_at(*neighbor)
                                     .map_or_else(|| true, |s| s.is_empty())
@@ -68,10 +96,13 @@ pub fn walk_enemies(
                         })
                         .collect();
                     if !potential_positions.is_empty() {
-                        *position = potential_positions
-                            [rand::random::<usize>() % potential_positions.len()];
-                        enemies.insert(*position, entity);
-                        return;
+                        let next_position = potential_positions
+                            [rng.ai.gen_range(0..potential_positions.len())];
+                        if step(&tiles, &mut enemies, entity, &mut position, &mut slowed, next_position) {
+                            movement_path.path = None;
+                        }
+                        energy.0 -= MOVE_COST;
+                        continue;
                     }
                 }
                 if movement_path.age >= 5
@@ -101,7 +132,8 @@ pub fn walk_enemies(
                     }
                     movement_path.age = 0;
                 }
-                movement_path.age += rand::random::<usize>() % 3;
+                movement_path.age += rng.ai.gen_range(0..3);
+                let mut moved = false;
                 if let Some(ref mut path) = &mut movement_path.path {
                     if let Some(next_vertex) = path.pop_front() {
                         let adjacency = position.is_adjacent_to(next_vertex);
@@ -115,8 +147,10 @@ pub fn walk_enemies(
                                 |set| set.contains(&entity) && set.len() == 1 || set.is_empty(),
                             )
                         {
-                            *position = next_vertex;
-                            enemies.insert(next_vertex, entity);
+                            if step(&tiles, &mut enemies, entity, &mut position, &mut slowed, next_vertex) {
+                                movement_path.path = None;
+                            }
+                            moved = true;
                         } else {
                             movement_path.path = None;
                         }
@@ -124,11 +158,34 @@ pub fn walk_enemies(
                         movement_path.path = None;
                     }
                 }
+                if moved {
+                    energy.0 -= MOVE_COST;
+                } else {
+                    energy.0 -= WAIT_COST;
+                    break;
+                }
             }
         }
     }
 }
 
+/// Moves an enemy onto `next`, sliding on from there over ice, and slows it
+/// down if it landed in slow terrain. Returns whether it slid past `next`.
+fn step(
+    tiles: &Tiles,
+    enemies: &mut Enemies,
+    entity: Entity,
+    position: &mut Position,
+    slowed: &mut Slowed,
+    next: Position,
+) -> bool {
+    let direction = (next.x - position.x, next.y - position.y);
+    *position = tiles.slide(next, direction, |other| !enemies.occupied_position(*other));
+    slowed.0 = slowdown(tiles, position);
+    enemies.insert(*position, entity);
+    *position != next
+}
+
 #[derive(Debug, PartialOrd, Ord, PartialEq, Eq, Copy, Clone)]
 enum WithInfinity<I> {
     Normal(I),
@@ -165,31 +222,86 @@ fn with_infinity_test() {
     assert!(x + z < y);
 }
 
-fn find_shortest_path(
+/// What stepping onto a tile costs when planning a path: the time it takes to
+/// cross, plus a steep price for tiles that hurt, so enemies only walk over
+/// them when there's no other way through.
+fn path_cost(kind: TileKind) -> u64 {
+    kind.movement_cost() + 10 * kind.damage() as u64
+}
+
+/// The cheapest path from `starting_position` to `ending_position` over
+/// passable, unoccupied tiles, not including the starting position.
+pub fn find_shortest_path(
     tiles: &Tiles,
     enemies: &Enemies,
     starting_position: Position,
     ending_position: Position,
 ) -> Option<VecDeque<Position>> {
-    fn dist_2d(a: Position, b: Position) -> i64 {
-        ((a.x.abs_diff(b.x).pow(2) + a.y.abs_diff(b.y).pow(2) + a.z.abs_diff(b.z).pow(2)) as f32)
-            .sqrt() as i64
-    }
-    let all_passable_tile_positions: BTreeSet<Position> = tiles
-        .0
-        .iter()
-        .filter_map(|(position, cached_tile)| {
-            if cached_tile.passable && !enemies.occupied_position(*position) {
-                Some(position)
-            } else {
-                None
+    let mut distances: BTreeMap<Position, WithInfinity<u64>> = BTreeMap::new();
+    let mut previous: BTreeMap<Position, Position> = BTreeMap::new();
+    let mut queue = DoublePriorityQueue::new();
+    distances.insert(starting_position, WithInfinity::Normal(0));
+    queue.push(starting_position, WithInfinity::Normal(0));
+
+    while let Some((position, distance)) = queue.pop_min() {
+        if position == ending_position {
+            let mut path = VecDeque::new();
+            let mut current = position;
+            while current != starting_position {
+                path.push_front(current);
+                current = previous[&current];
+            }
+            return Some(path);
+        }
+        for neighbor in position.adjacent() {
+            let cost = match tiles.get(&neighbor) {
+                Some(cached_tile)
+                    if cached_tile.passable && !enemies.occupied_position(neighbor) =>
+                {
+                    WithInfinity::Normal(path_cost(cached_tile.kind))
+                }
+                _ => continue,
+            };
+            let candidate = distance + cost;
+            if candidate < distances.get(&neighbor).copied().unwrap_or_default() {
+                distances.insert(neighbor, candidate);
+                previous.insert(neighbor, position);
+                queue.push(neighbor, candidate);
             }
-        })
-        .copied()
-        .collect();
-    pathfinding::HammingDistance.find_shortest_path(
-        &all_passable_tile_positions,
-        starting_position,
-        ending_position,
-    )
+        }
+    }
+    None
+}
+
+#[test]
+fn test_paths_avoid_lava() {
+    let mut tiles = Tiles::new();
+    let add = |tiles: &mut Tiles, x: i64, y: i64, kind: TileKind| {
+        tiles.insert(
+            Position::new(x, y, 0),
+            CachedTile {
+                entity: Entity::PLACEHOLDER,
+                passable: kind.passable(),
+                kind,
+                lock: None,
+            },
+        );
+    };
+    for x in 0..=4 {
+        add(&mut tiles, x, 0, if x == 2 { TileKind::Lava } else { TileKind::Floor });
+    }
+    let enemies = Enemies::new();
+    let (start, end) = (Position::new(0, 0, 0), Position::new(4, 0, 0));
+
+    // The lava is the only way through
+    let path = find_shortest_path(&tiles, &enemies, start, end).unwrap();
+    assert!(path.contains(&Position::new(2, 0, 0)));
+
+    // Until there's a way around it
+    for x in 0..=4 {
+        add(&mut tiles, x, 1, TileKind::Floor);
+    }
+    let path = find_shortest_path(&tiles, &enemies, start, end).unwrap();
+    assert_eq!(path.back(), Some(&end));
+    assert!(!path.contains(&Position::new(2, 0, 0)));
 }
@@ -2,22 +2,35 @@ use bevy::prelude::*;
 
 use crate::components::Position;
 
-#[allow(unused)]
-pub fn convert_mouse_position_to_world_coordinates(
+/// The tile under `cursor` on `floor`, for sprites laid out `scale_factor`
+/// apart the way `setup_play` spawns them.
+pub fn cursor_to_position(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    scale_factor: f32,
+    floor: i64,
+    cursor: Vec2,
+) -> Option<Position> {
+    let world = camera.viewport_to_world_2d(camera_transform, cursor).ok()?;
+    Some(Position::new(
+        (world.x / scale_factor).floor() as i64 + 1,
+        (world.y / scale_factor).floor() as i64 + 1,
+        floor,
+    ))
+}
+
+/// The tile under the mouse cursor on `floor`, if the cursor is over
+/// `window`. The camera's viewport accounts for the window's size and the
+/// camera's position.
+pub fn hovered_tile(
     window: &Window,
-    transform: &Transform,
-    scaling_factor: f32,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    scale_factor: f32,
     floor: i64,
-    mouse_position: Vec2,
-) -> Position {
-    Vec3::new(
-        transform.translation.x / scaling_factor
-            + (mouse_position.x - window.width() / 2.) / scaling_factor,
-        transform.translation.y / scaling_factor
-            + (mouse_position.y - window.height() / 2.) / scaling_factor,
-        floor as f32,
-    )
-    .into()
+) -> Option<Position> {
+    let cursor = window.cursor_position()?;
+    cursor_to_position(camera, camera_transform, scale_factor, floor, cursor)
 }
 
 pub fn convert_world_coordinates_to_ui_position(
@@ -49,3 +62,38 @@ pub fn move_camera_2d(transform: &mut Transform, scale_factor: f32, by: KeyCode)
     }
     f(transform, g(by, scale_factor));
 }
+
+#[test]
+fn test_cursor_to_position() {
+    use bevy::camera::{CameraProjection, RenderTargetInfo};
+
+    let mut projection = OrthographicProjection::default_2d();
+    projection.update(800., 600.);
+    let mut camera = Camera::default();
+    camera.computed.clip_from_view = projection.get_clip_from_view();
+    camera.computed.target_info = Some(RenderTargetInfo {
+        physical_size: UVec2::new(800, 600),
+        scale_factor: 1.,
+    });
+    // Where the cursor lands `x` right and `y` up from the window's centre,
+    // which shows the point the camera is over
+    let at = |camera_translation: Vec3, x: f32, y: f32| {
+        let camera_transform = GlobalTransform::from_translation(camera_translation);
+        let cursor = Vec2::new(400. + x, 300. - y);
+        cursor_to_position(&camera, &camera_transform, 50., 2, cursor)
+    };
+    let tile = |x, y| Some(Position::new(x, y, 2));
+
+    // Tile (1, 1) covers world coordinates 0 to 50
+    assert_eq!(at(Vec3::ZERO, 25., 25.), tile(1, 1));
+    assert_eq!(at(Vec3::ZERO, 0.5, 0.5), tile(1, 1));
+    assert_eq!(at(Vec3::ZERO, 49.5, 49.5), tile(1, 1));
+    assert_eq!(at(Vec3::ZERO, 50.5, 25.), tile(2, 1));
+    assert_eq!(at(Vec3::ZERO, 25., -0.5), tile(1, 0));
+
+    assert_eq!(at(Vec3::ZERO, -0.5, -0.5), tile(0, 0));
+    assert_eq!(at(Vec3::ZERO, -75., -125.), tile(-1, -2));
+
+    // Panning the camera moves what lies under the same spot on screen
+    assert_eq!(at(Vec3::new(500., -250., 0.), 25., 25.), tile(11, -4));
+}
@@ -0,0 +1,908 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+
+use crate::components::Position;
+use crate::map::{
+    in_region, ItemId, Map, Room, Tile, TriggerAction, TriggerCondition, VictoryCondition,
+    ATLAS_COLUMNS, ATLAS_ROWS,
+};
+
+/// What kind of map entry a problem refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Player,
+    Tile,
+    Enemy,
+    Health,
+    Item,
+    Ally,
+    Connection,
+    EnemyArrival,
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Placement::Player => "player",
+            Placement::Tile => "tile",
+            Placement::Enemy => "enemy",
+            Placement::Health => "health pickup",
+            Placement::Item => "item",
+            Placement::Ally => "ally",
+            Placement::Connection => "connection",
+            Placement::EnemyArrival => "enemy arrival target",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapProblem {
+    MissingInitialRoom {
+        room: usize,
+    },
+    StartNotPassable {
+        room: usize,
+        position: Position,
+    },
+    NotOnPassableTile {
+        placement: Placement,
+        room: usize,
+        position: Position,
+    },
+    ConnectionToMissingRoom {
+        room: usize,
+        position: Position,
+        target: usize,
+    },
+    ArrivalUnreachable {
+        room: usize,
+        position: Position,
+    },
+    RegionUnreachable {
+        room: usize,
+        from: Position,
+        to: Position,
+    },
+    GoalItemUnobtainable {
+        item: ItemId,
+    },
+    EnemyUnreachable {
+        room: usize,
+        position: Position,
+    },
+    StairsLeadNowhere {
+        room: usize,
+        position: Position,
+    },
+    LockWithoutKey {
+        room: usize,
+        position: Position,
+        item: ItemId,
+    },
+    UnknownItem {
+        room: usize,
+        position: Position,
+        item: ItemId,
+    },
+    UnknownGivenItem {
+        room: usize,
+        item: ItemId,
+    },
+    UnknownGroup {
+        room: usize,
+        group: String,
+    },
+    SpriteOutOfAtlas {
+        placement: Placement,
+        location: Option<(usize, Position)>,
+        sprite_index: u64,
+    },
+}
+
+impl MapProblem {
+    /// Whether the game can't even start the map, rather than the map merely
+    /// playing badly.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            MapProblem::MissingInitialRoom { .. } | MapProblem::ConnectionToMissingRoom { .. }
+        )
+    }
+}
+
+fn show(position: &Position) -> String {
+    format!("({}, {}, {})", position.x, position.y, position.z)
+}
+
+impl fmt::Display for MapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapProblem::MissingInitialRoom { room } => {
+                write!(f, "initial room {} does not exist", room)
+            }
+            MapProblem::StartNotPassable { room, position } => write!(
+                f,
+                "room {}: player start {} is not on a passable tile",
+                room,
+                show(position)
+            ),
+            MapProblem::NotOnPassableTile {
+                placement,
+                room,
+                position,
+            } => write!(
+                f,
+                "room {}: {} at {} is not on a passable tile",
+                room,
+                placement,
+                show(position)
+            ),
+            MapProblem::ConnectionToMissingRoom {
+                room,
+                position,
+                target,
+            } => write!(
+                f,
+                "room {}: connection at {} leads to missing room {}",
+                room,
+                show(position),
+                target
+            ),
+            MapProblem::ArrivalUnreachable { room, position } => write!(
+                f,
+                "room {}: arrival target {} cannot be reached",
+                room,
+                show(position)
+            ),
+            MapProblem::RegionUnreachable { room, from, to } => write!(
+                f,
+                "room {}: no part of the region from {} to {} can be reached",
+                room,
+                show(from),
+                show(to)
+            ),
+            MapProblem::GoalItemUnobtainable { item } => {
+                write!(f, "item {} must be collected to win, but cannot be", item.0)
+            }
+            MapProblem::EnemyUnreachable { room, position } => write!(
+                f,
+                "room {}: enemy at {} cannot be reached for extermination",
+                room,
+                show(position)
+            ),
+            MapProblem::StairsLeadNowhere { room, position } => write!(
+                f,
+                "room {}: stairs at {} have no stairs to lead to",
+                room,
+                show(position)
+            ),
+            MapProblem::LockWithoutKey {
+                room,
+                position,
+                item,
+            } => write!(
+                f,
+                "room {}: door at {} is locked with item {}, which no room holds",
+                room,
+                show(position),
+                item.0
+            ),
+            MapProblem::UnknownItem {
+                room,
+                position,
+                item,
+            } => write!(
+                f,
+                "room {}: item {} at {} is not one of the map's items",
+                room,
+                item.0,
+                show(position)
+            ),
+            MapProblem::UnknownGivenItem { room, item } => write!(
+                f,
+                "room {}: a trigger gives item {}, which is not one of the map's items",
+                room, item.0
+            ),
+            MapProblem::UnknownGroup { room, group } => write!(
+                f,
+                "room {}: a trigger refers to group `{}`, which no enemy of the room is in",
+                room, group
+            ),
+            MapProblem::SpriteOutOfAtlas {
+                placement,
+                location,
+                sprite_index,
+            } => {
+                if let Some((room, position)) = location {
+                    write!(f, "room {}: {} at {} ", room, placement, show(position))?;
+                } else {
+                    write!(f, "{} ", placement)?;
+                }
+                write!(
+                    f,
+                    "uses sprite {}, outside the {}x{} atlas",
+                    sprite_index, ATLAS_COLUMNS, ATLAS_ROWS
+                )
+            }
+        }
+    }
+}
+
+fn is_passable(room: &Room, position: &Position) -> bool {
+    room.tiles.get(position).is_some_and(|tile| tile.passable())
+}
+
+fn in_atlas(sprite_index: u64) -> bool {
+    sprite_index < (ATLAS_COLUMNS * ATLAS_ROWS) as u64
+}
+
+/// Whether the player can walk onto `position` while holding `keys`, now or
+/// once a trigger has replaced its tile.
+fn is_open(room: &Room, position: &Position, keys: &BTreeSet<ItemId>) -> bool {
+    let open =
+        |tile: &Tile| tile.kind.passable() && tile.lock.is_none_or(|lock| keys.contains(&lock));
+    room.tiles.get(position).is_some_and(open)
+        || trigger_actions(room).any(|action| {
+            matches!(action, TriggerAction::SetTile(p, tile) if p == position && open(tile))
+        })
+}
+
+fn trigger_actions(room: &Room) -> impl Iterator<Item = &TriggerAction> {
+    room.triggers.iter().flat_map(|trigger| &trigger.actions)
+}
+
+/// Items the room's triggers give or have enemies drop.
+fn trigger_items(room: &Room) -> impl Iterator<Item = ItemId> + '_ {
+    trigger_actions(room).filter_map(|action| match action {
+        TriggerAction::Give(item) => Some(*item),
+        TriggerAction::SpawnEnemy(_, enemy) => enemy.drops,
+        _ => None,
+    })
+}
+
+/// Every (room, position) the player can walk to from the start, moving the
+/// same way `move_player` does, following connections between rooms, and
+/// going through doors once they can reach the item that opens them, lying
+/// in a room, dropped by an enemy or given by a trigger of a room they reach.
+/// Also returns every item they can get.
+fn reachable(map: &Map) -> (BTreeSet<(usize, Position)>, BTreeSet<ItemId>) {
+    let mut keys = BTreeSet::new();
+    loop {
+        let seen = walkable(map, &keys);
+        let rooms: BTreeSet<usize> = seen.iter().map(|(room, _)| *room).collect();
+        let found: BTreeSet<ItemId> = seen
+            .iter()
+            .flat_map(|(room, position)| {
+                let room = &map.rooms[*room];
+                let dropped = room.enemies.get(position).and_then(|enemy| enemy.drops);
+                room.items.get(position).copied().into_iter().chain(dropped)
+            })
+            .chain(
+                rooms
+                    .iter()
+                    .flat_map(|room| trigger_items(&map.rooms[*room])),
+            )
+            .collect();
+        if found == keys {
+            return (seen, keys);
+        }
+        keys = found;
+    }
+}
+
+/// Every (room, position) the player can walk to from the start holding
+/// `keys`.
+fn walkable(map: &Map, keys: &BTreeSet<ItemId>) -> BTreeSet<(usize, Position)> {
+    let mut seen = BTreeSet::new();
+    let start = (map.initial_room, map.starting_room().initial_position);
+    if !is_passable(map.starting_room(), &start.1) {
+        return seen;
+    }
+    let mut queue = VecDeque::from([start]);
+    seen.insert(start);
+    while let Some((room_index, position)) = queue.pop_front() {
+        let room = &map.rooms[room_index];
+        let vertical = [-1, 1]
+            .into_iter()
+            .filter(|dz| room.has_stairs(&position, *dz))
+            .map(|dz| Position::new(position.x, position.y, position.z + dz));
+        let mut next: Vec<(usize, Position)> = position
+            .adjacent()
+            .filter(|neighbor| is_open(room, neighbor, keys))
+            .chain(vertical)
+            .map(|neighbor| (room_index, neighbor))
+            .collect();
+        if let Some(connection) = room.connections.get(&position) {
+            if map
+                .rooms
+                .get(connection.room)
+                .is_some_and(|target| is_open(target, &connection.position, keys))
+            {
+                next.push((connection.room, connection.position));
+            }
+        }
+        for node in next {
+            if seen.insert(node) {
+                queue.push_back(node);
+            }
+        }
+    }
+    seen
+}
+
+/// What the player has to reach or clear to win. Conditions under a `Not`
+/// are things to avoid, so they aren't goals.
+#[derive(Default)]
+struct Goals {
+    arrivals: Vec<(usize, Position)>,
+    regions: Vec<(usize, Position, Position)>,
+    /// Floors to exterminate, or `None` for every enemy in the map
+    exterminations: Vec<Option<i64>>,
+    items: Vec<ItemId>,
+}
+
+impl Goals {
+    fn collect(&mut self, victory_condition: &VictoryCondition) {
+        match victory_condition {
+            VictoryCondition::Arrival(room, position) => self.arrivals.push((*room, *position)),
+            VictoryCondition::Region(room, from, to) => self.regions.push((*room, *from, *to)),
+            VictoryCondition::Extermination => self.exterminations.push(None),
+            VictoryCondition::FloorExtermination(floor) => self.exterminations.push(Some(*floor)),
+            VictoryCondition::Collect(item) => self.items.push(*item),
+            VictoryCondition::Or(cs) | VictoryCondition::And(cs) => {
+                for c in cs {
+                    self.collect(c);
+                }
+            }
+            VictoryCondition::Kill { .. }
+            | VictoryCondition::Survive(_)
+            | VictoryCondition::Not(_)
+            | VictoryCondition::Unwinnable => {}
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.arrivals.is_empty()
+            && self.regions.is_empty()
+            && self.exterminations.is_empty()
+            && self.items.is_empty()
+    }
+}
+
+impl Map {
+    /// Checks the map for mistakes that would make it unplayable or broken.
+    pub fn validate(&self) -> Vec<MapProblem> {
+        let mut problems = Vec::new();
+        if self.rooms.get(self.initial_room).is_none() {
+            problems.push(MapProblem::MissingInitialRoom {
+                room: self.initial_room,
+            });
+            return problems;
+        }
+
+        if !in_atlas(self.player_sprite) {
+            problems.push(MapProblem::SpriteOutOfAtlas {
+                placement: Placement::Player,
+                location: None,
+                sprite_index: self.player_sprite,
+            });
+        }
+
+        let start = self.starting_room().initial_position;
+        if !is_passable(self.starting_room(), &start) {
+            problems.push(MapProblem::StartNotPassable {
+                room: self.initial_room,
+                position: start,
+            });
+        }
+
+        let all_keys: BTreeSet<ItemId> = self
+            .rooms
+            .iter()
+            .flat_map(|room| {
+                let dropped = (&room.enemies).into_iter().filter_map(|(_, enemy)| enemy.drops);
+                (&room.items)
+                    .into_iter()
+                    .map(|(_, item)| *item)
+                    .chain(dropped)
+                    .chain(trigger_items(room))
+            })
+            .collect();
+        for item in self.items.values() {
+            if !in_atlas(item.sprite_index) {
+                problems.push(MapProblem::SpriteOutOfAtlas {
+                    placement: Placement::Item,
+                    location: None,
+                    sprite_index: item.sprite_index,
+                });
+            }
+        }
+        for (i, room) in self.rooms.iter().enumerate() {
+            let mut sprites = Vec::new();
+            for (position, tile) in &room.tiles {
+                sprites.push((Placement::Tile, *position, tile.sprite_index));
+                if (tile.kind.goes_up() && !room.has_stairs(position, 1))
+                    || (tile.kind.goes_down() && !room.has_stairs(position, -1))
+                {
+                    problems.push(MapProblem::StairsLeadNowhere {
+                        room: i,
+                        position: *position,
+                    });
+                }
+                if let Some(item) = tile.lock.filter(|item| !all_keys.contains(item)) {
+                    problems.push(MapProblem::LockWithoutKey {
+                        room: i,
+                        position: *position,
+                        item,
+                    });
+                }
+            }
+            for (position, enemy) in &room.enemies {
+                if !is_passable(room, position) {
+                    problems.push(MapProblem::NotOnPassableTile {
+                        placement: Placement::Enemy,
+                        room: i,
+                        position: *position,
+                    });
+                }
+                if let Some(sprite_index) = enemy.sprite_index {
+                    sprites.push((Placement::Enemy, *position, sprite_index));
+                }
+                if let Some(item) = enemy.drops.filter(|item| !self.items.contains_key(item)) {
+                    problems.push(MapProblem::UnknownItem {
+                        room: i,
+                        position: *position,
+                        item,
+                    });
+                }
+            }
+            for (position, health) in &room.healths {
+                if !is_passable(room, position) {
+                    problems.push(MapProblem::NotOnPassableTile {
+                        placement: Placement::Health,
+                        room: i,
+                        position: *position,
+                    });
+                }
+                sprites.push((Placement::Health, *position, health.sprite_index));
+            }
+            for (position, ally) in &room.allies {
+                if !is_passable(room, position) {
+                    problems.push(MapProblem::NotOnPassableTile {
+                        placement: Placement::Ally,
+                        room: i,
+                        position: *position,
+                    });
+                }
+                sprites.push((Placement::Ally, *position, ally.sprite_index));
+            }
+            for (position, item) in &room.items {
+                if !is_passable(room, position) {
+                    problems.push(MapProblem::NotOnPassableTile {
+                        placement: Placement::Item,
+                        room: i,
+                        position: *position,
+                    });
+                }
+                if !self.items.contains_key(item) {
+                    problems.push(MapProblem::UnknownItem {
+                        room: i,
+                        position: *position,
+                        item: *item,
+                    });
+                }
+            }
+            for (position, connection) in &room.connections {
+                if !is_open(room, position, &all_keys) {
+                    problems.push(MapProblem::NotOnPassableTile {
+                        placement: Placement::Connection,
+                        room: i,
+                        position: *position,
+                    });
+                }
+                if self.rooms.get(connection.room).is_none() {
+                    problems.push(MapProblem::ConnectionToMissingRoom {
+                        room: i,
+                        position: *position,
+                        target: connection.room,
+                    });
+                }
+            }
+            let groups: BTreeSet<&str> = (&room.enemies)
+                .into_iter()
+                .filter_map(|(_, enemy)| enemy.group.as_deref())
+                .chain(trigger_actions(room).filter_map(TriggerAction::spawned_group))
+                .collect();
+            for trigger in &room.triggers {
+                if let TriggerCondition::Defeated(group) = &trigger.condition {
+                    if !groups.contains(group.as_str()) {
+                        problems.push(MapProblem::UnknownGroup {
+                            room: i,
+                            group: group.clone(),
+                        });
+                    }
+                }
+                for action in &trigger.actions {
+                    match action {
+                        TriggerAction::SpawnEnemy(position, enemy) => {
+                            if !is_open(room, position, &all_keys) {
+                                problems.push(MapProblem::NotOnPassableTile {
+                                    placement: Placement::Enemy,
+                                    room: i,
+                                    position: *position,
+                                });
+                            }
+                            if let Some(sprite_index) = enemy.sprite_index {
+                                sprites.push((Placement::Enemy, *position, sprite_index));
+                            }
+                            if let Some(item) =
+                                enemy.drops.filter(|item| !self.items.contains_key(item))
+                            {
+                                problems.push(MapProblem::UnknownItem {
+                                    room: i,
+                                    position: *position,
+                                    item,
+                                });
+                            }
+                        }
+                        TriggerAction::SetTile(position, tile) => {
+                            sprites.push((Placement::Tile, *position, tile.sprite_index));
+                        }
+                        TriggerAction::Wake(group) if !groups.contains(group.as_str()) => {
+                            problems.push(MapProblem::UnknownGroup {
+                                room: i,
+                                group: group.clone(),
+                            });
+                        }
+                        TriggerAction::Give(item) if !self.items.contains_key(item) => {
+                            problems.push(MapProblem::UnknownGivenItem {
+                                room: i,
+                                item: *item,
+                            });
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            for (placement, position, sprite_index) in sprites {
+                if !in_atlas(sprite_index) {
+                    problems.push(MapProblem::SpriteOutOfAtlas {
+                        placement,
+                        location: Some((i, position)),
+                        sprite_index,
+                    });
+                }
+            }
+        }
+
+        for (room, position) in self.defeat_condition.enemy_arrivals() {
+            if !self
+                .rooms
+                .get(room)
+                .is_some_and(|target| is_passable(target, &position))
+            {
+                problems.push(MapProblem::NotOnPassableTile {
+                    placement: Placement::EnemyArrival,
+                    room,
+                    position,
+                });
+            }
+        }
+
+        let mut goals = Goals::default();
+        goals.collect(&self.victory_condition);
+        if goals.is_empty() {
+            return problems;
+        }
+
+        let (reachable, obtainable) = reachable(self);
+        for (room, position) in goals.arrivals {
+            if !reachable.contains(&(room, position)) {
+                problems.push(MapProblem::ArrivalUnreachable { room, position });
+            }
+        }
+        for (room, from, to) in goals.regions {
+            if !reachable
+                .iter()
+                .any(|(i, reached)| *i == room && in_region(reached, &from, &to))
+            {
+                problems.push(MapProblem::RegionUnreachable { room, from, to });
+            }
+        }
+        for item in goals.items {
+            if !obtainable.contains(&item) {
+                problems.push(MapProblem::GoalItemUnobtainable { item });
+            }
+        }
+        for (i, room) in self.rooms.iter().enumerate() {
+            for (position, _) in &room.enemies {
+                if !goals
+                    .exterminations
+                    .iter()
+                    .any(|floor| floor.is_none_or(|z| position.z == z))
+                {
+                    continue;
+                }
+                // The player fights from an adjacent tile
+                if !position
+                    .adjacent()
+                    .any(|neighbor| reachable.contains(&(i, neighbor)))
+                {
+                    problems.push(MapProblem::EnemyUnreachable {
+                        room: i,
+                        position: *position,
+                    });
+                }
+            }
+        }
+        problems
+    }
+}
+
+#[test]
+fn test_validate_reports_problems() {
+    use crate::map::{Enemy, Health, Tile, TileKind, UP_STAIRS_SPRITE};
+
+    let mut room = Room::new(Position::new(0, 0, 0));
+    room.add_tile(
+        Position::new(0, 0, 0),
+        Tile {
+            sprite_index: UP_STAIRS_SPRITE,
+            kind: TileKind::UpStairs,
+            lock: None,
+        },
+    )
+    .add_tile(Position::new(1, 0, 0), Tile::new(960, true))
+    .add_tile(Position::new(2, 0, 0), Tile::new(947, false))
+    .add_tile(Position::new(3, 0, 0), Tile::new(960, true))
+    .add_tile(Position::new(4, 0, 0), Tile::new(5000, true))
+    .add_enemy(
+        Position::new(4, 0, 0),
+        Enemy::new(74, 1, 1, BTreeSet::new()),
+    )
+    .add_health(
+        Position::new(2, 0, 0),
+        Health {
+            sprite_index: 1517,
+            health: 1,
+        },
+    );
+    let map = Map {
+        victory_condition: VictoryCondition::And(vec![
+            VictoryCondition::Extermination,
+            VictoryCondition::Arrival(0, Position::new(3, 0, 0)),
+        ]),
+        ..Map::single_room(room)
+    };
+
+    assert_eq!(
+        map.validate(),
+        vec![
+            MapProblem::StairsLeadNowhere {
+                room: 0,
+                position: Position::new(0, 0, 0),
+            },
+            MapProblem::NotOnPassableTile {
+                placement: Placement::Health,
+                room: 0,
+                position: Position::new(2, 0, 0),
+            },
+            MapProblem::SpriteOutOfAtlas {
+                placement: Placement::Tile,
+                location: Some((0, Position::new(4, 0, 0))),
+                sprite_index: 5000,
+            },
+            MapProblem::ArrivalUnreachable {
+                room: 0,
+                position: Position::new(3, 0, 0),
+            },
+            MapProblem::EnemyUnreachable {
+                room: 0,
+                position: Position::new(4, 0, 0),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_validate_locked_doors() {
+    use crate::map::{Item, Tile};
+
+    let mut room = Room::new(Position::new(0, 0, 0));
+    room.add_tile(Position::new(0, 0, 0), Tile::new(960, true))
+        .add_tile(
+            Position::new(1, 0, 0),
+            Tile {
+                lock: Some(ItemId(1)),
+                ..Tile::new(960, true)
+            },
+        )
+        .add_tile(Position::new(2, 0, 0), Tile::new(960, true))
+        .add_tile(Position::new(0, 1, 0), Tile::new(960, true));
+    let mut map = Map {
+        victory_condition: VictoryCondition::Arrival(0, Position::new(2, 0, 0)),
+        ..Map::single_room(room)
+    };
+
+    assert_eq!(
+        map.validate(),
+        vec![
+            MapProblem::LockWithoutKey {
+                room: 0,
+                position: Position::new(1, 0, 0),
+                item: ItemId(1),
+            },
+            MapProblem::ArrivalUnreachable {
+                room: 0,
+                position: Position::new(2, 0, 0),
+            },
+        ]
+    );
+
+    map.rooms[0].add_item(Position::new(0, 1, 0), ItemId(1));
+    assert_eq!(
+        map.validate(),
+        vec![MapProblem::UnknownItem {
+            room: 0,
+            position: Position::new(0, 1, 0),
+            item: ItemId(1),
+        }]
+    );
+
+    map.items.insert(
+        ItemId(1),
+        Item {
+            name: "Key".into(),
+            sprite_index: 1400,
+            equipment: None,
+        },
+    );
+    assert_eq!(map.validate(), vec![]);
+
+    // The same position in a room the player never enters is no goal
+    map.rooms.push(map.rooms[0].clone());
+    map.victory_condition = VictoryCondition::Arrival(1, Position::new(2, 0, 0));
+    assert_eq!(
+        map.validate(),
+        vec![MapProblem::ArrivalUnreachable {
+            room: 1,
+            position: Position::new(2, 0, 0),
+        }]
+    );
+    map.victory_condition =
+        VictoryCondition::Region(1, Position::new(2, 0, 0), Position::new(5, 5, 0));
+    assert_eq!(
+        map.validate(),
+        vec![MapProblem::RegionUnreachable {
+            room: 1,
+            from: Position::new(2, 0, 0),
+            to: Position::new(5, 5, 0),
+        }]
+    );
+    map.rooms.pop();
+
+    map.victory_condition = VictoryCondition::And(vec![
+        VictoryCondition::Region(0, Position::new(2, 0, 0), Position::new(5, 5, 0)),
+        VictoryCondition::Region(0, Position::new(3, 0, 0), Position::new(5, 5, 0)),
+        VictoryCondition::Collect(ItemId(1)),
+        VictoryCondition::Collect(ItemId(2)),
+        VictoryCondition::Not(Box::new(VictoryCondition::Survive(900))),
+    ]);
+    assert_eq!(
+        map.validate(),
+        vec![
+            MapProblem::RegionUnreachable {
+                room: 0,
+                from: Position::new(3, 0, 0),
+                to: Position::new(5, 5, 0),
+            },
+            MapProblem::GoalItemUnobtainable { item: ItemId(2) },
+        ]
+    );
+}
+
+#[test]
+fn test_validate_triggers() {
+    use crate::map::{Enemy, Trigger};
+
+    let mut room = Room::new(Position::new(0, 0, 0));
+    room.add_tile(Position::new(0, 0, 0), Tile::new(960, true))
+        .add_tile(Position::new(1, 0, 0), Tile::new(947, false))
+        .add_tile(Position::new(2, 0, 0), Tile::new(960, true));
+    room.triggers.push(Trigger {
+        condition: TriggerCondition::Defeated("guards".into()),
+        actions: vec![
+            TriggerAction::SetTile(Position::new(1, 0, 0), Tile::new(960, true)),
+            TriggerAction::Wake("archers".into()),
+            TriggerAction::Give(ItemId(3)),
+        ],
+    });
+    let mut map = Map {
+        victory_condition: VictoryCondition::Arrival(0, Position::new(2, 0, 0)),
+        ..Map::single_room(room)
+    };
+
+    assert_eq!(
+        map.validate(),
+        vec![
+            MapProblem::UnknownGroup {
+                room: 0,
+                group: "guards".into(),
+            },
+            MapProblem::UnknownGroup {
+                room: 0,
+                group: "archers".into(),
+            },
+            MapProblem::UnknownGivenItem {
+                room: 0,
+                item: ItemId(3),
+            },
+        ]
+    );
+
+    let mut guard = Enemy::new(74, 1, 1, BTreeSet::new());
+    guard.group = Some("guards".into());
+    map.rooms[0].triggers[0].condition =
+        TriggerCondition::Enter(Position::new(0, 0, 0), Position::new(0, 0, 0));
+    map.rooms[0].triggers[0].actions = vec![
+        TriggerAction::SpawnEnemy(Position::new(1, 0, 0), guard),
+        TriggerAction::Wake("guards".into()),
+    ];
+    assert_eq!(
+        map.validate(),
+        vec![
+            MapProblem::NotOnPassableTile {
+                placement: Placement::Enemy,
+                room: 0,
+                position: Position::new(1, 0, 0),
+            },
+            MapProblem::ArrivalUnreachable {
+                room: 0,
+                position: Position::new(2, 0, 0),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_validate_enemy_arrivals() {
+    use crate::map::DefeatCondition;
+
+    let mut room = Room::new(Position::new(0, 0, 0));
+    room.add_tile(Position::new(0, 0, 0), Tile::new(960, true))
+        .add_tile(Position::new(1, 0, 0), Tile::new(960, true))
+        .add_tile(Position::new(2, 0, 0), Tile::new(947, false));
+    let mut other = Room::new(Position::new(0, 0, 0));
+    other.add_tile(Position::new(0, 0, 0), Tile::new(960, true));
+    let mut map = Map::single_room(room);
+    map.rooms.push(other);
+    map.defeat_condition = DefeatCondition::Or(vec![
+        DefeatCondition::EnemyArrival(0, Position::new(1, 0, 0)),
+        DefeatCondition::EnemyArrival(0, Position::new(2, 0, 0)),
+        // Passable in the first room, but not in the one named
+        DefeatCondition::EnemyArrival(1, Position::new(1, 0, 0)),
+        DefeatCondition::EnemyArrival(2, Position::new(0, 0, 0)),
+    ]);
+
+    assert_eq!(
+        map.validate(),
+        vec![
+            MapProblem::NotOnPassableTile {
+                placement: Placement::EnemyArrival,
+                room: 0,
+                position: Position::new(2, 0, 0),
+            },
+            MapProblem::NotOnPassableTile {
+                placement: Placement::EnemyArrival,
+                room: 1,
+                position: Position::new(1, 0, 0),
+            },
+            MapProblem::NotOnPassableTile {
+                placement: Placement::EnemyArrival,
+                room: 2,
+                position: Position::new(0, 0, 0),
+            },
+        ]
+    );
+}